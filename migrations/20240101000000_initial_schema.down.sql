@@ -0,0 +1,6 @@
+-- The baseline adopts tables that predate migrations, so reverting it would drop
+-- every stored log. Refuse instead; drop the table by hand if that is really meant.
+DO $$
+BEGIN
+    RAISE EXCEPTION 'the baseline migration is irreversible; refusing to drop logs';
+END $$;
@@ -0,0 +1,35 @@
+-- Baseline schema. Uses IF NOT EXISTS so databases created before migrations
+-- existed adopt it without changes.
+CREATE TABLE IF NOT EXISTS logs (
+    id TEXT PRIMARY KEY NOT NULL,
+    level VARCHAR(10) NOT NULL,
+    message TEXT NOT NULL,
+    timestamp TEXT NOT NULL, -- TIMESTAMPTZ for timezone-aware timestamps
+    service VARCHAR(255) NOT NULL,
+    context JSONB,         -- Stored as JSONB for efficient querying
+    global_context JSONB NOT NULL, -- JSONB, not nullable as per your model
+    user_context JSONB,
+    user_id TEXT,
+    user_username VARCHAR(255),
+    user_email VARCHAR(255),
+    device JSONB,          -- Stored as JSONB
+    breadcrumbs JSONB,     -- Stored as JSONB
+    error_name VARCHAR(255),
+    stack TEXT,
+    reason JSONB,
+    request_method VARCHAR(10),
+    request_url TEXT,
+    status_code SMALLINT, -- Fits u16
+    status_text VARCHAR(255),
+    duration_ms BIGINT,   -- Fits u64
+    response_size BIGINT,
+    error_message TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_logs_level ON logs (level);
+CREATE INDEX IF NOT EXISTS idx_logs_timestamp ON logs (timestamp);
+CREATE INDEX IF NOT EXISTS idx_logs_service ON logs (service);
+-- Serves DISTINCT ON (service) ... ORDER BY service, timestamp DESC without a sort.
+CREATE INDEX IF NOT EXISTS idx_logs_service_timestamp ON logs (service, timestamp DESC);
+-- jsonb_path_ops is smaller than the default opclass and only needs to serve `@>`.
+CREATE INDEX IF NOT EXISTS idx_logs_context ON logs USING GIN (context jsonb_path_ops);
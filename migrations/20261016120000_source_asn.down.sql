@@ -0,0 +1,3 @@
+ALTER TABLE logs
+    DROP COLUMN IF EXISTS source_asn,
+    DROP COLUMN IF EXISTS source_org;
@@ -0,0 +1,3 @@
+ALTER TABLE logs
+    ADD COLUMN IF NOT EXISTS source_asn BIGINT,
+    ADD COLUMN IF NOT EXISTS source_org TEXT;
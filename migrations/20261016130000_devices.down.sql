@@ -0,0 +1,3 @@
+ALTER TABLE logs
+    DROP COLUMN IF EXISTS device_hash;
+DROP TABLE IF EXISTS devices;
@@ -0,0 +1,9 @@
+-- Optional normalized storage for `device` (NORMALIZE_DEVICES): each distinct device
+-- is stored once and logs reference it by hash instead of carrying their own copy.
+CREATE TABLE IF NOT EXISTS devices (
+    hash TEXT PRIMARY KEY NOT NULL, -- hex SHA-256 of the device JSON
+    info JSONB NOT NULL
+);
+
+ALTER TABLE logs
+    ADD COLUMN IF NOT EXISTS device_hash TEXT;
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_logs_deleted_at;
+ALTER TABLE logs
+    DROP COLUMN IF EXISTS deleted_at;
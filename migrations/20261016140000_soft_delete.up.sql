@@ -0,0 +1,7 @@
+-- Soft-deleted rows (RETENTION_SOFT_DELETE) keep their data until purged; every read
+-- filters on `deleted_at IS NULL`.
+ALTER TABLE logs
+    ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+
+-- Only soft-deleted rows are indexed, which is all the purge job looks at.
+CREATE INDEX IF NOT EXISTS idx_logs_deleted_at ON logs (deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_logs_user_username;
+DROP INDEX IF EXISTS idx_logs_user_email;
+DROP INDEX IF EXISTS idx_logs_user_id;
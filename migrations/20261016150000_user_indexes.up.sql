@@ -0,0 +1,4 @@
+-- Exact-match lookups for the `/logs` user filters.
+CREATE INDEX IF NOT EXISTS idx_logs_user_id ON logs (user_id);
+CREATE INDEX IF NOT EXISTS idx_logs_user_email ON logs (user_email);
+CREATE INDEX IF NOT EXISTS idx_logs_user_username ON logs (user_username);
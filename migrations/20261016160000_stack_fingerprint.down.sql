@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_logs_stack_fingerprint;
+ALTER TABLE logs DROP COLUMN IF EXISTS stack_fingerprint;
@@ -0,0 +1,3 @@
+-- Groups identical errors whose raw stacks differ only in line numbers or bundle hashes.
+ALTER TABLE logs ADD COLUMN IF NOT EXISTS stack_fingerprint TEXT;
+CREATE INDEX IF NOT EXISTS idx_logs_stack_fingerprint ON logs (stack_fingerprint) WHERE stack_fingerprint IS NOT NULL;
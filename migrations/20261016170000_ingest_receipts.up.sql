@@ -0,0 +1,8 @@
+-- Signed receipts for accepted ingest batches (INGEST_RECEIPT_KEY), kept so a client's
+-- receipt can later be checked against what was stored.
+CREATE TABLE IF NOT EXISTS ingest_receipts (
+    hash TEXT PRIMARY KEY,
+    signature TEXT NOT NULL,
+    entry_ids TEXT[] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_logs_session_sequence;
+ALTER TABLE logs DROP COLUMN IF EXISTS sequence;
+ALTER TABLE logs DROP COLUMN IF EXISTS session_id;
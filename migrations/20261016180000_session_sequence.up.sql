@@ -0,0 +1,4 @@
+-- Orders entries within a session when their client timestamps tie.
+ALTER TABLE logs ADD COLUMN IF NOT EXISTS session_id TEXT;
+ALTER TABLE logs ADD COLUMN IF NOT EXISTS sequence BIGINT;
+CREATE INDEX IF NOT EXISTS idx_logs_session_sequence ON logs (session_id, sequence) WHERE session_id IS NOT NULL;
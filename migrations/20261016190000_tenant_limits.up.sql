@@ -0,0 +1,8 @@
+-- Per-tenant rate limits (RATE_LIMIT_TENANTS), keyed by the hex SHA-256 of the tenant's
+-- X-Api-Key so the keys themselves are never stored. Picked up without a restart.
+CREATE TABLE IF NOT EXISTS tenant_limits (
+    api_key_sha256 TEXT PRIMARY KEY,
+    capacity BIGINT NOT NULL CHECK (capacity > 0),
+    fill_interval_secs BIGINT NOT NULL CHECK (fill_interval_secs > 0),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
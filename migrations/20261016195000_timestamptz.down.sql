@@ -0,0 +1,3 @@
+-- Back to the text storage form written by pkg::time::to_storage_string.
+ALTER TABLE logs ALTER COLUMN timestamp TYPE TEXT
+    USING to_char(timestamp AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS.US"Z"');
@@ -0,0 +1,18 @@
+-- Stores log timestamps as TIMESTAMPTZ so ranges and ordering compare instants rather
+-- than text. Rows kept unparsed under the old TIMESTAMP_FALLBACK=keep move their value
+-- to context.original_timestamp and take the Unix epoch, so the conversion can't fail.
+CREATE FUNCTION pg_temp.parses_as_timestamptz(value TEXT) RETURNS BOOLEAN AS $$
+BEGIN
+    PERFORM value::TIMESTAMPTZ;
+    RETURN TRUE;
+EXCEPTION WHEN others THEN
+    RETURN FALSE;
+END;
+$$ LANGUAGE plpgsql;
+
+UPDATE logs
+SET context = COALESCE(context, '{}'::JSONB) || jsonb_build_object('original_timestamp', timestamp),
+    timestamp = '1970-01-01T00:00:00Z'
+WHERE NOT pg_temp.parses_as_timestamptz(timestamp);
+
+ALTER TABLE logs ALTER COLUMN timestamp TYPE TIMESTAMPTZ USING timestamp::TIMESTAMPTZ;
@@ -0,0 +1,2 @@
+-- Full-text search over messages (`GET /logs/search`); queries must use the same expression.
+CREATE INDEX IF NOT EXISTS idx_logs_message_search ON logs USING GIN (to_tsvector('english', message));
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_logs_content_hash;
+ALTER TABLE logs DROP COLUMN IF EXISTS content_hash;
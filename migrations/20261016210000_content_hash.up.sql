@@ -0,0 +1,3 @@
+-- Deduplicates retried entries that were sent without an id.
+ALTER TABLE logs ADD COLUMN IF NOT EXISTS content_hash TEXT;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_logs_content_hash ON logs (content_hash) WHERE content_hash IS NOT NULL;
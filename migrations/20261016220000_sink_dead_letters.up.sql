@@ -0,0 +1,9 @@
+-- Batches one of several sinks (LOG_SINK) failed to write, kept so they can be replayed
+-- to that sink alone; the other sinks already have them.
+CREATE TABLE IF NOT EXISTS sink_dead_letters (
+    id BIGSERIAL PRIMARY KEY,
+    sink TEXT NOT NULL,
+    error TEXT NOT NULL,
+    entries JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
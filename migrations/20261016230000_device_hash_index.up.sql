@@ -0,0 +1,2 @@
+-- Lets the retention pass find `devices` rows no log references any more.
+CREATE INDEX IF NOT EXISTS idx_logs_device_hash ON logs (device_hash) WHERE device_hash IS NOT NULL;
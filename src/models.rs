@@ -1,9 +1,19 @@
+use actix_web::{
+    http::{header, StatusCode},
+    HttpResponse,
+};
+use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
 use std::collections::HashMap;
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+use crate::pkg::{config::TimestampConfig, ingest::receipts::Receipt, time};
+
+/// Variants are declared in increasing severity, so `Ord` compares severity.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LogLevel {
     #[serde(rename = "trace")]
     Trace,
@@ -21,7 +31,37 @@ pub enum LogLevel {
     Critical,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+impl LogLevel {
+    /// The lowercase name used on the wire and in the `logs.level` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+            LogLevel::Fatal => "fatal",
+            LogLevel::Critical => "critical",
+        }
+    }
+
+    /// Parses the lowercase level name produced by [`LogLevel::as_str`].
+    pub fn parse(value: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(value.to_string())).ok()
+    }
+}
+
+/// Client-supplied delivery urgency, independent of `level`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    Normal,
+    /// Jumps ahead of queued live batches when `PRIORITY_HINTS` is on.
+    High,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum BreadcrumbType {
     #[serde(rename = "click")]
     Click,
@@ -37,10 +77,28 @@ pub enum BreadcrumbType {
     Error,
 }
 
+impl BreadcrumbType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BreadcrumbType::Click => "click",
+            BreadcrumbType::Navigation => "navigation",
+            BreadcrumbType::Xhr => "xhr",
+            BreadcrumbType::Console => "console",
+            BreadcrumbType::Custom => "custom",
+            BreadcrumbType::Error => "error",
+        }
+    }
+
+    /// Parses the lowercase type name used on the wire.
+    pub fn parse(value: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(value.to_string())).ok()
+    }
+}
+
 // LogContext maps to a HashMap with flexible JSON values (Rust's direct equivalent of JsonObject)
 pub type LogContext = HashMap<String, serde_json::Value>;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")] // Apply camelCase deserialization
 pub struct UserInfo {
     pub id: Option<String>,
@@ -48,13 +106,13 @@ pub struct UserInfo {
     pub email: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Brand {
     pub brand: String,
     pub version: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")] // Apply camelCase deserialization
 pub struct UserAgentClientHints {
     pub brands: Vec<Brand>,
@@ -62,7 +120,7 @@ pub struct UserAgentClientHints {
     pub platform: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")] // Apply camelCase deserialization
 pub struct DeviceInfo {
     pub os_name: Option<String>,
@@ -87,7 +145,7 @@ pub struct DeviceInfo {
     pub used_js_heap_size: Option<u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Breadcrumb {
     pub timestamp: String,
     #[serde(rename = "type")] // Explicitly rename "type" to "breadcrumb_type"
@@ -99,6 +157,7 @@ pub struct Breadcrumb {
 // ElementInfo and CoordsInfo are defined here for completeness of types,
 // but they are NOT direct fields of LogEntry in the payload.
 // They are nested within the `context` field.
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ElementInfo {
@@ -108,6 +167,7 @@ pub struct ElementInfo {
     pub text_content: Option<String>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CoordsInfo {
     pub x: f64,
@@ -115,8 +175,9 @@ pub struct CoordsInfo {
 }
 
 // --- Main LogEntry Struct ---
-#[derive(Debug, Serialize, Deserialize, Validate)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 #[serde(rename_all = "camelCase")] // Apply camelCase deserialization to all fields
+#[validate(schema(function = "validate_error_details"))]
 pub struct LogEntry {
     pub id: Option<String>, // Optional string UUID
     pub level: LogLevel,
@@ -147,35 +208,678 @@ pub struct LogEntry {
     pub duration_ms: Option<u64>,
     pub response_size: Option<u64>,
     pub error_message: Option<String>,
+
+    /// Unknown values fail deserialization like any other malformed field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
+
+    /// Position within the entry's session (`context.session_id`), breaking ties between
+    /// identical timestamps. Assigned server-side when missing if `SESSION_SEQUENCE_ASSIGN` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<i64>,
+
+    /// Server-side enrichment from the client IP; never taken from the payload.
+    #[serde(skip_deserializing)]
+    pub source_asn: Option<u32>,
+    #[serde(skip_deserializing)]
+    pub source_org: Option<String>,
+    /// Hash of the normalized `stack` (see `pkg::ingest::stack`); set server-side.
+    #[serde(skip_deserializing)]
+    pub stack_fingerprint: Option<String>,
+    /// [`LogEntry::content_hash`] of an entry sent without an `id`, so retries of it are
+    /// stored once; set server-side and never read back.
+    #[serde(skip)]
+    pub content_hash: Option<String>,
     // REMOVED: `element` and `coords` as top-level fields from LogEntry struct.
     // They are correctly observed to be nested inside `context` in the actual payloads.
     // If you need to access them, you'd do so by parsing the `context` LogContext.
 }
 
+/// Entries at `error` level or above must say what went wrong: an `errorName`,
+/// `errorMessage` or `stack`.
+fn validate_error_details(entry: &LogEntry) -> Result<(), ValidationError> {
+    let described = [&entry.error_name, &entry.error_message, &entry.stack]
+        .into_iter()
+        .any(|field| field.as_deref().is_some_and(|value| !value.trim().is_empty()));
+    if entry.level >= LogLevel::Error && !described {
+        let mut error = ValidationError::new("error_details");
+        error.message = Some("Error-level logs need an errorName, errorMessage or stack".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Body accepted by `/ingest`: either a JSON array of entries or a single entry object.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum IngestPayload {
+    Batch(Vec<LogEntry>),
+    Single(Box<LogEntry>),
+}
+
+impl IngestPayload {
+    pub fn len(&self) -> usize {
+        match self {
+            IngestPayload::Batch(entries) => entries.len(),
+            IngestPayload::Single(_) => 1,
+        }
+    }
+
+    /// Only a literal `[]`.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiResponse {
     pub status: String,
     pub message: String,
 }
 
+/// Body of an accepted ingest request: how many entries were kept, and why the others
+/// were rejected.
+#[derive(Debug, Serialize)]
+pub struct IngestResponse {
+    pub status: String,
+    pub message: String,
+    pub accepted: usize,
+    pub rejected: usize,
+    /// Valid entries dropped by per-level sampling, counted in neither of the above.
+    pub sampled: usize,
+    pub rejections: Vec<EntryError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<Receipt>,
+    /// One per batch queued from an NDJSON stream, which has no single receipt.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub receipts: Vec<Receipt>,
+}
+
+/// Stable, machine-readable reason for an [`ErrorResponse`]; clients should branch on
+/// this rather than on the message text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request itself is malformed: bad JSON, parameters or headers.
+    BadRequest,
+    /// The batch was an empty array, or the NDJSON stream had no lines.
+    EmptyBatch,
+    /// No entry in the batch passed validation; `details` says why for each.
+    ValidationFailed,
+    PayloadTooLarge,
+    Unauthorized,
+    RateLimited,
+    /// The ingest queue has no room; retry after `Retry-After`.
+    QueueFull,
+    /// Low-priority ingest shed under CPU load.
+    Overloaded,
+    /// Ingest is paused by an operator.
+    Maintenance,
+    /// Too many read queries in flight; retry after `Retry-After`.
+    Busy,
+    NotFound,
+    MethodNotAllowed,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status_code(self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest | ErrorCode::EmptyBatch | ErrorCode::ValidationFailed => StatusCode::BAD_REQUEST,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::QueueFull | ErrorCode::Overloaded | ErrorCode::Maintenance | ErrorCode::Busy => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The `status` these errors carried before they had a `code`: `"failed"` when the
+    /// request itself must change, `"error"` when it may succeed as is later.
+    pub fn legacy_status(self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest
+            | ErrorCode::EmptyBatch
+            | ErrorCode::ValidationFailed
+            | ErrorCode::PayloadTooLarge
+            | ErrorCode::Unauthorized
+            | ErrorCode::NotFound => "failed",
+            ErrorCode::RateLimited
+            | ErrorCode::QueueFull
+            | ErrorCode::Overloaded
+            | ErrorCode::Maintenance
+            | ErrorCode::Busy
+            | ErrorCode::MethodNotAllowed
+            | ErrorCode::Internal => "error",
+        }
+    }
+}
+
+/// Why one entry of a batch was rejected; `index` is its position in the request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntryError {
+    pub index: usize,
+    pub errors: Vec<String>,
+}
+
+/// Body of every error response. `status` keeps the value it had before `code` was
+/// added (see [`ErrorCode::legacy_status`]), for clients that only look at it; `code`
+/// is what to branch on.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub status: String,
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<Vec<EntryError>>,
+    /// Entries of a streamed request already queued when it failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accepted: Option<usize>,
+    #[serde(skip)]
+    retry_after_secs: Option<u64>,
+}
+
+impl ErrorResponse {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ErrorResponse {
+            status: code.legacy_status().to_string(),
+            code,
+            message: message.into(),
+            details: None,
+            accepted: None,
+            retry_after_secs: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: Vec<EntryError>) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn with_accepted(mut self, accepted: usize) -> Self {
+        self.accepted = Some(accepted);
+        self
+    }
+
+    /// Tells the client to retry after `secs` seconds once this becomes a response.
+    pub fn retrying_after(mut self, secs: u64) -> Self {
+        self.retry_after_secs = Some(secs);
+        self
+    }
+
+    /// The response, telling the client to retry after `secs` seconds.
+    pub fn retry_after(self, secs: u64) -> HttpResponse {
+        self.retrying_after(secs).into()
+    }
+}
+
+impl From<ErrorResponse> for HttpResponse {
+    fn from(error: ErrorResponse) -> Self {
+        let mut response = HttpResponse::build(error.code.status_code());
+        if let Some(secs) = error.retry_after_secs {
+            response.insert_header((header::RETRY_AFTER, secs.to_string()));
+        }
+        response.json(error)
+    }
+}
+
 impl LogEntry {
-    /// Applies PII masking to sensitive fields within the log entry. [20, 18, 21]
-    /// This is a basic example; a real-world implementation would use more sophisticated
-    /// and configurable redaction rules.
-    pub fn mask_pii(&mut self) {
-        // Example: Mask email addresses in the message
-        let email_regex = Regex::new(r"[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}")
-            .expect("Invalid email regex");
-        self.message = email_regex.replace_all(&self.message, "").to_string();
-
-        // Recursively mask sensitive data in context if it's a string
-        if let Some(context) = self.context.as_mut() {
-            for (_key, value) in context.iter_mut() {
-                if let serde_json::Value::String(s) = value {
-                    *s = email_regex.replace_all(s, "").to_string();
-                    // Add more regex for other PII types (SSN, credit card numbers, etc.) [18]
-                }
+    /// SHA-256 over `service|level|message|timestamp`, as sent by the client.
+    pub fn content_hash(&self) -> String {
+        let content = format!("{}|{}|{}|{}", self.service, self.level.as_str(), self.message, self.timestamp);
+        format!("{:x}", Sha256::digest(content.as_bytes()))
+    }
+
+    /// The `context.session_id` the entry belongs to, if any, as a string.
+    pub fn session_id(&self) -> Option<String> {
+        self.context
+            .as_ref()
+            .and_then(|context| context.get("session_id"))
+            .map(|id| match id {
+                serde_json::Value::String(id) => id.clone(),
+                other => other.to_string(),
+            })
+    }
+
+    /// Replaces email addresses, SSNs and card numbers (13-16 digits passing the Luhn
+    /// check) with `[REDACTED]`, then applies `config`'s rules in order, in `message`,
+    /// `error_message`, `stack` and every string nested anywhere in `context`,
+    /// `user_context` and `global_context`.
+    pub fn mask_pii(&mut self, config: &RedactionConfig) {
+        let mask = |text: &mut String| mask_string(text, &config.rules);
+        mask(&mut self.message);
+        for text in [self.error_message.as_mut(), self.stack.as_mut()].into_iter().flatten() {
+            mask(text);
+        }
+        let contexts = [self.context.as_mut(), self.user_context.as_mut(), Some(&mut self.global_context)];
+        for context in contexts.into_iter().flatten() {
+            context.values_mut().for_each(|value| mask_value(value, &mask));
+        }
+    }
+
+    /// Replaces `user.id` and `user.email` with salted SHA-256 hashes so they can still be
+    /// counted distinctly (e.g. `COUNT(DISTINCT user_email)`) without exposing the raw values.
+    pub fn hash_user_identifiers(&mut self, salt: &str) {
+        if let Some(user) = self.user.as_mut() {
+            user.id = user.id.as_deref().map(|id| salted_hash(salt, id));
+            user.email = user.email.as_deref().map(|email| salted_hash(salt, &email.to_lowercase()));
+        }
+    }
+
+    /// Rewrites `timestamp` as UTC in the canonical stored form, trying the configured
+    /// formats in order. With `preserve_original_offset` the client's original offset is
+    /// kept in `context.original_timestamp_offset`. Returns `false` (leaving the entry
+    /// untouched) if no format matches.
+    pub fn normalize_timestamp(&mut self, config: &TimestampConfig) -> bool {
+        let Some((instant, offset)) = time::parse_flexible(&self.timestamp, &config.formats) else {
+            return false;
+        };
+        self.timestamp = time::to_storage_string(instant);
+        if config.preserve_original_offset {
+            self.context.get_or_insert_with(HashMap::new).insert(
+                "original_timestamp_offset".to_string(),
+                serde_json::Value::String(offset.to_string()),
+            );
+        }
+        true
+    }
+
+    /// Gives `reason` a consistent shape: a bare string becomes `{ "message": .. }` and an
+    /// error-like object (one with `name`, `message` or `stack`) always carries both `name`
+    /// and `message`. Any other JSON is left as sent.
+    pub fn normalize_reason(&mut self) {
+        match self.reason.as_mut() {
+            Some(serde_json::Value::String(message)) => {
+                let message = std::mem::take(message);
+                self.reason = Some(serde_json::json!({ "message": message }));
             }
+            Some(serde_json::Value::Object(fields))
+                if ["name", "message", "stack"].iter().any(|key| fields.contains_key(*key)) =>
+            {
+                fields.entry("name").or_insert_with(|| "Error".into());
+                fields.entry("message").or_insert_with(|| "".into());
+            }
+            _ => {}
+        }
+    }
+}
+
+const PII_REDACTED: &str = "[REDACTED]";
+
+static EMAIL_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}").expect("valid email regex"));
+static SSN_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b\d{3}-\d{2}-\d{4}\b").expect("valid SSN regex"));
+/// 13-16 digits, optionally grouped by single spaces or dashes; confirmed with [`passes_luhn`].
+static CARD_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b\d(?:[ -]?\d){12,15}\b").expect("valid card regex"));
+
+/// A named pattern whose matches `mask_pii` replaces. `replacement` may refer to capture
+/// groups as `$1` or `${name}`.
+#[derive(Debug, Clone)]
+pub struct RedactionRule {
+    pub name: String,
+    pub pattern: Regex,
+    pub replacement: String,
+}
+
+/// Redaction rules applied after the built-in ones, from the file named by `REDACTION_RULES`.
+#[derive(Debug, Clone, Default)]
+pub struct RedactionConfig {
+    pub rules: Vec<RedactionRule>,
+}
+
+#[derive(Deserialize)]
+struct RedactionFile {
+    rules: Vec<RawRedactionRule>,
+}
+
+#[derive(Deserialize)]
+struct RawRedactionRule {
+    name: String,
+    pattern: String,
+    #[serde(default = "default_replacement")]
+    replacement: String,
+}
+
+fn default_replacement() -> String {
+    PII_REDACTED.to_string()
+}
+
+impl RedactionConfig {
+    /// Reads rules from a TOML file (by its `.toml` extension) or otherwise JSON, shaped
+    /// as `{"rules": [{"name": ..., "pattern": ..., "replacement": ...}]}`; the
+    /// replacement defaults to `[REDACTED]`.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let raw = std::fs::read_to_string(path).map_err(|e| format!("cannot read redaction rules from {}: {}", path, e))?;
+        let file: RedactionFile = if path.ends_with(".toml") {
+            toml::from_str(&raw).map_err(|e| format!("invalid redaction rules in {}: {}", path, e))?
+        } else {
+            serde_json::from_str(&raw).map_err(|e| format!("invalid redaction rules in {}: {}", path, e))?
+        };
+        let rules = file
+            .rules
+            .into_iter()
+            .map(|rule| {
+                let pattern = Regex::new(&rule.pattern)
+                    .map_err(|e| format!("redaction rule '{}' has an invalid pattern: {}", rule.name, e))?;
+                Ok(RedactionRule {
+                    name: rule.name,
+                    pattern,
+                    replacement: rule.replacement,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { rules })
+    }
+}
+
+fn mask_string(text: &mut String, rules: &[RedactionRule]) {
+    for pattern in [&*EMAIL_PATTERN, &*SSN_PATTERN] {
+        if let Cow::Owned(masked) = pattern.replace_all(text, PII_REDACTED) {
+            *text = masked;
+        }
+    }
+    let cards = CARD_PATTERN.replace_all(text, |caps: &regex::Captures| {
+        if passes_luhn(&caps[0]) {
+            PII_REDACTED.to_string()
+        } else {
+            caps[0].to_string()
+        }
+    });
+    if let Cow::Owned(masked) = cards {
+        *text = masked;
+    }
+    for rule in rules {
+        if let Cow::Owned(masked) = rule.pattern.replace_all(text, rule.replacement.as_str()) {
+            *text = masked;
+        }
+    }
+}
+
+fn mask_value(value: &mut serde_json::Value, mask: &impl Fn(&mut String)) {
+    match value {
+        serde_json::Value::String(text) => mask(text),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(|item| mask_value(item, mask)),
+        serde_json::Value::Object(fields) => fields.values_mut().for_each(|field| mask_value(field, mask)),
+        _ => {}
+    }
+}
+
+/// The Luhn checksum used by payment card numbers, over the digits of `candidate`.
+fn passes_luhn(candidate: &str) -> bool {
+    let sum: u32 = candidate
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, digit)| match (i % 2 == 1, digit * 2) {
+            (true, doubled) if doubled > 9 => doubled - 9,
+            (true, doubled) => doubled,
+            (false, _) => digit,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Hex-encoded SHA-256 of `salt` followed by `value`.
+pub fn salted_hash(salt: &str, value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(value.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry_with_user(email: &str) -> LogEntry {
+        serde_json::from_value(json!({
+            "level": "info",
+            "message": format!("checkout completed for {}", email),
+            "timestamp": "2024-01-01T00:00:00Z",
+            "service": "web",
+            "context": { "contact": email },
+            "user": { "id": "user-1", "email": email }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_hash_user_identifiers_is_stable() {
+        let mut a = entry_with_user("Jane@Example.com");
+        let mut b = entry_with_user("jane@example.com");
+        a.hash_user_identifiers("pepper");
+        b.hash_user_identifiers("pepper");
+
+        let hashed = a.user.as_ref().unwrap().email.clone().unwrap();
+        assert_eq!(hashed.len(), 64);
+        assert_eq!(Some(hashed.clone()), b.user.as_ref().unwrap().email);
+        assert_eq!(a.user.as_ref().unwrap().id, b.user.as_ref().unwrap().id);
+
+        let mut c = entry_with_user("jane@example.com");
+        c.hash_user_identifiers("other-salt");
+        assert_ne!(Some(hashed), c.user.unwrap().email);
+    }
+
+    #[test]
+    fn test_raw_email_not_stored_after_masking_and_hashing() {
+        let mut entry = entry_with_user("jane@example.com");
+        entry.mask_pii(&RedactionConfig::default());
+        entry.hash_user_identifiers("pepper");
+
+        let stored = serde_json::to_string(&entry).unwrap();
+        assert!(!stored.contains("jane@example.com"));
+    }
+
+    #[test]
+    fn test_mask_pii_redacts_ssns_and_valid_card_numbers() {
+        let mut entry = entry_with_user("jane@example.com");
+        entry.message = "charged 4111 1111 1111 1111 for 123-45-6789, card 4111111111111112 declined".to_string();
+        entry.error_message = Some("card 5500-0000-0000-0004 expired".to_string());
+        entry.stack = Some("at charge (pay.js:1:1) ssn=078-05-1120".to_string());
+        entry.mask_pii(&RedactionConfig::default());
+
+        assert_eq!(
+            entry.message,
+            "charged [REDACTED] for [REDACTED], card 4111111111111112 declined",
+            "a number failing the Luhn check is not a card"
+        );
+        assert_eq!(entry.error_message.as_deref(), Some("card [REDACTED] expired"));
+        assert_eq!(entry.stack.as_deref(), Some("at charge (pay.js:1:1) ssn=[REDACTED]"));
+        assert_eq!(entry.context.unwrap()["contact"], json!("[REDACTED]"));
+    }
+
+    #[test]
+    fn test_mask_pii_reaches_nested_context_values() {
+        let mut entry = entry_with_user("jane@example.com");
+        entry.user_context = Some(serde_json::from_value(json!({
+            "billing": { "payment": { "card": { "number": "4242424242424242", "last4": "4242" } } }
+        })).unwrap());
+        entry.global_context = serde_json::from_value(json!({
+            "applicants": [{ "profile": { "ssn": "123-45-6789", "order": 1234567890123u64 } }]
+        })).unwrap();
+        entry.mask_pii(&RedactionConfig::default());
+
+        assert_eq!(
+            entry.user_context.unwrap()["billing"],
+            json!({ "payment": { "card": { "number": "[REDACTED]", "last4": "4242" } } })
+        );
+        assert_eq!(
+            entry.global_context["applicants"],
+            json!([{ "profile": { "ssn": "[REDACTED]", "order": 1234567890123u64 } }])
+        );
+    }
+
+    #[test]
+    fn test_mask_pii_visits_every_leaf_of_nested_arrays() {
+        let nested = json!({
+            "breadcrumbs": [
+                { "data": { "email": "a@example.com", "cc": ["b@example.com", ["c@example.com"]] } },
+                { "data": { "steps": [{ "form": { "fields": [{ "value": "d@example.com" }] } }] } }
+            ]
+        });
+        let mut entry = entry_with_user("jane@example.com");
+        entry.context = Some(serde_json::from_value(nested.clone()).unwrap());
+        entry.user_context = Some(serde_json::from_value(nested.clone()).unwrap());
+        entry.global_context = serde_json::from_value(nested).unwrap();
+        entry.mask_pii(&RedactionConfig::default());
+
+        for context in [entry.context.unwrap(), entry.user_context.unwrap(), entry.global_context] {
+            let text = serde_json::to_string(&context).unwrap();
+            assert!(!text.contains("@example.com"), "{}", text);
+            assert_eq!(context["breadcrumbs"][0]["data"]["cc"][1][0], "[REDACTED]");
+            assert_eq!(context["breadcrumbs"][1]["data"]["steps"][0]["form"]["fields"][0]["value"], "[REDACTED]");
+        }
+    }
+
+    fn write_rules(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}", uuid::Uuid::new_v4(), name));
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_configured_redaction_rules_are_applied_in_order() {
+        let json = write_rules(
+            "rules.json",
+            r#"{ "rules": [
+                { "name": "order-id", "pattern": "ORD-\\d+", "replacement": "ORD-***" },
+                { "name": "ipv4", "pattern": "\\b\\d{1,3}(?:\\.\\d{1,3}){3}\\b" }
+            ] }"#,
+        );
+        let toml = write_rules(
+            "rules.toml",
+            r#"
+                [[rules]]
+                name = "order-id"
+                pattern = 'ORD-\d+'
+                replacement = "ORD-***"
+
+                [[rules]]
+                name = "ipv4"
+                pattern = '\b\d{1,3}(?:\.\d{1,3}){3}\b'
+            "#,
+        );
+        for path in [json, toml] {
+            let config = RedactionConfig::load(&path).unwrap();
+            assert_eq!(config.rules.iter().map(|rule| rule.name.as_str()).collect::<Vec<_>>(), ["order-id", "ipv4"]);
+
+            let mut entry = entry_with_user("jane@example.com");
+            entry.context = Some(serde_json::from_value(json!({ "client": { "addr": "10.1.2.3" } })).unwrap());
+            entry.message = "ORD-991 paid from 192.168.0.7 by jane@example.com".to_string();
+            entry.mask_pii(&config);
+            assert_eq!(entry.message, "ORD-*** paid from [REDACTED] by [REDACTED]");
+            assert_eq!(entry.context.unwrap()["client"]["addr"], json!("[REDACTED]"));
+        }
+    }
+
+    #[test]
+    fn test_invalid_redaction_pattern_fails_to_load() {
+        let path = write_rules("bad.json", r#"{ "rules": [{ "name": "broken", "pattern": "(unclosed" }] }"#);
+        let err = RedactionConfig::load(&path).unwrap_err();
+        assert!(err.contains("redaction rule 'broken' has an invalid pattern"), "{}", err);
+    }
+
+    #[test]
+    fn test_normalize_timestamp_preserves_offset_in_context() {
+        let mut entry = entry_with_user("jane@example.com");
+        entry.timestamp = "2024-03-01T10:00:00+02:00".to_string();
+
+        let config = TimestampConfig {
+            preserve_original_offset: true,
+            ..TimestampConfig::default()
+        };
+        assert!(entry.normalize_timestamp(&config));
+        assert_eq!(entry.timestamp, "2024-03-01T08:00:00.000000Z");
+        assert_eq!(
+            entry.context.unwrap()["original_timestamp_offset"],
+            json!("+02:00")
+        );
+    }
+
+    #[test]
+    fn test_normalize_reason_shapes() {
+        let normalized = |reason: serde_json::Value| {
+            let mut entry = entry_with_user("jane@example.com");
+            entry.reason = Some(reason);
+            entry.normalize_reason();
+            entry.reason.unwrap()
+        };
+
+        assert_eq!(normalized(json!("timeout")), json!({ "message": "timeout" }));
+        assert_eq!(
+            normalized(json!({ "message": "boom", "code": 7 })),
+            json!({ "name": "Error", "message": "boom", "code": 7 })
+        );
+        assert_eq!(
+            normalized(json!({ "name": "TypeError", "stack": "at x" })),
+            json!({ "name": "TypeError", "message": "", "stack": "at x" })
+        );
+        assert_eq!(normalized(json!({ "retry": true })), json!({ "retry": true }));
+        assert_eq!(normalized(json!([1, 2])), json!([1, 2]));
+    }
+
+    #[test]
+    fn test_error_levels_require_error_details() {
+        for level in [LogLevel::Error, LogLevel::Fatal, LogLevel::Critical] {
+            let mut entry = entry_with_user("jane@example.com");
+            entry.level = level;
+            let errors = entry.validate().unwrap_err();
+            assert!(errors.errors().contains_key("__all__"), "{:?}", errors);
+
+            entry.error_message = Some("  ".to_string());
+            assert!(entry.validate().is_err(), "blank details don't count");
+            entry.stack = Some("at checkout (cart.js:10:3)".to_string());
+            assert!(entry.validate().is_ok());
+        }
+
+        let mut warning = entry_with_user("jane@example.com");
+        warning.level = LogLevel::Warn;
+        assert!(warning.validate().is_ok());
+    }
+
+    #[test]
+    fn test_content_hash_covers_only_the_identifying_fields() {
+        let a = entry_with_user("jane@example.com");
+        let mut b = entry_with_user("jane@example.com");
+        b.user = None;
+        b.context = None;
+        assert_eq!(a.content_hash(), b.content_hash());
+        assert_eq!(a.content_hash().len(), 64);
+
+        b.timestamp = "2024-01-01T00:00:01Z".to_string();
+        assert_ne!(a.content_hash(), b.content_hash());
+    }
+
+    #[test]
+    fn test_error_codes_map_to_statuses() {
+        for (code, status, name) in [
+            (ErrorCode::BadRequest, StatusCode::BAD_REQUEST, "bad_request"),
+            (ErrorCode::EmptyBatch, StatusCode::BAD_REQUEST, "empty_batch"),
+            (ErrorCode::ValidationFailed, StatusCode::BAD_REQUEST, "validation_failed"),
+            (ErrorCode::PayloadTooLarge, StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large"),
+            (ErrorCode::Unauthorized, StatusCode::UNAUTHORIZED, "unauthorized"),
+            (ErrorCode::RateLimited, StatusCode::TOO_MANY_REQUESTS, "rate_limited"),
+            (ErrorCode::QueueFull, StatusCode::SERVICE_UNAVAILABLE, "queue_full"),
+            (ErrorCode::Overloaded, StatusCode::SERVICE_UNAVAILABLE, "overloaded"),
+            (ErrorCode::Maintenance, StatusCode::SERVICE_UNAVAILABLE, "maintenance"),
+            (ErrorCode::Busy, StatusCode::SERVICE_UNAVAILABLE, "busy"),
+            (ErrorCode::NotFound, StatusCode::NOT_FOUND, "not_found"),
+            (ErrorCode::MethodNotAllowed, StatusCode::METHOD_NOT_ALLOWED, "method_not_allowed"),
+            (ErrorCode::Internal, StatusCode::INTERNAL_SERVER_ERROR, "internal"),
+        ] {
+            let response = HttpResponse::from(ErrorResponse::new(code, "nope"));
+            assert_eq!(response.status(), status, "{:?}", code);
+            assert_eq!(serde_json::to_value(code).unwrap(), json!(name));
         }
+        let body = serde_json::to_value(ErrorResponse::new(ErrorCode::QueueFull, "full")).unwrap();
+        assert_eq!(body, json!({ "status": "error", "code": "queue_full", "message": "full" }));
+        let body = serde_json::to_value(ErrorResponse::new(ErrorCode::ValidationFailed, "bad")).unwrap();
+        assert_eq!(body["status"], "failed");
     }
 }
@@ -0,0 +1,32 @@
+use actix_web::HttpRequest;
+use std::net::{IpAddr, SocketAddr};
+
+/// The client's address: the peer, or what `Forwarded`/`X-Forwarded-For` say when the
+/// peer is one of `trusted_proxies`. Anyone else could put any address there.
+pub fn client_ip(req: &HttpRequest, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+    let addr = req.connection_info().realip_remote_addr()?.to_string();
+    addr.parse::<IpAddr>()
+        .ok()
+        .or_else(|| addr.parse::<SocketAddr>().ok().map(|socket| socket.ip()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test;
+
+    #[actix_web::test]
+    async fn test_forwarded_for_is_only_believed_from_trusted_proxies() {
+        let req = test::TestRequest::get()
+            .peer_addr("10.0.0.2:40000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+            .to_http_request();
+        assert_eq!(client_ip(&req, &[]), Some("10.0.0.2".parse().unwrap()));
+        assert_eq!(client_ip(&req, &["10.0.0.1".parse().unwrap()]), Some("10.0.0.2".parse().unwrap()));
+        assert_eq!(client_ip(&req, &["10.0.0.2".parse().unwrap()]), Some("203.0.113.7".parse().unwrap()));
+    }
+}
@@ -0,0 +1,144 @@
+use parking_lot::Mutex;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tracing::{info, warn};
+
+/// Columns a `/logs` filter can narrow on, in the order a composite index lists them:
+/// equality tests first, the `timestamp` range last. Anything else is not counted, so
+/// the counts stay bounded by the combinations of these.
+const FILTER_COLUMNS: &[&str] = &[
+    "service",
+    "level",
+    "session_id",
+    "user_id",
+    "user_email",
+    "user_username",
+    "timestamp",
+];
+
+/// Btree indexes in `migrations/`, by column. `context` is left out of both lists: its
+/// `@>` test is served by the GIN index and can't be part of a btree one.
+const INDEXES: &[&[&str]] = &[
+    &["level"],
+    &["timestamp"],
+    &["service"],
+    &["service", "timestamp"],
+    &["user_id"],
+    &["user_email"],
+    &["user_username"],
+    &["session_id", "sequence"],
+];
+
+/// Counts slow `/logs` queries per combination of filtered columns and suggests a
+/// composite index for the combinations that keep showing up without one to serve them.
+/// Advisory only: nothing is ever created automatically.
+pub struct IndexAdvisor {
+    slow_threshold: Duration,
+    min_slow_queries: u64,
+    /// Keyed by the filtered columns, in [`FILTER_COLUMNS`] order.
+    slow_counts: Mutex<HashMap<Vec<&'static str>, u64>>,
+}
+
+impl IndexAdvisor {
+    pub fn new(slow_threshold: Duration, min_slow_queries: u64) -> Self {
+        Self {
+            slow_threshold,
+            min_slow_queries,
+            slow_counts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records one query that filtered on `columns` and took `elapsed`.
+    pub fn record(&self, columns: &[&str], elapsed: Duration) {
+        if elapsed < self.slow_threshold {
+            return;
+        }
+        warn!("Slow log query ({:?}) filtering on {:?}", elapsed, columns);
+        let key: Vec<&'static str> = FILTER_COLUMNS.iter().copied().filter(|column| columns.contains(column)).collect();
+        if key.len() < 2 {
+            // Every column a filter can narrow on has an index of its own.
+            return;
+        }
+        *self.slow_counts.lock().entry(key).or_insert(0) += 1;
+    }
+
+    /// `CREATE INDEX` statements for column combinations seen in at least
+    /// `min_slow_queries` slow queries that no index leads with, most frequent first.
+    pub fn suggestions(&self) -> Vec<String> {
+        let slow_counts = self.slow_counts.lock();
+        let mut candidates: Vec<(&Vec<&str>, &u64)> = slow_counts
+            .iter()
+            .filter(|(columns, count)| **count >= self.min_slow_queries && !indexed(columns))
+            .collect();
+        candidates.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        candidates
+            .into_iter()
+            .map(|(columns, count)| {
+                format!(
+                    "CREATE INDEX CONCURRENTLY ON logs ({}); -- {} slow queries",
+                    columns.join(", "),
+                    count
+                )
+            })
+            .collect()
+    }
+
+    /// Logs the current suggestions every `interval`.
+    pub async fn report(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            for suggestion in self.suggestions() {
+                info!("Index advisor suggests: {}", suggestion);
+            }
+        }
+    }
+}
+
+/// Whether some index's leading columns are exactly `columns`.
+fn indexed(columns: &[&str]) -> bool {
+    INDEXES.iter().any(|index| {
+        index.len() >= columns.len() && columns.iter().all(|column| index[..columns.len()].contains(column))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeated_slow_filter_yields_suggestion() {
+        let advisor = IndexAdvisor::new(Duration::from_millis(100), 3);
+        let user_and_level = ["level", "user_id"];
+        let slow = Duration::from_millis(250);
+
+        for _ in 0..2 {
+            advisor.record(&user_and_level, slow);
+        }
+        advisor.record(&user_and_level, Duration::from_millis(5));
+        assert!(advisor.suggestions().is_empty(), "below the slow-query minimum");
+
+        advisor.record(&user_and_level, slow);
+        for _ in 0..5 {
+            // Served by the (service, timestamp) index.
+            advisor.record(&["timestamp", "service"], slow);
+        }
+        assert_eq!(
+            advisor.suggestions(),
+            vec!["CREATE INDEX CONCURRENTLY ON logs (level, user_id); -- 3 slow queries"]
+        );
+    }
+
+    #[test]
+    fn test_context_and_single_columns_are_never_suggested() {
+        let advisor = IndexAdvisor::new(Duration::from_millis(100), 1);
+        let slow = Duration::from_millis(250);
+
+        advisor.record(&["context"], slow);
+        advisor.record(&["context", "level"], slow);
+        advisor.record(&["session_id"], slow);
+        advisor.record(&["context->>'order_id'", "level"], slow);
+        assert!(advisor.suggestions().is_empty());
+        assert!(advisor.slow_counts.lock().is_empty());
+    }
+}
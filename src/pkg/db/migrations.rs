@@ -0,0 +1,130 @@
+use sqlx::migrate::{Migrate, MigrateError, Migrator};
+use sqlx::PgConnection;
+use tracing::{error, info, warn};
+
+/// Migrations under `migrations/`, embedded at build time. Each is reversible
+/// (`.up.sql` / `.down.sql`) except the baseline, whose down script refuses to run
+/// rather than drop `logs`; down scripts should use `IF EXISTS` so they also work
+/// on a migration that only got part-way.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Applies pending migrations. If one fails and `auto_rollback` is set, its down script
+/// is run so a partially applied migration (one marked `-- no-transaction`) doesn't
+/// leave the schema half-changed. The original error is returned either way.
+pub async fn run(migrator: &Migrator, conn: &mut PgConnection, auto_rollback: bool) -> Result<(), MigrateError> {
+    let result = migrator.run(&mut *conn).await;
+    if let Err(MigrateError::ExecuteMigration(_, version)) = &result {
+        // The migrator bails out without releasing its advisory lock.
+        if let Err(e) = sqlx::query("SELECT pg_advisory_unlock_all()").execute(&mut *conn).await {
+            warn!("Failed to release migration lock: {}", e);
+        }
+        if auto_rollback {
+            if let Err(e) = roll_back_failed(migrator, conn, *version).await {
+                error!("Rolling back failed migration {} also failed: {}", version, e);
+            }
+        }
+    }
+    result
+}
+
+async fn roll_back_failed(migrator: &Migrator, conn: &mut PgConnection, version: i64) -> Result<(), sqlx::Error> {
+    let Some(down) = migrator
+        .iter()
+        .find(|m| m.version == version && m.migration_type.is_down_migration())
+    else {
+        warn!("Migration {} failed and has no down script to roll back with", version);
+        return Ok(());
+    };
+    sqlx::raw_sql(&down.sql).execute(&mut *conn).await?;
+    warn!("Rolled back failed migration {} ({})", version, down.description);
+    Ok(())
+}
+
+/// Reverts the most recently applied migration (`migrate down`), returning its version,
+/// or `None` if nothing has been applied.
+pub async fn revert_latest(migrator: &Migrator, conn: &mut PgConnection) -> Result<Option<i64>, MigrateError> {
+    conn.ensure_migrations_table().await?;
+    let mut applied: Vec<i64> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| m.version)
+        .collect();
+    applied.sort_unstable();
+    let Some(&latest) = applied.last() else {
+        return Ok(None);
+    };
+    let target = applied.len().checked_sub(2).map_or(0, |i| applied[i]);
+    migrator.undo(&mut *conn, target).await?;
+    info!("Reverted migration {}", latest);
+    Ok(Some(latest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::Connection;
+    use std::path::Path;
+
+    async fn column_exists(conn: &mut PgConnection, schema: &str, table: &str, column: &str) -> bool {
+        sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM information_schema.columns
+             WHERE table_schema = $1 AND table_name = $2 AND column_name = $3)",
+        )
+        .bind(schema)
+        .bind(table)
+        .bind(column)
+        .fetch_one(conn)
+        .await
+        .unwrap()
+    }
+
+    fn write(dir: &Path, name: &str, sql: &str) {
+        std::fs::write(dir.join(name), sql).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_migration_is_rolled_back() {
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+            return;
+        };
+        let mut conn = PgConnection::connect(&url).await.unwrap();
+        // A private schema (and so a private _sqlx_migrations) keeps this away from the app's migrations.
+        let schema = format!("migrate_test_{}", uuid::Uuid::new_v4().simple());
+        sqlx::raw_sql(&format!("CREATE SCHEMA {0}; SET search_path TO {0};", schema))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let dir = std::env::temp_dir().join(&schema);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        write(&dir, "1_widgets.up.sql", "CREATE TABLE widgets (id INT);");
+        write(&dir, "1_widgets.down.sql", "DROP TABLE IF EXISTS widgets;");
+        let migrator = Migrator::new(dir.as_path()).await.unwrap();
+        run(&migrator, &mut conn, true).await.unwrap();
+
+        // Outside a transaction the ALTER sticks even though the next statement fails.
+        write(
+            &dir,
+            "2_widget_color.up.sql",
+            "-- no-transaction\nALTER TABLE widgets ADD COLUMN color TEXT;\nSELECT 1 / 0;",
+        );
+        write(&dir, "2_widget_color.down.sql", "ALTER TABLE widgets DROP COLUMN IF EXISTS color;");
+        let migrator = Migrator::new(dir.as_path()).await.unwrap();
+        let result = run(&migrator, &mut conn, true).await;
+
+        assert!(matches!(result, Err(MigrateError::ExecuteMigration(_, 2))));
+        assert!(column_exists(&mut conn, &schema, "widgets", "id").await);
+        assert!(!column_exists(&mut conn, &schema, "widgets", "color").await);
+
+        assert_eq!(revert_latest(&migrator, &mut conn).await.unwrap(), Some(1));
+        assert!(!column_exists(&mut conn, &schema, "widgets", "id").await);
+        assert_eq!(revert_latest(&migrator, &mut conn).await.unwrap(), None);
+
+        sqlx::raw_sql(&format!("DROP SCHEMA {} CASCADE", schema))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1 +1,6 @@
-pub mod postgres;
\ No newline at end of file
+pub mod index_advisor;
+pub mod migrations;
+pub mod postgres;
+pub mod query_cache;
+pub mod read_limit;
+pub mod retention;
\ No newline at end of file
@@ -1,153 +1,1308 @@
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use sqlx::{migrate::MigrateError, postgres::PgPoolOptions, types::Json, FromRow, Pool, Postgres, QueryBuilder};
+use sha2::{Digest, Sha256};
 use tracing::info;
-use std::time::Duration;
+use std::{collections::HashMap, time::Duration};
+use futures::TryStreamExt;
+use tokio::sync::mpsc;
+use chrono::{DateTime, Utc};
 use crate::models;
-use serde_json::Value as JsonValue;
+use crate::pkg::time;
+use crate::pkg::ingest::receipts::Receipt;
+use crate::pkg::tail::Cursor;
+use super::migrations;
+
+/// Size of the pool `get_db_pool` opens.
+pub const MAX_CONNECTIONS: u32 = 50;
 
 /// Establishes a connection pool to the PostgreSQL database.
 pub async fn get_db_pool(database_url: &str) -> Result<Pool<Postgres>, sqlx::Error> {
     info!("Attempting to connect to PostgreSQL at: {}", database_url);
     PgPoolOptions::new()
-        .max_connections(50)
+        .max_connections(MAX_CONNECTIONS)
         .min_connections(5)
         .acquire_timeout(Duration::from_secs(5))
         .connect(database_url)
         .await
 }
 
-/// Initializes the database schema.
-pub async fn initialize_db_schema(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
-    info!("Initializing PostgreSQL database schema...");
+/// Runs a trivial query, to check the database is reachable.
+pub async fn ping(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1").execute(pool).await.map(|_| ())
+}
 
-    // Creates the 'logs' table if it doesn't exist.
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS logs (
-            id TEXT PRIMARY KEY NOT NULL,
-            level VARCHAR(10) NOT NULL,
-            message TEXT NOT NULL,
-            timestamp TEXT NOT NULL, -- TIMESTAMPTZ for timezone-aware timestamps
-            service VARCHAR(255) NOT NULL,
-            context JSONB,         -- Stored as JSONB for efficient querying
-            global_context JSONB NOT NULL, -- JSONB, not nullable as per your model
-            user_context JSONB,
-            user_id TEXT,
-            user_username VARCHAR(255),
-            user_email VARCHAR(255),
-            device JSONB,          -- Stored as JSONB
-            breadcrumbs JSONB,     -- Stored as JSONB
-            error_name VARCHAR(255),
-            stack TEXT,
-            reason JSONB,
-            request_method VARCHAR(10),
-            request_url TEXT,
-            status_code SMALLINT, -- Fits u16
-            status_text VARCHAR(255),
-            duration_ms BIGINT,   -- Fits u64
-            response_size BIGINT,
-            error_message TEXT
-        );
-        "#
-    )
-    .execute(pool)
-    .await?;
+/// Brings the schema up to date by applying pending migrations from `migrations/`.
+pub async fn initialize_db_schema(pool: &Pool<Postgres>, auto_rollback: bool) -> Result<(), MigrateError> {
+    info!("Applying PostgreSQL schema migrations...");
+    let mut conn = pool.acquire().await?;
+    migrations::run(&migrations::MIGRATOR, &mut conn, auto_rollback).await?;
+    info!("PostgreSQL database schema initialized successfully.");
+    Ok(())
+}
 
-    info!("'logs' table ensured.");
+/// Columns `insert_log_entries` writes per row.
+const INSERT_COLUMNS: usize = 30;
 
-    // 2. Create indexes separately
-    sqlx::query(
-        r#"CREATE INDEX IF NOT EXISTS idx_logs_level ON logs (level);"#
-    )
-    .execute(pool)
-    .await?;
-    info!("Index 'idx_logs_level' ensured.");
+/// Postgres caps a statement at 65535 bind parameters, which bounds the rows one
+/// multi-row insert can carry.
+const MAX_BIND_PARAMS: usize = u16::MAX as usize;
 
-    sqlx::query(
-        r#"CREATE INDEX IF NOT EXISTS idx_logs_timestamp ON logs (timestamp);"#
+/// Rows per `INSERT` statement of [`insert_log_entries`].
+const INSERT_CHUNK_ROWS: usize = MAX_BIND_PARAMS / INSERT_COLUMNS;
+
+/// One entry's values, converted for the `logs` columns.
+struct NewLogRow {
+    id: Option<String>,
+    level: &'static str,
+    message: String,
+    timestamp: DateTime<Utc>,
+    service: String,
+    context: Option<serde_json::Value>,
+    global_context: serde_json::Value,
+    user_context: Option<serde_json::Value>,
+    user: Option<models::UserInfo>,
+    device: Option<serde_json::Value>,
+    breadcrumbs: Option<serde_json::Value>,
+    error_name: Option<String>,
+    stack: Option<String>,
+    reason: Option<serde_json::Value>,
+    request_method: Option<String>,
+    request_url: Option<String>,
+    status_code: Option<i16>,
+    status_text: Option<String>,
+    duration_ms: Option<i64>,
+    response_size: Option<i64>,
+    error_message: Option<String>,
+    source_asn: Option<i64>,
+    source_org: Option<String>,
+    device_hash: Option<String>,
+    stack_fingerprint: Option<String>,
+    session_id: Option<String>,
+    sequence: Option<i64>,
+    content_hash: Option<String>,
+}
+
+impl NewLogRow {
+    fn new(log: models::LogEntry) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            timestamp: storage_instant(&log.timestamp)?,
+            session_id: log.session_id(),
+            id: log.id,
+            level: log.level.as_str(),
+            message: log.message,
+            service: log.service,
+            context: log.context.map(|c| serde_json::to_value(c).unwrap_or_default()),
+            global_context: serde_json::to_value(log.global_context).unwrap_or_default(),
+            user_context: log.user_context.map(|uc| serde_json::to_value(uc).unwrap_or_default()),
+            user: log.user,
+            device: log.device.map(|d| serde_json::to_value(d).unwrap_or_default()),
+            breadcrumbs: log.breadcrumbs.map(|b| serde_json::to_value(b).unwrap_or_default()),
+            error_name: log.error_name,
+            stack: log.stack,
+            reason: log.reason,
+            request_method: log.request_method,
+            request_url: log.request_url,
+            status_code: log.status_code.map(|s| s as i16), // Use i16 for SMALLINT
+            status_text: log.status_text,
+            duration_ms: log.duration_ms.map(|d| d as i64), // Use i64 for BIGINT
+            response_size: log.response_size.map(|s| s as i64),
+            error_message: log.error_message,
+            source_asn: log.source_asn.map(i64::from),
+            source_org: log.source_org,
+            device_hash: None,
+            stack_fingerprint: log.stack_fingerprint,
+            sequence: log.sequence,
+            content_hash: log.content_hash,
+        })
+    }
+}
+
+/// Inserts a batch of log entries into the 'logs' table, as one multi-row `INSERT` per
+/// [`INSERT_CHUNK_ROWS`] entries inside a single transaction. With `normalize_devices`,
+/// the stable part of each entry's `device` goes into the `devices` table (once per
+/// distinct device) and the log row keeps its `device_hash` plus the per-request
+/// fields (see [`split_device`]). Returns the number of rows inserted, which leaves out
+/// entries already stored by an earlier attempt.
+pub async fn insert_log_entries(
+    pool: &Pool<Postgres>,
+    log_entries: Vec<models::LogEntry>,
+    normalize_devices: bool,
+) -> Result<u64, sqlx::Error> {
+    info!("Attempting to insert batch of {} log entries into PostgreSQL.", log_entries.len());
+    let mut rows = log_entries.into_iter().map(NewLogRow::new).collect::<Result<Vec<_>, _>>()?;
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let mut tx = pool.begin().await?;
+    if normalize_devices {
+        // Keeps `prune_orphaned_devices` from deleting a device between this insert
+        // finding it already stored and the logs that reference it landing.
+        sqlx::query("SELECT pg_advisory_xact_lock_shared($1)")
+            .bind(DEVICES_LOCK)
+            .execute(&mut *tx)
+            .await?;
+        let mut devices = HashMap::new();
+        for row in &mut rows {
+            if let Some(info) = row.device.take() {
+                let (stable, volatile) = split_device(info);
+                let hash = device_fingerprint(&stable);
+                row.device_hash = Some(hash.clone());
+                row.device = volatile;
+                devices.entry(hash).or_insert(stable);
+            }
+        }
+        let devices: Vec<_> = devices.into_iter().collect();
+        for chunk in devices.chunks(MAX_BIND_PARAMS / 2) {
+            QueryBuilder::new("INSERT INTO devices (hash, info) ")
+                .push_values(chunk, |mut values, (hash, info)| {
+                    values.push_bind(hash).push_bind(info);
+                })
+                .push(" ON CONFLICT (hash) DO NOTHING")
+                .build()
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    let mut rows = rows.into_iter().peekable();
+    let mut inserted = 0;
+    while rows.peek().is_some() {
+        let result = build_insert(rows.by_ref().take(INSERT_CHUNK_ROWS)).build().execute(&mut *tx).await?;
+        inserted += result.rows_affected();
+    }
+    tx.commit().await?; // Commit the transaction
+    info!("Successfully inserted batch of log entries into PostgreSQL.");
+    Ok(inserted)
+}
+
+/// A single `INSERT` of `rows`; `None` fields are bound as NULL.
+fn build_insert(rows: impl Iterator<Item = NewLogRow>) -> QueryBuilder<'static, Postgres> {
+    let mut builder = QueryBuilder::new(
+        "INSERT INTO logs (
+            id, level, message, timestamp, service,
+            context, global_context, user_context,
+            user_id, user_username, user_email,
+            device, breadcrumbs,
+            error_name, stack, reason,
+            request_method, request_url, status_code, status_text, duration_ms, response_size, error_message,
+            source_asn, source_org, device_hash, stack_fingerprint,
+            session_id, sequence, content_hash
+        ) ",
+    );
+    builder.push_values(rows, |mut values, row| {
+        let user = row.user.unwrap_or(models::UserInfo { id: None, username: None, email: None });
+        values
+            .push_bind(row.id)
+            .push_bind(row.level)
+            .push_bind(row.message)
+            .push_bind(row.timestamp)
+            .push_bind(row.service)
+            .push_bind(row.context)
+            .push_bind(row.global_context)
+            .push_bind(row.user_context)
+            .push_bind(user.id)
+            .push_bind(user.username)
+            .push_bind(user.email)
+            .push_bind(row.device)
+            .push_bind(row.breadcrumbs)
+            .push_bind(row.error_name)
+            .push_bind(row.stack)
+            .push_bind(row.reason)
+            .push_bind(row.request_method)
+            .push_bind(row.request_url)
+            .push_bind(row.status_code)
+            .push_bind(row.status_text)
+            .push_bind(row.duration_ms)
+            .push_bind(row.response_size)
+            .push_bind(row.error_message)
+            .push_bind(row.source_asn)
+            .push_bind(row.source_org)
+            .push_bind(row.device_hash)
+            .push_bind(row.stack_fingerprint)
+            .push_bind(row.session_id)
+            .push_bind(row.sequence)
+            .push_bind(row.content_hash);
+    });
+    // Retries resend the same id, or for id-less entries the same content hash.
+    builder.push(" ON CONFLICT DO NOTHING");
+    builder
+}
+
+/// Parses an entry's timestamp for the `TIMESTAMPTZ` column. Ingest rejects entries
+/// whose timestamp can't be parsed, so failing here means a caller skipped it.
+fn storage_instant(timestamp: &str) -> Result<DateTime<Utc>, sqlx::Error> {
+    time::parse_flexible(timestamp, time::DEFAULT_FORMATS)
+        .map(|(instant, _)| instant)
+        .ok_or_else(|| sqlx::Error::Encode(format!("unparseable timestamp {:?}", timestamp).into()))
+}
+
+/// Numbers the entries of `entries` that belong to a session but carry no `sequence`,
+/// continuing from the highest sequence stored or seen earlier in the batch for that
+/// session. Concurrent writers to one session may hand out the same number; reads then
+/// fall back to timestamp order between them.
+pub async fn assign_session_sequences(
+    pool: &Pool<Postgres>,
+    entries: &mut [models::LogEntry],
+) -> Result<(), sqlx::Error> {
+    let mut sessions: Vec<String> = entries
+        .iter()
+        .filter(|entry| entry.sequence.is_none())
+        .filter_map(models::LogEntry::session_id)
+        .collect();
+    if sessions.is_empty() {
+        return Ok(());
+    }
+    sessions.sort();
+    sessions.dedup();
+    let stored: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT session_id, MAX(sequence) FROM logs WHERE session_id = ANY($1) AND sequence IS NOT NULL \
+         GROUP BY session_id",
     )
-    .execute(pool)
+    .bind(&sessions)
+    .fetch_all(pool)
     .await?;
-    info!("Index 'idx_logs_timestamp' ensured.");
 
+    let mut latest: HashMap<String, i64> = stored.into_iter().collect();
+    for entry in entries.iter_mut() {
+        let Some(session) = entry.session_id() else {
+            continue;
+        };
+        let latest = latest.entry(session).or_insert(0);
+        match entry.sequence {
+            Some(sequence) => *latest = (*latest).max(sequence),
+            None => {
+                *latest += 1;
+                entry.sequence = Some(*latest);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Postgres caps `NOTIFY` payloads just under 8000 bytes.
+const NOTIFY_PAYLOAD_BYTES: usize = 7900;
+
+/// Announces newly persisted `ids` on `channel`, as JSON arrays small enough for `NOTIFY`,
+/// so live tails on every instance can pick them up.
+pub async fn notify_persisted(pool: &Pool<Postgres>, channel: &str, ids: &[String]) -> Result<(), sqlx::Error> {
+    let mut start = 0;
+    while start < ids.len() {
+        let mut end = start;
+        let mut size = 2;
+        while end < ids.len() && (end == start || size + ids[end].len() + 3 <= NOTIFY_PAYLOAD_BYTES) {
+            size += ids[end].len() + 3;
+            end += 1;
+        }
+        let payload = serde_json::to_string(&ids[start..end]).unwrap_or_default();
+        sqlx::query("SELECT pg_notify($1, $2)").bind(channel).bind(payload).execute(pool).await?;
+        start = end;
+    }
+    Ok(())
+}
+
+/// Every row of `tenant_limits` as `(api_key_sha256, capacity, fill_interval_secs)`.
+pub async fn load_tenant_limits(pool: &Pool<Postgres>) -> Result<Vec<(String, i64, i64)>, sqlx::Error> {
+    sqlx::query_as("SELECT api_key_sha256, capacity, fill_interval_secs FROM tenant_limits")
+        .fetch_all(pool)
+        .await
+}
+
+/// Records an ingest receipt. Identical batches produce identical receipts, so a
+/// resubmission is a no-op.
+pub async fn insert_receipt(pool: &Pool<Postgres>, receipt: &Receipt) -> Result<(), sqlx::Error> {
     sqlx::query(
-        r#"CREATE INDEX IF NOT EXISTS idx_logs_service ON logs (service);"#
+        "INSERT INTO ingest_receipts (hash, signature, entry_ids) VALUES ($1, $2, $3) ON CONFLICT (hash) DO NOTHING",
     )
+    .bind(&receipt.hash)
+    .bind(&receipt.signature)
+    .bind(&receipt.entry_ids)
     .execute(pool)
     .await?;
-    info!("Index 'idx_logs_service' ensured.");
-
-    info!("PostgreSQL database schema initialized successfully.");
     Ok(())
 }
 
-/// Inserts a batch of log entries into the 'logs' table.
-pub async fn insert_log_entries(
+/// The entry ids recorded for the receipt with `hash`, if one was stored.
+pub async fn receipt_entry_ids(pool: &Pool<Postgres>, hash: &str) -> Result<Option<Vec<String>>, sqlx::Error> {
+    sqlx::query_scalar("SELECT entry_ids FROM ingest_receipts WHERE hash = $1")
+        .bind(hash)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Keeps a batch that `sink` failed to write, with the reason, for a later replay.
+pub async fn insert_dead_letter(
     pool: &Pool<Postgres>,
-    log_entries: Vec<models::LogEntry>,
+    sink: &str,
+    error: &str,
+    entries: &[models::LogEntry],
 ) -> Result<(), sqlx::Error> {
-    info!("Attempting to insert batch of {} log entries into PostgreSQL.", log_entries.len());
+    sqlx::query("INSERT INTO sink_dead_letters (sink, error, entries) VALUES ($1, $2, $3)")
+        .bind(sink)
+        .bind(error)
+        .bind(Json(entries))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
 
+/// `DeviceInfo` fields that change from request to request on the same device, so are
+/// kept on the log row rather than shared through `devices`.
+const VOLATILE_DEVICE_FIELDS: [&str; 7] = [
+    "connectionType",
+    "effectiveConnectionType",
+    "rtt",
+    "downlink",
+    "saveData",
+    "totalJsHeapSize",
+    "usedJsHeapSize",
+];
+
+/// Advisory lock key serializing device inserts against [`prune_orphaned_devices`].
+const DEVICES_LOCK: i64 = 0x0064_6576_6963_6573; // "devices"
+
+/// Splits a device's JSON into the fields describing the device itself and the
+/// [`VOLATILE_DEVICE_FIELDS`], which are `None` when none of them are set.
+fn split_device(mut info: serde_json::Value) -> (serde_json::Value, Option<serde_json::Value>) {
+    let Some(fields) = info.as_object_mut() else {
+        return (info, None);
+    };
+    let volatile: serde_json::Map<_, _> = VOLATILE_DEVICE_FIELDS
+        .iter()
+        .filter_map(|field| fields.remove(*field).map(|value| (field.to_string(), value)))
+        .filter(|(_, value)| !value.is_null())
+        .collect();
+    (info, (!volatile.is_empty()).then_some(serde_json::Value::Object(volatile)))
+}
+
+/// Hex SHA-256 of a device's stable JSON (see [`split_device`]). `DeviceInfo` serializes
+/// its fields in declaration order, so identical devices always hash the same.
+fn device_fingerprint(info: &serde_json::Value) -> String {
+    format!("{:x}", Sha256::digest(info.to_string().as_bytes()))
+}
+
+/// Deletes `devices` rows no log references any more, e.g. after retention removed the
+/// last of them. Returns the number of devices removed.
+pub async fn prune_orphaned_devices(pool: &Pool<Postgres>) -> Result<u64, sqlx::Error> {
     let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(DEVICES_LOCK)
+        .execute(&mut *tx)
+        .await?;
+    let result = sqlx::query("DELETE FROM devices WHERE NOT EXISTS (SELECT 1 FROM logs WHERE logs.device_hash = devices.hash)")
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+/// What log reads select from: `logs` plus any normalized device it references.
+const LOG_SOURCE: &str = "logs LEFT JOIN devices ON devices.hash = logs.device_hash";
+
+/// Column list matching [`LogRow`], in table order; select it `FROM` [`LOG_SOURCE`].
+/// A normalized `device` is the shared `devices` row overlaid with the fields the log
+/// kept for itself.
+const LOG_COLUMNS: &str = "id, level, message, timestamp, service, \
+    context, global_context, user_context, user_id, user_username, user_email, \
+    CASE WHEN devices.info IS NULL THEN logs.device ELSE devices.info || COALESCE(logs.device, '{}') END AS device, \
+    breadcrumbs, error_name, stack, reason, \
+    request_method, request_url, status_code, status_text, duration_ms, response_size, error_message, \
+    source_asn, source_org, stack_fingerprint, sequence";
 
-    for log in log_entries {
-        // Convert LogLevel enum to string for DB storage
-        let level_str = match log.level {
-            models::LogLevel::Trace => "trace",
-            models::LogLevel::Debug => "debug",
-            models::LogLevel::Info => "info",
-            models::LogLevel::Warn => "warn",
-            models::LogLevel::Error => "error",
-            models::LogLevel::Fatal => "fatal",
-            models::LogLevel::Critical => "critical",
+/// A row of the 'logs' table, converted back into a `LogEntry` for API responses.
+#[derive(Debug, FromRow)]
+struct LogRow {
+    id: String,
+    level: String,
+    message: String,
+    timestamp: DateTime<Utc>,
+    service: String,
+    context: Option<Json<models::LogContext>>,
+    global_context: Json<models::LogContext>,
+    user_context: Option<Json<models::LogContext>>,
+    user_id: Option<String>,
+    user_username: Option<String>,
+    user_email: Option<String>,
+    device: Option<Json<models::DeviceInfo>>,
+    breadcrumbs: Option<Json<Vec<models::Breadcrumb>>>,
+    error_name: Option<String>,
+    stack: Option<String>,
+    reason: Option<serde_json::Value>,
+    request_method: Option<String>,
+    request_url: Option<String>,
+    status_code: Option<i16>,
+    status_text: Option<String>,
+    duration_ms: Option<i64>,
+    response_size: Option<i64>,
+    error_message: Option<String>,
+    source_asn: Option<i64>,
+    source_org: Option<String>,
+    stack_fingerprint: Option<String>,
+    sequence: Option<i64>,
+}
+
+impl From<LogRow> for models::LogEntry {
+    fn from(row: LogRow) -> Self {
+        let user = if row.user_id.is_some() || row.user_username.is_some() || row.user_email.is_some() {
+            Some(models::UserInfo {
+                id: row.user_id,
+                username: row.user_username,
+                email: row.user_email,
+            })
+        } else {
+            None
         };
 
-        // SQLx's `json` feature allows direct binding of `serde_json::Value` and structs
-        // if they derive Serialize/Deserialize and are compatible with PostgreSQL's JSONB type.
-        // Option values will be inserted as NULL if None.
-        sqlx::query(
-            r#"
-            INSERT INTO logs (
-                id, level, message, timestamp, service,
-                context, global_context, user_context,
-                user_id, user_username, user_email,
-                device, breadcrumbs,
-                error_name, stack, reason,
-                request_method, request_url, status_code, status_text, duration_ms, response_size, error_message
-            ) VALUES (
-                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10,
-                $11, $12, $13, $14, $15, $16, $17, $18, $19, $20,
-                $21, $22, $23
-            )
-            ON CONFLICT (id) DO NOTHING; -- Handle duplicate IDs if any (e.g., retries might send same ID)
-            "#
-        )
-        .bind(log.id)
-        .bind(level_str)
-        .bind(log.message)
-        .bind(log.timestamp)
-        .bind(log.service)
-        .bind(log.context.map(|c| JsonValue::from(serde_json::to_value(c).unwrap_or_default()))) // Convert HashMap to JsonValue
-        .bind(JsonValue::from(serde_json::to_value(log.global_context).unwrap_or_default())) // global_context is not Option
-        .bind(log.user_context.map(|uc| JsonValue::from(serde_json::to_value(uc).unwrap_or_default())))
-        .bind(log.user.as_ref().and_then(|u| u.id.clone()))
-        .bind(log.user.as_ref().and_then(|u| u.username.clone()))
-        .bind(log.user.as_ref().and_then(|u| u.email.clone()))
-        .bind(log.device.map(|d| JsonValue::from(serde_json::to_value(d).unwrap_or_default()))) // Convert DeviceInfo struct to JsonValue
-        .bind(log.breadcrumbs.map(|b| JsonValue::from(serde_json::to_value(b).unwrap_or_default()))) // Convert Vec<Breadcrumb> to JsonValue
-        .bind(log.error_name)
-        .bind(log.stack)
-        .bind(log.reason)
-        .bind(log.request_method)
-        .bind(log.request_url)
-        .bind(log.status_code.map(|s| s as i16)) // Use i16 for SMALLINT
-        .bind(log.status_text)
-        .bind(log.duration_ms.map(|d| d as i64)) // Use i64 for BIGINT
-        .bind(log.response_size.map(|s| s as i64))
-        .bind(log.error_message)
-        .execute(&mut *tx) // Execute within the transaction
+        models::LogEntry {
+            id: Some(row.id),
+            // Levels are written from `LogLevel::as_str`, so anything else is a corrupt row.
+            level: models::LogLevel::parse(&row.level).unwrap_or(models::LogLevel::Info),
+            message: row.message,
+            timestamp: time::to_storage_string(row.timestamp),
+            service: row.service,
+            context: row.context.map(|c| c.0),
+            global_context: row.global_context.0,
+            user_context: row.user_context.map(|c| c.0),
+            user,
+            device: row.device.map(|d| d.0),
+            breadcrumbs: row.breadcrumbs.map(|b| b.0),
+            error_name: row.error_name,
+            stack: row.stack,
+            reason: row.reason,
+            request_method: row.request_method,
+            request_url: row.request_url,
+            status_code: row.status_code.map(|s| s as u16),
+            status_text: row.status_text,
+            duration_ms: row.duration_ms.map(|d| d as u64),
+            response_size: row.response_size.map(|s| s as u64),
+            error_message: row.error_message,
+            priority: None,
+            source_asn: row.source_asn.map(|asn| asn as u32),
+            source_org: row.source_org,
+            stack_fingerprint: row.stack_fingerprint,
+            content_hash: None,
+            sequence: row.sequence,
+        }
+    }
+}
+
+/// Fetches the most recent `limit` logs with a timestamp at or after `since`, returned oldest first.
+/// `since` must be in the normalized storage form (see `pkg::time::to_storage_string`).
+pub async fn fetch_logs_since(
+    pool: &Pool<Postgres>,
+    since: &str,
+    limit: i64,
+) -> Result<Vec<models::LogEntry>, sqlx::Error> {
+    let sql = format!(
+        "SELECT {} FROM {} WHERE timestamp >= $1::TIMESTAMPTZ AND deleted_at IS NULL ORDER BY timestamp DESC, id DESC LIMIT $2",
+        LOG_COLUMNS, LOG_SOURCE
+    );
+    let rows: Vec<LogRow> = sqlx::query_as(&sql)
+        .bind(since)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().rev().map(models::LogEntry::from).collect())
+}
+
+/// Fetches up to `limit` logs that sort after `cursor` in `(timestamp, id)` order, oldest
+/// first, optionally only those of `service`.
+pub async fn fetch_logs_after(
+    pool: &Pool<Postgres>,
+    cursor: &Cursor,
+    service: Option<&str>,
+    limit: i64,
+) -> Result<Vec<models::LogEntry>, sqlx::Error> {
+    let sql = format!(
+        "SELECT {} FROM {} WHERE (timestamp, id) > ($1::TIMESTAMPTZ, $2) AND ($3::TEXT IS NULL OR service = $3) \
+         AND deleted_at IS NULL ORDER BY timestamp, id LIMIT $4",
+        LOG_COLUMNS, LOG_SOURCE
+    );
+    let rows: Vec<LogRow> = sqlx::query_as(&sql)
+        .bind(&cursor.timestamp)
+        .bind(&cursor.id)
+        .bind(service)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(models::LogEntry::from).collect())
+}
+
+/// Returns the most recent log of every service, optionally restricted to one level.
+pub async fn latest_log_per_service(
+    pool: &Pool<Postgres>,
+    level: Option<&models::LogLevel>,
+) -> Result<Vec<models::LogEntry>, sqlx::Error> {
+    let sql = format!(
+        "SELECT DISTINCT ON (service) {} FROM {} \
+         WHERE ($1::TEXT IS NULL OR level = $1) AND deleted_at IS NULL \
+         ORDER BY service, timestamp DESC, id DESC",
+        LOG_COLUMNS, LOG_SOURCE
+    );
+    let rows: Vec<LogRow> = sqlx::query_as(&sql)
+        .bind(level.map(|l| l.as_str()))
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(models::LogEntry::from).collect())
+}
+
+/// Fetches the logs with the given ids, in the order of `ids`.
+pub async fn get_logs_by_ids(pool: &Pool<Postgres>, ids: &[String]) -> Result<Vec<models::LogEntry>, sqlx::Error> {
+    let sql = format!(
+        "SELECT {} FROM {} WHERE id = ANY($1) AND deleted_at IS NULL ORDER BY array_position($1, id)",
+        LOG_COLUMNS, LOG_SOURCE
+    );
+    let rows: Vec<LogRow> = sqlx::query_as(&sql).bind(ids).fetch_all(pool).await?;
+    Ok(rows.into_iter().map(models::LogEntry::from).collect())
+}
+
+/// Looks up a single log by its id.
+pub async fn get_log_by_id(
+    pool: &Pool<Postgres>,
+    id: &str,
+) -> Result<Option<models::LogEntry>, sqlx::Error> {
+    let sql = format!("SELECT {} FROM {} WHERE id = $1 AND deleted_at IS NULL", LOG_COLUMNS, LOG_SOURCE);
+    let row: Option<LogRow> = sqlx::query_as(&sql).bind(id).fetch_optional(pool).await?;
+    Ok(row.map(models::LogEntry::from))
+}
+
+/// Removes logs with a timestamp before `cutoff` (in the normalized storage form), or with
+/// `soft` only marks them `deleted_at` so they disappear from reads but can still be
+/// recovered until [`purge_deleted`]. Returns the number of rows affected.
+pub async fn expire_logs_before(pool: &Pool<Postgres>, cutoff: &str, soft: bool) -> Result<u64, sqlx::Error> {
+    let sql = if soft {
+        "UPDATE logs SET deleted_at = NOW() WHERE timestamp < $1::TIMESTAMPTZ AND deleted_at IS NULL"
+    } else {
+        "DELETE FROM logs WHERE timestamp < $1::TIMESTAMPTZ"
+    };
+    let result = sqlx::query(sql).bind(cutoff).execute(pool).await?;
+    Ok(result.rows_affected())
+}
+
+/// Hard-deletes rows that were soft-deleted more than `grace` ago.
+pub async fn purge_deleted(pool: &Pool<Postgres>, grace: Duration) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM logs WHERE deleted_at < NOW() - make_interval(secs => $1)")
+        .bind(grace.as_secs_f64())
+        .execute(pool)
         .await?;
+    Ok(result.rows_affected())
+}
+
+/// Filters accepted by the `/logs` query endpoint. Unset fields don't constrain the result.
+/// Serializes to the query cache's key, so every field is part of it.
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct LogFilter {
+    /// JSON object that `context` must contain (`context @> $1`).
+    pub context_match: Option<serde_json::Value>,
+    /// Exact matches on the stored `user_*` columns.
+    pub user_id: Option<String>,
+    pub user_email: Option<String>,
+    pub user_username: Option<String>,
+    pub service: Option<String>,
+    pub level: Option<String>,
+    /// Restricts to one session and orders by `sequence` ahead of `timestamp`.
+    pub session_id: Option<String>,
+    /// Time window: `since` inclusive, `until` exclusive.
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl LogFilter {
+    fn user_columns(&self) -> [(&'static str, Option<&String>); 3] {
+        [
+            ("user_id", self.user_id.as_ref()),
+            ("user_email", self.user_email.as_ref()),
+            ("user_username", self.user_username.as_ref()),
+        ]
+    }
+
+    /// Columns this filter's predicates narrow on, as fed to the index advisor. A
+    /// `context_match` is one `context @>` containment test, whatever keys it holds.
+    pub fn filter_columns(&self) -> Vec<&'static str> {
+        let mut columns = Vec::new();
+        if self.context_match.is_some() {
+            columns.push("context");
+        }
+        for (column, value) in self.user_columns() {
+            if value.is_some() {
+                columns.push(column);
+            }
+        }
+        if self.service.is_some() {
+            columns.push("service");
+        }
+        if self.level.is_some() {
+            columns.push("level");
+        }
+        if self.session_id.is_some() {
+            columns.push("session_id");
+        }
+        if self.since.is_some() || self.until.is_some() {
+            columns.push("timestamp");
+        }
+        columns
+    }
+}
+
+/// Appends the `WHERE` clause for `filter` to `builder`.
+fn push_log_filter(builder: &mut QueryBuilder<'_, Postgres>, filter: &LogFilter) {
+    builder.push(" WHERE deleted_at IS NULL");
+    if let Some(context_match) = &filter.context_match {
+        builder
+            .push(" AND context @> ")
+            .push_bind(context_match.clone())
+            .push("::jsonb");
+    }
+    for (column, value) in filter.user_columns() {
+        if let Some(value) = value {
+            builder.push(format!(" AND {} = ", column)).push_bind(value.clone());
+        }
+    }
+    if let Some(service) = &filter.service {
+        builder.push(" AND service = ").push_bind(service.clone());
+    }
+    if let Some(level) = &filter.level {
+        builder.push(" AND level = ").push_bind(level.clone());
+    }
+    if let Some(session_id) = &filter.session_id {
+        builder.push(" AND session_id = ").push_bind(session_id.clone());
+    }
+    if let Some(since) = filter.since {
+        builder.push(" AND timestamp >= ").push_bind(since);
+    }
+    if let Some(until) = filter.until {
+        builder.push(" AND timestamp < ").push_bind(until);
+    }
+}
+
+/// Within a session, client timestamps can tie, so `sequence` decides ahead of them;
+/// entries without one sort after those with one.
+fn order_by(filter: &LogFilter, direction: &str) -> String {
+    if filter.session_id.is_some() {
+        format!("sequence {0} NULLS LAST, timestamp {0}, id {0}", direction)
+    } else {
+        format!("timestamp {0}, id {0}", direction)
+    }
+}
+
+fn build_query_logs(filter: &LogFilter) -> QueryBuilder<'_, Postgres> {
+    build_query_logs_with_prefix(filter, "")
+}
+
+fn build_query_logs_with_prefix<'a>(filter: &'a LogFilter, prefix: &str) -> QueryBuilder<'a, Postgres> {
+    let mut builder = QueryBuilder::new(format!("{}SELECT {} FROM {}", prefix, LOG_COLUMNS, LOG_SOURCE));
+    push_log_filter(&mut builder, filter);
+    builder
+        .push(" ORDER BY ")
+        .push(order_by(filter, "DESC"))
+        .push(" LIMIT ")
+        .push_bind(filter.limit)
+        .push(" OFFSET ")
+        .push_bind(filter.offset);
+    builder
+}
+
+/// Returns logs matching `filter`, newest first.
+pub async fn query_logs(
+    pool: &Pool<Postgres>,
+    filter: &LogFilter,
+) -> Result<Vec<models::LogEntry>, sqlx::Error> {
+    let rows: Vec<LogRow> = build_query_logs(filter)
+        .build_query_as()
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(models::LogEntry::from).collect())
+}
+
+/// Sends the logs `query_logs` would return into `entries` as they are read, instead of
+/// collecting them. Stops early, without error, once the receiver is dropped.
+pub async fn stream_query_logs(
+    pool: &Pool<Postgres>,
+    filter: &LogFilter,
+    entries: mpsc::Sender<models::LogEntry>,
+) -> Result<(), sqlx::Error> {
+    let mut builder = build_query_logs(filter);
+    let mut rows = builder.build_query_as::<LogRow>().fetch(pool);
+    while let Some(row) = rows.try_next().await? {
+        if entries.send(models::LogEntry::from(row)).await.is_err() {
+            break;
+        }
     }
-    tx.commit().await?; // Commit the transaction
-    info!("Successfully inserted batch of log entries into PostgreSQL.");
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Streams every log matching `filter` into `entries`, oldest first, ignoring its
+/// `limit` and `offset`. Stops early, without error, once the receiver is dropped.
+pub async fn export_logs(
+    pool: &Pool<Postgres>,
+    filter: &LogFilter,
+    entries: mpsc::Sender<models::LogEntry>,
+) -> Result<(), sqlx::Error> {
+    let mut builder = QueryBuilder::new(format!("SELECT {} FROM {}", LOG_COLUMNS, LOG_SOURCE));
+    push_log_filter(&mut builder, filter);
+    builder.push(" ORDER BY ").push(order_by(filter, "ASC"));
+    let mut rows = builder.build_query_as::<LogRow>().fetch(pool);
+    while let Some(row) = rows.try_next().await? {
+        if entries.send(models::LogEntry::from(row)).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the `query_logs` query for `filter` under `EXPLAIN (ANALYZE, BUFFERS)` and
+/// returns the plan, one line per element.
+pub async fn explain_query_logs(pool: &Pool<Postgres>, filter: &LogFilter) -> Result<Vec<String>, sqlx::Error> {
+    build_query_logs_with_prefix(filter, "EXPLAIN (ANALYZE, BUFFERS) ")
+        .build_query_scalar()
+        .fetch_all(pool)
+        .await
+}
+
+/// Matching logs counted per device kind. Entries without a device, or without one of
+/// its fields, group under null.
+#[derive(Debug, PartialEq, FromRow, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceBreakdown {
+    pub os_name: Option<String>,
+    /// The browser family (`device.family`).
+    pub browser: Option<String>,
+    /// `device.userAgentClientHints.mobile`.
+    pub mobile: Option<bool>,
+    pub count: i64,
+}
+
+/// Counts the logs matching `filter` (ignoring its limit and offset) by device OS,
+/// browser family and mobile flag, largest groups first.
+pub async fn device_breakdown(pool: &Pool<Postgres>, filter: &LogFilter) -> Result<Vec<DeviceBreakdown>, sqlx::Error> {
+    let mut builder = QueryBuilder::new(format!(
+        "SELECT device->>'osName' AS os_name, device->>'family' AS browser, \
+         (device->'userAgentClientHints'->>'mobile')::BOOLEAN AS mobile, COUNT(*) AS count \
+         FROM (SELECT {} FROM {}",
+        LOG_COLUMNS, LOG_SOURCE
+    ));
+    push_log_filter(&mut builder, filter);
+    builder.push(") AS matching GROUP BY 1, 2, 3 ORDER BY count DESC, 1 NULLS LAST, 2 NULLS LAST, 3 NULLS LAST");
+    builder.build_query_as().fetch_all(pool).await
+}
+
+/// Number of logs matching `filter` per level, most frequent first.
+pub async fn count_by_level(pool: &Pool<Postgres>, filter: &LogFilter) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    count_by(pool, filter, "level").await
+}
+
+/// Number of logs matching `filter` per service, most frequent first.
+pub async fn count_by_service(pool: &Pool<Postgres>, filter: &LogFilter) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    count_by(pool, filter, "service").await
+}
+
+async fn count_by(pool: &Pool<Postgres>, filter: &LogFilter, column: &str) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    let mut builder = QueryBuilder::new(format!("SELECT {0}, COUNT(*) FROM logs", column));
+    push_log_filter(&mut builder, filter);
+    builder.push(format!(" GROUP BY {0} ORDER BY 2 DESC, {0}", column));
+    builder.build_query_as().fetch_all(pool).await
+}
+
+/// Text-search document for a message; matches the `idx_logs_message_search` index.
+const MESSAGE_DOCUMENT: &str = "to_tsvector('english', message)";
+
+/// A search result and how well its message matched (`ts_rank`).
+#[derive(Debug, serde::Serialize)]
+pub struct SearchHit {
+    pub rank: f32,
+    #[serde(flatten)]
+    pub entry: models::LogEntry,
+}
+
+#[derive(FromRow)]
+struct SearchRow {
+    #[sqlx(flatten)]
+    log: LogRow,
+    rank: f32,
+}
+
+/// Returns logs matching `filter` whose message matches the words of `text`
+/// (`plainto_tsquery`), best match first.
+pub async fn search_logs(pool: &Pool<Postgres>, text: &str, filter: &LogFilter) -> Result<Vec<SearchHit>, sqlx::Error> {
+    let mut builder = QueryBuilder::new(format!("SELECT {}, ts_rank({}, plainto_tsquery('english', ", LOG_COLUMNS, MESSAGE_DOCUMENT));
+    builder
+        .push_bind(text.to_string())
+        .push(format!(")) AS rank FROM {}", LOG_SOURCE));
+    push_log_filter(&mut builder, filter);
+    builder
+        .push(format!(" AND {} @@ plainto_tsquery('english', ", MESSAGE_DOCUMENT))
+        .push_bind(text.to_string())
+        .push(") ORDER BY rank DESC, timestamp DESC, id DESC LIMIT ")
+        .push_bind(filter.limit)
+        .push(" OFFSET ")
+        .push_bind(filter.offset);
+    let rows: Vec<SearchRow> = builder.build_query_as().fetch_all(pool).await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| SearchHit {
+            rank: row.rank,
+            entry: models::LogEntry::from(row.log),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::sync::OnceCell;
+
+    static SCHEMA: OnceCell<()> = OnceCell::const_new();
+
+    /// Connects to `TEST_DATABASE_URL` and ensures the schema exists.
+    /// Returns `None` (and the calling test should return early) when it is unset.
+    pub(crate) async fn test_pool() -> Option<Pool<Postgres>> {
+        let url = std::env::var("TEST_DATABASE_URL").ok()?;
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(&url)
+            .await
+            .expect("TEST_DATABASE_URL is set but unreachable");
+        SCHEMA
+            .get_or_init(|| async {
+                initialize_db_schema(&pool, false).await.expect("schema init failed");
+            })
+            .await;
+        Some(pool)
+    }
+
+    /// Builds a minimal valid entry for `service`; tests use a unique service name
+    /// so they can share one database.
+    pub(crate) fn sample_entry(service: &str, id: &str, timestamp: &str) -> models::LogEntry {
+        serde_json::from_value(json!({
+            "id": id,
+            "level": "info",
+            "message": format!("message {}", id),
+            "timestamp": timestamp,
+            "service": service,
+            "context": { "step": id },
+            "user": { "id": "u-1" },
+            "statusCode": 204
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fetch_logs_since_round_trips_entries() {
+        let Some(pool) = test_pool().await else { return };
+        let service = format!("svc-{}", uuid::Uuid::new_v4());
+        let ids: Vec<String> = (0..3).map(|_| uuid::Uuid::new_v4().to_string()).collect();
+        // Later than anything earlier runs inserted, so the window only holds this run's rows.
+        let base = chrono::Utc::now() + chrono::Duration::days(365 * 100);
+        let at = |secs: i64| crate::pkg::time::to_storage_string(base + chrono::Duration::seconds(secs));
+        let entries = vec![
+            sample_entry(&service, &ids[0], &at(1)),
+            sample_entry(&service, &ids[1], &at(2)),
+            sample_entry(&service, &ids[2], &at(3)),
+        ];
+        insert_log_entries(&pool, entries, false).await.unwrap();
+
+        let fetched = fetch_logs_since(&pool, &at(2), 10).await.unwrap();
+        let fetched: Vec<_> = fetched.into_iter().filter(|e| e.service == service).collect();
+        assert_eq!(fetched.len(), 2);
+        assert_eq!(fetched[0].id.as_deref(), Some(ids[1].as_str()));
+        assert_eq!(fetched[1].id.as_deref(), Some(ids[2].as_str()));
+        assert_eq!(fetched[0].status_code, Some(204));
+        assert_eq!(fetched[0].user.as_ref().unwrap().id.as_deref(), Some("u-1"));
+        assert_eq!(fetched[0].context.as_ref().unwrap()["step"], json!(ids[1]));
+    }
+
+    #[tokio::test]
+    async fn test_batches_insert_as_one_statement_per_chunk() {
+        let rows = |service: &str, count: usize| -> Vec<models::LogEntry> {
+            (0..count)
+                .map(|n| sample_entry(service, &format!("{}-{}", service, n), "2024-05-01T10:00:00Z"))
+                .collect()
+        };
+        let statement = build_insert(rows("web", 1000).into_iter().map(|entry| NewLogRow::new(entry).unwrap()));
+        assert_eq!(statement.sql().matches("INSERT INTO logs").count(), 1);
+        assert_eq!(statement.sql().matches("($").count(), 1000);
+
+        let Some(pool) = test_pool().await else { return };
+        let count = |service: String| {
+            let pool = pool.clone();
+            async move {
+                sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM logs WHERE service = $1")
+                    .bind(service)
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap()
+            }
+        };
+        let service = format!("svc-{}", uuid::Uuid::new_v4());
+        insert_log_entries(&pool, rows(&service, 1000), false).await.unwrap();
+        assert_eq!(count(service.clone()).await, 1000);
+
+        // One row past a chunk takes a second statement; resending it all inserts nothing.
+        let service = format!("svc-{}", uuid::Uuid::new_v4());
+        insert_log_entries(&pool, rows(&service, INSERT_CHUNK_ROWS + 1), false).await.unwrap();
+        insert_log_entries(&pool, rows(&service, INSERT_CHUNK_ROWS + 1), false).await.unwrap();
+        assert_eq!(count(service).await, INSERT_CHUNK_ROWS as i64 + 1);
+    }
+
+    #[tokio::test]
+    async fn test_timestamps_are_stored_and_ordered_as_instants() {
+        let Some(pool) = test_pool().await else { return };
+        let service = format!("svc-{}", uuid::Uuid::new_v4());
+        // As text, "12:00+02:00" would sort after "11:00Z" and the epoch value (11:30Z) before both.
+        let entries = vec![
+            sample_entry(&service, &format!("{}-offset", service), "2024-05-01T12:00:00+02:00"),
+            sample_entry(&service, &format!("{}-utc", service), "2024-05-01T11:00:00Z"),
+            sample_entry(&service, &format!("{}-millis", service), "1714563000000"),
+        ];
+        insert_log_entries(&pool, entries, false).await.unwrap();
+
+        let filter = LogFilter {
+            service: Some(service.clone()),
+            since: Some("2024-05-01T10:30:00Z".parse().unwrap()),
+            limit: 10,
+            ..Default::default()
+        };
+        let fetched = query_logs(&pool, &filter).await.unwrap();
+        assert_eq!(
+            fetched.iter().map(|e| (e.id.clone().unwrap(), e.timestamp.as_str())).collect::<Vec<_>>(),
+            [
+                (format!("{}-millis", service), "2024-05-01T11:30:00.000000Z"),
+                (format!("{}-utc", service), "2024-05-01T11:00:00.000000Z"),
+            ]
+        );
+
+        let malformed = sample_entry(&service, &format!("{}-bad", service), "not a time");
+        let result = insert_log_entries(&pool, vec![malformed], false).await;
+        assert!(matches!(result, Err(sqlx::Error::Encode(_))), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_normalized_devices_are_shared_and_joined_back() {
+        let Some(pool) = test_pool().await else { return };
+        let service = format!("svc-{}", uuid::Uuid::new_v4());
+        let model = format!("Pixel-{}", uuid::Uuid::new_v4());
+        let ids: Vec<String> = (0..2).map(|_| uuid::Uuid::new_v4().to_string()).collect();
+        // The same device on two connections still shares one `devices` row.
+        let entries = ids
+            .iter()
+            .zip([50, 300])
+            .map(|(id, rtt)| {
+                let mut entry = sample_entry(&service, id, "2024-05-01T00:00:00.000000Z");
+                entry.device =
+                    serde_json::from_value(json!({ "osName": "Android", "model": model, "screenWidth": 412, "rtt": rtt })).unwrap();
+                entry
+            })
+            .collect();
+        insert_log_entries(&pool, entries, true).await.unwrap();
+
+        let hashes: Vec<(Option<String>, Option<serde_json::Value>)> =
+            sqlx::query_as("SELECT device_hash, device FROM logs WHERE service = $1 ORDER BY device->'rtt'")
+                .bind(&service)
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(hashes.len(), 2);
+        assert!(hashes.iter().all(|(hash, _)| hash.is_some()));
+        assert_eq!(hashes[0].0, hashes[1].0);
+        assert_eq!(hashes[0].1, Some(json!({ "rtt": 50 })));
+        let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM devices WHERE info->>'model' = $1")
+            .bind(&model)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, 1);
+
+        let stored = get_log_by_id(&pool, &ids[1]).await.unwrap().unwrap();
+        let device = stored.device.unwrap();
+        assert_eq!(device.model.as_deref(), Some(model.as_str()));
+        assert_eq!(device.os_name.as_deref(), Some("Android"));
+        assert_eq!(device.screen_width, Some(412));
+        assert_eq!(device.rtt, Some(300));
+
+        sqlx::query("DELETE FROM logs WHERE service = $1").bind(&service).execute(&pool).await.unwrap();
+        prune_orphaned_devices(&pool).await.unwrap();
+        let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM devices WHERE info->>'model' = $1")
+            .bind(&model)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, 0);
+    }
+
+    #[tokio::test]
+    async fn test_soft_deleted_rows_are_hidden_until_purged() {
+        let Some(pool) = test_pool().await else { return };
+        let service = format!("svc-{}", uuid::Uuid::new_v4());
+        // Older than anything other tests write, so the cutoff below only reaches these rows.
+        let old_id = uuid::Uuid::new_v4().to_string();
+        let kept_id = uuid::Uuid::new_v4().to_string();
+        insert_log_entries(
+            &pool,
+            vec![
+                sample_entry(&service, &old_id, "1990-01-01T00:00:00.000000Z"),
+                sample_entry(&service, &kept_id, "1990-01-03T00:00:00.000000Z"),
+            ],
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(expire_logs_before(&pool, "1990-01-02T00:00:00.000000Z", true).await.unwrap(), 1);
+        assert!(get_log_by_id(&pool, &old_id).await.unwrap().is_none());
+        assert!(get_log_by_id(&pool, &kept_id).await.unwrap().is_some());
+        let visible = fetch_logs_since(&pool, "1990-01-01T00:00:00.000000Z", 1000).await.unwrap();
+        assert!(visible.iter().all(|e| e.id.as_deref() != Some(old_id.as_str())));
+
+        let present = |id: String| {
+            let pool = pool.clone();
+            async move {
+                sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM logs WHERE id = $1)")
+                    .bind(id)
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap()
+            }
+        };
+        assert!(present(old_id.clone()).await, "soft delete keeps the row");
+        purge_deleted(&pool, Duration::from_secs(3600)).await.unwrap();
+        assert!(present(old_id.clone()).await, "still within the grace period");
+        purge_deleted(&pool, Duration::ZERO).await.unwrap();
+        assert!(!present(old_id).await);
+        assert!(present(kept_id).await);
+    }
+
+    #[tokio::test]
+    async fn test_query_logs_by_user() {
+        let Some(pool) = test_pool().await else { return };
+        let run = uuid::Uuid::new_v4().simple().to_string();
+        let mut entries = Vec::new();
+        for (n, user) in ["alice", "bob", "alice"].iter().enumerate() {
+            let mut entry = sample_entry("web", &format!("{}-{}", run, n), "2024-05-01T00:00:00.000000Z");
+            entry.user = Some(models::UserInfo {
+                id: Some(format!("{}-{}", user, run)),
+                username: Some(format!("{}_{}", user, run)),
+                email: Some(format!("{}-{}@example.com", user, run)),
+            });
+            entries.push(entry);
+        }
+        insert_log_entries(&pool, entries, false).await.unwrap();
+
+        let by = |filter: LogFilter| {
+            let pool = pool.clone();
+            async move {
+                let mut ids: Vec<String> = query_logs(&pool, &LogFilter { limit: 10, ..filter })
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .filter_map(|e| e.id)
+                    .collect();
+                ids.sort();
+                ids
+            }
+        };
+        let alice = vec![format!("{}-0", run), format!("{}-2", run)];
+        assert_eq!(by(LogFilter { user_id: Some(format!("alice-{}", run)), ..Default::default() }).await, alice);
+        assert_eq!(
+            by(LogFilter { user_email: Some(format!("alice-{}@example.com", run)), ..Default::default() }).await,
+            alice
+        );
+        assert_eq!(
+            by(LogFilter { user_username: Some(format!("bob_{}", run)), ..Default::default() }).await,
+            vec![format!("{}-1", run)]
+        );
+        // Exact match only.
+        assert!(by(LogFilter { user_username: Some(format!("ALICE_{}", run)), ..Default::default() }).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_latest_log_per_service_returns_newest() {
+        let Some(pool) = test_pool().await else { return };
+        let services: Vec<String> = (0..2).map(|_| format!("svc-{}", uuid::Uuid::new_v4())).collect();
+        let mut entries = Vec::new();
+        for service in &services {
+            for second in 1..=3 {
+                let ts = format!("2024-05-01T00:00:0{}.000000Z", second);
+                let id = format!("{}-{}", service, second);
+                entries.push(sample_entry(service, &id, &ts));
+            }
+        }
+        let mut error = sample_entry(&services[0], &format!("{}-err", services[0]), "2024-05-01T00:00:02.500000Z");
+        error.level = models::LogLevel::Error;
+        entries.push(error);
+        insert_log_entries(&pool, entries, false).await.unwrap();
+
+        let latest = latest_log_per_service(&pool, None).await.unwrap();
+        let ours: Vec<_> = latest.iter().filter(|e| services.contains(&e.service)).collect();
+        assert_eq!(ours.len(), 2);
+        for entry in ours {
+            assert_eq!(entry.id, Some(format!("{}-3", entry.service)));
+        }
+
+        let latest_errors = latest_log_per_service(&pool, Some(&models::LogLevel::Error)).await.unwrap();
+        let ours: Vec<_> = latest_errors.iter().filter(|e| services.contains(&e.service)).collect();
+        assert_eq!(ours.len(), 1);
+        assert_eq!(ours[0].id, Some(format!("{}-err", services[0])));
+    }
+
+    #[tokio::test]
+    async fn test_session_entries_with_tied_timestamps_follow_sequence() {
+        let Some(pool) = test_pool().await else { return };
+        let session = uuid::Uuid::new_v4().to_string();
+        let in_session = |id: &str, sequence: Option<i64>| {
+            let mut entry = sample_entry("replay", &format!("{}-{}", session, id), "2024-07-01T00:00:00.000000Z");
+            entry.context = Some(serde_json::from_value(json!({ "session_id": session })).unwrap());
+            entry.sequence = sequence;
+            entry
+        };
+        // Ids sort opposite to the sequence, so only `sequence` can produce the expected order.
+        insert_log_entries(&pool, vec![in_session("c", Some(1)), in_session("a", Some(3)), in_session("b", Some(2))], false)
+            .await
+            .unwrap();
+
+        let mut later = vec![in_session("e", None), in_session("d", None)];
+        assign_session_sequences(&pool, &mut later).await.unwrap();
+        assert_eq!(later.iter().map(|e| e.sequence).collect::<Vec<_>>(), [Some(4), Some(5)]);
+        insert_log_entries(&pool, later, false).await.unwrap();
+
+        let filter = LogFilter {
+            session_id: Some(session.clone()),
+            limit: 10,
+            ..Default::default()
+        };
+        let ids: Vec<String> = query_logs(&pool, &filter)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.id.unwrap().trim_start_matches(&format!("{}-", session)).to_string())
+            .collect();
+        assert_eq!(ids, ["d", "e", "a", "b", "c"]);
+
+        let (tx, mut rx) = mpsc::channel(10);
+        export_logs(&pool, &filter, tx).await.unwrap();
+        let mut sequences = Vec::new();
+        while let Some(entry) = rx.recv().await {
+            sequences.push(entry.sequence.unwrap());
+        }
+        assert_eq!(sequences, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_context_match_uses_containment() {
+        let filter = LogFilter {
+            context_match: Some(json!({ "order_id": "12345" })),
+            limit: 10,
+            offset: 0,
+            ..Default::default()
+        };
+        let sql = build_query_logs(&filter).into_sql();
+        assert!(sql.contains("context @> $1::jsonb"), "{}", sql);
+        assert!(sql.ends_with("LIMIT $2 OFFSET $3"), "{}", sql);
+    }
+
+    #[tokio::test]
+    async fn test_search_logs_ranks_matching_messages() {
+        let Some(pool) = test_pool().await else { return };
+        let service = format!("svc-{}", uuid::Uuid::new_v4());
+        let messages = [
+            "Payment declined by issuer",
+            "User opened the settings page",
+            "Payment declined: card expired, payment retried and declined again",
+            "Payments dashboard loaded",
+        ];
+        let mut ids = Vec::new();
+        let mut entries = Vec::new();
+        for (i, message) in messages.iter().enumerate() {
+            let id = uuid::Uuid::new_v4().to_string();
+            let mut entry = sample_entry(&service, &id, &format!("2024-07-01T00:00:0{}.000000Z", i));
+            entry.message = message.to_string();
+            ids.push(id);
+            entries.push(entry);
+        }
+        insert_log_entries(&pool, entries, false).await.unwrap();
+
+        let filter = LogFilter {
+            service: Some(service.clone()),
+            limit: 10,
+            offset: 0,
+            ..Default::default()
+        };
+        let hits = search_logs(&pool, "declined payment", &filter).await.unwrap();
+        let found: Vec<_> = hits.iter().map(|hit| hit.entry.id.clone().unwrap()).collect();
+        // Stemming matches "Payments" too, but only messages with both words match.
+        assert_eq!(found, [ids[2].clone(), ids[0].clone()]);
+        assert!(hits[0].rank > hits[1].rank);
+
+        let filter = LogFilter { limit: 1, offset: 1, ..filter };
+        let hits = search_logs(&pool, "declined payment", &filter).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].entry.id.as_deref(), Some(ids[0].as_str()));
+        assert!(search_logs(&pool, "settings page", &filter).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_counts_by_level_and_service() {
+        let Some(pool) = test_pool().await else { return };
+        let (web, api) = (format!("web-{}", uuid::Uuid::new_v4()), format!("api-{}", uuid::Uuid::new_v4()));
+        // A minute of its own in the 1990s, clear of the windows other tests use and of other runs.
+        let offset = 700_000_000 + (uuid::Uuid::new_v4().as_u128() % 200_000_000) as i64;
+        let base = chrono::DateTime::<chrono::Utc>::UNIX_EPOCH + chrono::Duration::seconds(offset);
+        let at = |secs: i64| crate::pkg::time::to_storage_string(base + chrono::Duration::seconds(secs));
+        let distribution = [(&web, models::LogLevel::Error, 3), (&web, models::LogLevel::Info, 1), (&api, models::LogLevel::Error, 2)];
+        let mut entries = Vec::new();
+        for (service, level, count) in distribution {
+            for _ in 0..count {
+                let mut entry = sample_entry(service, &uuid::Uuid::new_v4().to_string(), &at(entries.len() as i64));
+                entry.level = level;
+                entries.push(entry);
+            }
+        }
+        insert_log_entries(&pool, entries, false).await.unwrap();
+
+        let bound = |secs: i64| Some(base + chrono::Duration::seconds(secs));
+        let filter = LogFilter {
+            since: bound(0),
+            until: bound(60),
+            ..Default::default()
+        };
+        assert_eq!(
+            count_by_level(&pool, &filter).await.unwrap(),
+            [("error".to_string(), 5), ("info".to_string(), 1)]
+        );
+        assert_eq!(count_by_service(&pool, &filter).await.unwrap(), [(web.clone(), 4), (api, 2)]);
+
+        let filter = LogFilter { since: bound(4), ..filter };
+        assert_eq!(count_by_level(&pool, &filter).await.unwrap(), [("error".to_string(), 2)]);
+    }
+
+    #[tokio::test]
+    async fn test_query_logs_by_context_match() {
+        let Some(pool) = test_pool().await else { return };
+        let order = uuid::Uuid::new_v4().to_string();
+        let mut matching = sample_entry("checkout", &uuid::Uuid::new_v4().to_string(), "2024-06-01T00:00:00.000000Z");
+        matching.context = Some(serde_json::from_value(json!({ "order_id": order, "step": "pay" })).unwrap());
+        let mut other = sample_entry("checkout", &uuid::Uuid::new_v4().to_string(), "2024-06-01T00:00:01.000000Z");
+        other.context = Some(serde_json::from_value(json!({ "order_id": "someone-else" })).unwrap());
+        let matching_id = matching.id.clone();
+        insert_log_entries(&pool, vec![matching, other], false).await.unwrap();
+
+        let filter = LogFilter {
+            context_match: Some(json!({ "order_id": order })),
+            limit: 10,
+            offset: 0,
+            ..Default::default()
+        };
+        let found = query_logs(&pool, &filter).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, matching_id);
+
+        let filter = LogFilter {
+            context_match: Some(json!({ "order_id": order, "step": "ship" })),
+            limit: 10,
+            offset: 0,
+            ..Default::default()
+        };
+        assert!(query_logs(&pool, &filter).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+use moka::future::Cache;
+use std::{future::Future, sync::Arc, time::Duration};
+
+use crate::models::LogEntry;
+use crate::pkg::metrics::QUERY_CACHE_REQUESTS;
+
+/// A cached query result, shared between every request that hits it.
+pub type CachedLogs = Arc<Vec<LogEntry>>;
+
+/// Read-through cache for repeated read queries (dashboards polling the same view),
+/// bounded by entry count (LRU) and expiring each result after a fixed TTL.
+pub struct QueryCache {
+    results: Cache<String, CachedLogs>,
+}
+
+impl QueryCache {
+    pub fn new(max_entries: u64, ttl: Duration) -> Self {
+        Self {
+            results: Cache::builder().max_capacity(max_entries).time_to_live(ttl).build(),
+        }
+    }
+
+    /// Returns the cached result for `key`, or runs `load` and caches it. Concurrent
+    /// misses on one key share a single load; failed loads are not cached.
+    /// `endpoint` labels the hit/miss metric.
+    pub async fn get_or_load<F>(&self, endpoint: &str, key: String, load: F) -> Result<CachedLogs, Arc<sqlx::Error>>
+    where
+        F: Future<Output = Result<Vec<LogEntry>, sqlx::Error>>,
+    {
+        let entry = self
+            .results
+            .entry(key)
+            .or_try_insert_with(async { load.await.map(Arc::new) })
+            .await?;
+        let outcome = if entry.is_fresh() { "miss" } else { "hit" };
+        QUERY_CACHE_REQUESTS.with_label_values(&[endpoint, outcome]).inc();
+        Ok(entry.into_value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn entry(message: &str) -> LogEntry {
+        serde_json::from_value(serde_json::json!({
+            "level": "info", "message": message, "timestamp": "2024-01-01T00:00:00Z", "service": "web"
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_hit_skips_load_and_expiry_refreshes() {
+        let cache = QueryCache::new(100, Duration::from_millis(200));
+        let loads = AtomicUsize::new(0);
+        let load = |message: &'static str| {
+            let loads = &loads;
+            async move {
+                loads.fetch_add(1, Ordering::SeqCst);
+                Ok(vec![entry(message)])
+            }
+        };
+
+        let first = cache.get_or_load("logs", "limit=10".to_string(), load("first")).await.unwrap();
+        let second = cache.get_or_load("logs", "limit=10".to_string(), load("second")).await.unwrap();
+        assert_eq!(loads.load(Ordering::SeqCst), 1, "second call is served from memory");
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(second[0].message, "first");
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let refreshed = cache.get_or_load("logs", "limit=10".to_string(), load("third")).await.unwrap();
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+        assert_eq!(refreshed[0].message, "third");
+    }
+
+    #[tokio::test]
+    async fn test_failed_load_is_not_cached() {
+        let cache = QueryCache::new(100, Duration::from_secs(60));
+        let failed = cache
+            .get_or_load("logs", "k".to_string(), async { Err(sqlx::Error::PoolTimedOut) })
+            .await;
+        assert!(failed.is_err());
+
+        let loaded = cache
+            .get_or_load("logs", "k".to_string(), async { Ok(vec![entry("ok")]) })
+            .await
+            .unwrap();
+        assert_eq!(loaded[0].message, "ok");
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Caps how many read queries (the `/logs` endpoints, stats, exports) may hold pool
+/// connections at once, so however heavy the query load, the rest of the pool is left
+/// to the insert path. Cheap to clone; clones share the cap.
+#[derive(Clone)]
+pub struct ReadLimiter {
+    /// `None` leaves reads unlimited.
+    permits: Option<Arc<Semaphore>>,
+    wait: Duration,
+}
+
+/// One read query's slot, released on drop.
+pub struct ReadPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl ReadLimiter {
+    pub fn new(max_concurrent: Option<usize>, wait: Duration) -> Self {
+        Self {
+            permits: max_concurrent.map(|max| Arc::new(Semaphore::new(max))),
+            wait,
+        }
+    }
+
+    /// Waits up to the configured time for a free slot; `None` means reads are saturated.
+    pub async fn acquire(&self) -> Option<ReadPermit> {
+        let Some(permits) = self.permits.clone() else {
+            return Some(ReadPermit { _permit: None });
+        };
+        match tokio::time::timeout(self.wait, permits.acquire_owned()).await {
+            Ok(Ok(permit)) => Some(ReadPermit { _permit: Some(permit) }),
+            // Timed out; the semaphore is never closed.
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_permits_are_capped_and_released() {
+        let limiter = ReadLimiter::new(Some(2), Duration::from_millis(20));
+        let first = limiter.acquire().await.unwrap();
+        let _second = limiter.acquire().await.unwrap();
+        assert!(limiter.acquire().await.is_none());
+        drop(first);
+        assert!(limiter.acquire().await.is_some());
+
+        let unlimited = ReadLimiter::new(None, Duration::ZERO);
+        let held: Vec<_> = (0..100).map(|_| unlimited.clone()).collect();
+        for limiter in &held {
+            assert!(limiter.acquire().await.is_some());
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use sqlx::{Pool, Postgres};
+use std::{sync::Arc, time::Duration};
+use tracing::{error, info};
+
+use super::postgres;
+use crate::pkg::config::RetentionConfig;
+use crate::pkg::time;
+
+/// Expires logs older than `config.max_age_days` every `config.interval_secs`. With
+/// soft delete on, expired rows are only marked, and rows marked longer than the grace
+/// period are purged in the same pass, as are normalized devices no log references.
+pub async fn run(pool: Arc<Pool<Postgres>>, config: RetentionConfig) {
+    let Some(max_age_days) = config.max_age_days else {
+        return;
+    };
+    let mut ticker = tokio::time::interval(Duration::from_secs(config.interval_secs));
+    loop {
+        ticker.tick().await;
+        let cutoff = time::to_storage_string(chrono::Utc::now() - chrono::Duration::days(max_age_days as i64));
+        match postgres::expire_logs_before(&pool, &cutoff, config.soft_delete).await {
+            Ok(0) => {}
+            Ok(expired) if config.soft_delete => info!("Soft-deleted {} logs older than {}", expired, cutoff),
+            Ok(expired) => info!("Deleted {} logs older than {}", expired, cutoff),
+            Err(e) => error!("Retention pass failed: {:?}", e),
+        }
+        if config.soft_delete {
+            let grace = Duration::from_secs(config.purge_grace_hours * 3600);
+            match postgres::purge_deleted(&pool, grace).await {
+                Ok(0) => {}
+                Ok(purged) => info!("Purged {} soft-deleted logs", purged),
+                Err(e) => error!("Purging soft-deleted logs failed: {:?}", e),
+            }
+        }
+        match postgres::prune_orphaned_devices(&pool).await {
+            Ok(0) => {}
+            Ok(pruned) => info!("Pruned {} devices no log references", pruned),
+            Err(e) => error!("Pruning orphaned devices failed: {:?}", e),
+        }
+    }
+}
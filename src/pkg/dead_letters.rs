@@ -0,0 +1,45 @@
+use crate::models::LogEntry;
+use std::path::PathBuf;
+
+/// Keeps batches the processor couldn't write as one JSON file per batch in a directory,
+/// named `<UTC time>-<uuid>.json` so a listing sorts oldest first. Unlike a table, this
+/// still works while the database is down.
+pub struct DirectoryDeadLetters {
+    dir: PathBuf,
+}
+
+impl DirectoryDeadLetters {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub async fn store(&self, error: &str, batch: &[LogEntry]) -> std::io::Result<()> {
+        let body = serde_json::to_vec(&serde_json::json!({ "error": error, "entries": batch }))?;
+        let name = format!("{}-{}.json", chrono::Utc::now().format("%Y%m%dT%H%M%S%.6fZ"), uuid::Uuid::new_v4());
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(self.dir.join(name), body).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkg::db::postgres::tests::sample_entry;
+
+    #[tokio::test]
+    async fn test_each_batch_gets_its_own_file() {
+        let dir = std::env::temp_dir().join(format!("dead-letters-{}", uuid::Uuid::new_v4()));
+        let dead_letters = DirectoryDeadLetters::new(&dir);
+        let batch = [sample_entry("web", "e-1", "2024-05-01T10:00:00Z"), sample_entry("web", "e-2", "2024-05-01T10:00:01Z")];
+        dead_letters.store("pool timed out", &batch).await.unwrap();
+        dead_letters.store("pool timed out", &batch[1..]).await.unwrap();
+
+        let mut files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        files.sort();
+        assert_eq!(files.len(), 2);
+        let first: serde_json::Value = serde_json::from_slice(&std::fs::read(&files[0]).unwrap()).unwrap();
+        assert_eq!(first["error"], "pool timed out");
+        assert_eq!(first["entries"][1]["id"], "e-2");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
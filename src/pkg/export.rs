@@ -0,0 +1,254 @@
+use actix_web::web::Bytes;
+use flate2::{write::GzEncoder, Compression};
+use futures::{Stream, StreamExt};
+use sqlx::{Pool, Postgres};
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+use tracing::error;
+
+use crate::models::LogEntry;
+use crate::pkg::db::index_advisor::IndexAdvisor;
+use crate::pkg::db::postgres::{self, LogFilter};
+use crate::pkg::db::read_limit::ReadPermit;
+use crate::pkg::utils::sliding_window::SlidingWindow;
+
+/// Compressed bytes are sent on once this many have accumulated.
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Caps exports per service within a sliding window, so one team exporting repeatedly
+/// can't starve the database for everyone else.
+pub struct ExportLimiter {
+    window: Duration,
+    limit: usize,
+    services: Mutex<HashMap<String, Arc<Mutex<SlidingWindow>>>>,
+}
+
+impl ExportLimiter {
+    pub fn new(window: Duration, limit: usize) -> Self {
+        Self {
+            window,
+            limit,
+            services: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts an export for `service`, or returns how long until one is allowed.
+    pub fn try_start(&self, service: &str) -> Result<(), Duration> {
+        let window = self
+            .services
+            .lock()
+            .unwrap()
+            .entry(service.to_string())
+            .or_insert_with(|| SlidingWindow::new(self.window, self.limit))
+            .clone();
+        let mut window = window.lock().unwrap();
+        if window.take_available(1) {
+            Ok(())
+        } else {
+            Err(window.retry_after(1))
+        }
+    }
+}
+
+/// Streams the logs matching `filter`, oldest first, as gzipped NDJSON, holding `permit`
+/// until the query is done. A database error mid-export ends the stream with an error,
+/// leaving the gzip visibly truncated.
+pub fn gzipped_ndjson(
+    pool: Arc<Pool<Postgres>>,
+    filter: LogFilter,
+    permit: ReadPermit,
+) -> impl Stream<Item = Result<Bytes, std::io::Error>> {
+    let (entry_tx, mut entry_rx) = mpsc::channel(256);
+    let (chunk_tx, chunk_rx) = mpsc::channel(8);
+    let query = tokio::spawn(async move {
+        let _permit = permit;
+        postgres::export_logs(&pool, &filter, entry_tx).await
+    });
+
+    tokio::spawn(async move {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        while let Some(entry) = entry_rx.recv().await {
+            let written = serde_json::to_writer(&mut encoder, &entry)
+                .map_err(std::io::Error::from)
+                .and_then(|()| encoder.write_all(b"\n"));
+            if let Err(e) = written {
+                let _ = chunk_tx.send(Err(e)).await;
+                return;
+            }
+            if encoder.get_ref().len() >= CHUNK_BYTES {
+                let chunk = std::mem::take(encoder.get_mut());
+                if chunk_tx.send(Ok(Bytes::from(chunk))).await.is_err() {
+                    // The client went away; dropping `entry_rx` stops the query.
+                    return;
+                }
+            }
+        }
+        let outcome = match query.await {
+            Ok(Ok(())) => encoder.finish().map(Bytes::from),
+            Ok(Err(e)) => {
+                error!("Log export failed: {:?}", e);
+                Err(std::io::Error::other(e))
+            }
+            Err(e) => Err(std::io::Error::other(e)),
+        };
+        let _ = chunk_tx.send(outcome).await;
+    });
+
+    futures::stream::unfold(chunk_rx, |mut rx| async move { rx.recv().await.map(|chunk| (chunk, rx)) })
+}
+
+/// Runs the `/logs` query for `filter` in the background, holding `permit` until it is
+/// done, and returns its rows as a JSON array streamed from the database cursor, so
+/// memory stays bounded whatever the result size. Waits for the first row so that a
+/// query failing outright can still be answered with an error status; a failure after
+/// that ends the stream with an error, leaving the array visibly unterminated.
+pub async fn json_array_logs(
+    pool: Arc<Pool<Postgres>>,
+    filter: LogFilter,
+    permit: ReadPermit,
+    advisor: Option<Arc<IndexAdvisor>>,
+) -> Result<impl Stream<Item = Result<Bytes, std::io::Error>>, sqlx::Error> {
+    let (entry_tx, mut entry_rx) = mpsc::channel(256);
+    let query = tokio::spawn(async move {
+        let _permit = permit;
+        let started = Instant::now();
+        let result = postgres::stream_query_logs(&pool, &filter, entry_tx).await;
+        if let Some(advisor) = advisor {
+            advisor.record(&filter.filter_columns(), started.elapsed());
+        }
+        result
+    });
+
+    let first = entry_rx.recv().await;
+    let query = match first {
+        Some(_) => Some(query),
+        None => {
+            query.await.map_err(|e| sqlx::Error::Io(std::io::Error::other(e)))??;
+            None
+        }
+    };
+    let entries = futures::stream::unfold((first, entry_rx, query), |(first, mut rx, query)| async move {
+        if let Some(entry) = first {
+            return Some((Ok(entry), (None, rx, query)));
+        }
+        if let Some(entry) = rx.recv().await {
+            return Some((Ok(entry), (None, rx, query)));
+        }
+        // The sender is gone, so the query has finished.
+        let error = match query?.await {
+            Ok(Ok(())) => return None,
+            Ok(Err(e)) => {
+                error!("Streaming logs query failed: {:?}", e);
+                std::io::Error::other(e)
+            }
+            Err(e) => std::io::Error::other(e),
+        };
+        Some((Err(error), (None, rx, None)))
+    });
+    Ok(json_array(entries))
+}
+
+/// Serializes `entries` as one JSON array, sent on in chunks of about `CHUNK_BYTES`.
+/// An error from `entries` ends the stream with that error and no closing `]`.
+pub fn json_array<S>(entries: S) -> impl Stream<Item = Result<Bytes, std::io::Error>>
+where
+    S: Stream<Item = Result<LogEntry, std::io::Error>>,
+{
+    struct State<S> {
+        entries: std::pin::Pin<Box<S>>,
+        buffer: Vec<u8>,
+        empty: bool,
+        done: bool,
+    }
+
+    let state = State {
+        entries: Box::pin(entries),
+        buffer: b"[".to_vec(),
+        empty: true,
+        done: false,
+    };
+    futures::stream::unfold(state, |mut state| async move {
+        if state.done {
+            return None;
+        }
+        loop {
+            let entry = match state.entries.next().await {
+                Some(Ok(entry)) => entry,
+                Some(Err(e)) => {
+                    state.done = true;
+                    return Some((Err(e), state));
+                }
+                None => {
+                    state.buffer.push(b']');
+                    state.done = true;
+                    let chunk = std::mem::take(&mut state.buffer);
+                    return Some((Ok(Bytes::from(chunk)), state));
+                }
+            };
+            if !state.empty {
+                state.buffer.push(b',');
+            }
+            state.empty = false;
+            if let Err(e) = serde_json::to_writer(&mut state.buffer, &entry) {
+                state.done = true;
+                return Some((Err(e.into()), state));
+            }
+            if state.buffer.len() >= CHUNK_BYTES {
+                let chunk = std::mem::take(&mut state.buffer);
+                return Some((Ok(Bytes::from(chunk)), state));
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_json_array_streams_large_results_in_chunks() {
+        use crate::pkg::db::postgres::tests::sample_entry;
+
+        // Entries are made as they are pulled, never held together.
+        let entries = futures::stream::iter(0..10_000)
+            .map(|n| Ok(sample_entry("web", &format!("e-{}", n), "2024-05-01T10:00:00.000000Z")));
+        let mut chunks = Box::pin(json_array(entries));
+        let mut body = Vec::new();
+        let mut largest = 0;
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk.unwrap();
+            largest = largest.max(chunk.len());
+            body.extend_from_slice(&chunk);
+        }
+        assert!(largest < 2 * CHUNK_BYTES, "a chunk of {} bytes", largest);
+        assert!(body.len() > 10 * CHUNK_BYTES);
+        let parsed: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(parsed.len(), 10_000);
+        assert_eq!(parsed[9_999]["id"], "e-9999");
+
+        let empty: Vec<Bytes> = json_array(futures::stream::empty()).map(Result::unwrap).collect().await;
+        assert_eq!(empty.concat(), b"[]");
+
+        let failing = futures::stream::iter([
+            Ok(sample_entry("web", "e-1", "2024-05-01T10:00:00.000000Z")),
+            Err(std::io::Error::other("connection reset")),
+        ]);
+        let chunks: Vec<_> = json_array(failing).collect().await;
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].is_err());
+    }
+
+    #[test]
+    fn test_limits_each_service_separately() {
+        let limiter = ExportLimiter::new(Duration::from_secs(60), 1);
+        assert!(limiter.try_start("checkout").is_ok());
+        let retry_after = limiter.try_start("checkout").unwrap_err();
+        assert!(retry_after > Duration::from_secs(55));
+        assert!(limiter.try_start("search").is_ok(), "another service is unaffected");
+    }
+}
@@ -0,0 +1,128 @@
+use crate::pkg::config::{IdFormat, IdValidationConfig};
+use chrono::{DateTime, Utc};
+use ulid::Ulid;
+use uuid::Uuid;
+
+/// Namespace of the UUIDv5s [`for_content`] derives.
+const CONTENT_NAMESPACE: Uuid = Uuid::from_u128(0x6a1f_93c2_5e0d_4b7a_9c38_d2e4_17f0_8b65);
+
+/// The id of an entry sent without one, derived from its content hash so a retry of the
+/// entry gets the id the original was stored under: a UUIDv5 of the hash.
+///
+/// With a `region_id` it is instead `<ULID>-<region_id>`, the ULID taking its time from
+/// the entry's `timestamp` and the rest from the hash. The ULID comes first so ids sort
+/// lexically by time across regions once their databases are merged; the region suffix
+/// keeps two regions from ever deriving the same id.
+pub fn for_content(content_hash: &str, region_id: Option<&str>, timestamp: Option<DateTime<Utc>>) -> String {
+    let id = Uuid::new_v5(&CONTENT_NAMESPACE, content_hash.as_bytes());
+    match region_id {
+        Some(region_id) => {
+            let millis = timestamp.map_or(0, |timestamp| timestamp.timestamp_millis().max(0) as u64);
+            format!("{}-{}", Ulid::from_parts(millis, id.as_u128()), region_id)
+        }
+        None => id.to_string(),
+    }
+}
+
+/// Region ids are embedded in every id, so keep them short and URL/SQL friendly.
+pub fn validate_region_id(region_id: &str) -> Result<(), String> {
+    let valid = !region_id.is_empty()
+        && region_id.len() <= 16
+        && region_id.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit());
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "REGION_ID '{}' must be 1-16 lowercase letters or digits",
+            region_id
+        ))
+    }
+}
+
+/// Checks a client-supplied id against `config`, describing the problem on failure.
+pub fn check(id: &str, config: &IdValidationConfig) -> Result<(), String> {
+    if id.is_empty() {
+        return Err("id is empty".to_string());
+    }
+    if id.len() > config.max_length {
+        return Err(format!("id is longer than {} bytes", config.max_length));
+    }
+    let valid = match &config.format {
+        IdFormat::Any => true,
+        IdFormat::Uuid => uuid::Uuid::parse_str(id).is_ok(),
+        IdFormat::Pattern(pattern) => pattern.is_match(id),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("id {:?} has the wrong format", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_region_ids_are_unique_and_time_sortable() {
+        let start: DateTime<Utc> = "2024-05-01T10:00:00Z".parse().unwrap();
+        let mut ids = Vec::new();
+        for round in 0..200 {
+            let timestamp = Some(start + chrono::Duration::milliseconds(round));
+            ids.push(for_content(&format!("hash-{}", round), Some("euw1"), timestamp));
+            ids.push(for_content(&format!("hash-{}", round), Some("use1"), timestamp));
+        }
+
+        let distinct: HashSet<&String> = ids.iter().collect();
+        assert_eq!(distinct.len(), ids.len());
+
+        let mut sorted = ids.clone();
+        sorted.sort();
+        assert_eq!(sorted, ids, "lexical order must match entry time");
+    }
+
+    #[test]
+    fn test_ids_derive_from_content() {
+        let timestamp = "2024-05-01T10:00:00Z".parse().ok();
+        assert_eq!(for_content("abc", None, timestamp), for_content("abc", None, None));
+        assert_ne!(for_content("abc", None, None), for_content("abd", None, None));
+        assert!(Uuid::parse_str(&for_content("abc", None, None)).is_ok());
+        assert_eq!(for_content("abc", Some("euw1"), timestamp), for_content("abc", Some("euw1"), timestamp));
+    }
+
+    #[test]
+    fn test_check_id() {
+        let uuids = IdValidationConfig {
+            enabled: true,
+            format: IdFormat::Uuid,
+            ..Default::default()
+        };
+        assert!(check("4b7c2a52-0000-4000-8000-000000000001", &uuids).is_ok());
+        assert!(check("", &uuids).is_err());
+        assert!(check("'; DROP TABLE logs; --", &uuids).is_err());
+
+        let any = IdValidationConfig {
+            enabled: true,
+            max_length: 8,
+            ..Default::default()
+        };
+        assert!(check("evt-1", &any).is_ok());
+        assert!(check("evt-123456", &any).is_err());
+
+        let pattern = IdValidationConfig {
+            enabled: true,
+            format: IdFormat::Pattern(regex::Regex::new("^(?:evt-[0-9]+)$").unwrap()),
+            ..Default::default()
+        };
+        assert!(check("evt-42", &pattern).is_ok());
+        assert!(check("evt-42x", &pattern).is_err());
+    }
+
+    #[test]
+    fn test_validate_region_id() {
+        assert!(validate_region_id("euw1").is_ok());
+        assert!(validate_region_id("").is_err());
+        assert!(validate_region_id("EU-West").is_err());
+    }
+}
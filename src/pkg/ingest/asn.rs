@@ -0,0 +1,47 @@
+use std::net::IpAddr;
+
+/// The network an address belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsnInfo {
+    pub asn: u32,
+    pub org: Option<String>,
+}
+
+/// Resolves client IPs to their autonomous system.
+pub trait AsnLookup: Send + Sync {
+    fn lookup(&self, ip: IpAddr) -> Option<AsnInfo>;
+}
+
+/// Lookups against a MaxMind GeoLite2/GeoIP2 ASN database, loaded into memory once.
+pub struct MaxMindAsn {
+    reader: maxminddb::Reader<Vec<u8>>,
+}
+
+impl MaxMindAsn {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let reader = maxminddb::Reader::open_readfile(path)
+            .map_err(|e| format!("cannot open ASN database {}: {}", path, e))?;
+        Ok(Self { reader })
+    }
+}
+
+impl AsnLookup for MaxMindAsn {
+    fn lookup(&self, ip: IpAddr) -> Option<AsnInfo> {
+        let record: maxminddb::geoip2::Asn = self.reader.lookup(ip).ok()?;
+        Some(AsnInfo {
+            asn: record.autonomous_system_number?,
+            org: record.autonomous_system_organization.map(str::to_string),
+        })
+    }
+}
+
+/// Fixed answers for tests.
+#[cfg(test)]
+pub struct StaticAsn(pub std::collections::HashMap<IpAddr, AsnInfo>);
+
+#[cfg(test)]
+impl AsnLookup for StaticAsn {
+    fn lookup(&self, ip: IpAddr) -> Option<AsnInfo> {
+        self.0.get(&ip).cloned()
+    }
+}
@@ -0,0 +1,27 @@
+/// Suggested client backoff for a queue holding `depth` of `capacity` batches: zero
+/// until the fill ratio reaches `start_ratio`, then rising linearly to `max_backoff_ms`
+/// when the queue is full.
+pub fn suggested_backoff_ms(depth: usize, capacity: usize, start_ratio: f64, max_backoff_ms: u64) -> u64 {
+    if capacity == 0 {
+        return max_backoff_ms;
+    }
+    let fill = depth as f64 / capacity as f64;
+    if fill < start_ratio || start_ratio >= 1.0 {
+        return 0;
+    }
+    let scaled = (fill - start_ratio) / (1.0 - start_ratio);
+    (scaled.min(1.0) * max_backoff_ms as f64).round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_scales_with_fill_ratio() {
+        assert_eq!(suggested_backoff_ms(0, 100, 0.5, 4000), 0);
+        assert_eq!(suggested_backoff_ms(49, 100, 0.5, 4000), 0);
+        assert_eq!(suggested_backoff_ms(75, 100, 0.5, 4000), 2000);
+        assert_eq!(suggested_backoff_ms(100, 100, 0.5, 4000), 4000);
+    }
+}
@@ -0,0 +1,49 @@
+use crate::models::LogEntry;
+use regex::RegexSet;
+
+/// True when the entry's `device.user_agent` matches any bot pattern.
+/// Entries without device information are never treated as bots.
+pub fn is_bot(entry: &LogEntry, patterns: &RegexSet) -> bool {
+    entry
+        .device
+        .as_ref()
+        .and_then(|device| device.user_agent.as_deref())
+        .is_some_and(|user_agent| patterns.is_match(user_agent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(device: serde_json::Value) -> LogEntry {
+        let mut value = json!({
+            "level": "info",
+            "message": "page view",
+            "timestamp": "2024-01-01T00:00:00Z",
+            "service": "web"
+        });
+        if !device.is_null() {
+            value["device"] = device;
+        }
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_bot_user_agents_are_detected() {
+        let patterns = RegexSet::new([r"(?i)bot\b", r"(?i)crawler"]).unwrap();
+
+        let googlebot = entry(json!({
+            "userAgent": "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)"
+        }));
+        assert!(is_bot(&googlebot, &patterns));
+
+        let firefox = entry(json!({
+            "userAgent": "Mozilla/5.0 (X11; Linux x86_64; rv:125.0) Gecko/20100101 Firefox/125.0"
+        }));
+        assert!(!is_bot(&firefox, &patterns));
+
+        assert!(!is_bot(&entry(serde_json::Value::Null), &patterns));
+        assert!(!is_bot(&entry(json!({ "osName": "iOS" })), &patterns));
+    }
+}
@@ -0,0 +1,151 @@
+use jsonschema::Validator;
+use serde_json::{json, Value};
+use std::{collections::HashMap, fs, sync::Arc};
+
+use crate::models::{BreadcrumbType, LogEntry};
+use crate::pkg::config::BreadcrumbPolicy;
+use crate::pkg::ingest::schema;
+
+/// Compiled JSON Schemas for breadcrumb `data`, keyed by breadcrumb type. Types
+/// without an entry accept any data.
+pub type BreadcrumbSchemas = HashMap<BreadcrumbType, Arc<Validator>>;
+
+/// Built-in shapes for the types whose data the UI relies on.
+pub fn default_schemas() -> BreadcrumbSchemas {
+    let xhr = json!({
+        "type": "object",
+        "required": ["url", "method"],
+        "properties": {
+            "url": { "type": "string" },
+            "method": { "type": "string" },
+            "status_code": { "type": "integer" }
+        }
+    });
+    let navigation = json!({
+        "type": "object",
+        "required": ["to"],
+        "properties": {
+            "from": { "type": "string" },
+            "to": { "type": "string" }
+        }
+    });
+    [(BreadcrumbType::Xhr, xhr), (BreadcrumbType::Navigation, navigation)]
+        .into_iter()
+        .map(|(kind, schema)| {
+            let validator = schema::compile(&schema, kind.as_str()).expect("built-in breadcrumb schema compiles");
+            (kind, Arc::new(validator))
+        })
+        .collect()
+}
+
+/// The built-in schemas, with `type=path/to/schema.json` pairs replacing or adding to them.
+pub fn load(specs: &[String]) -> Result<BreadcrumbSchemas, String> {
+    let mut schemas = default_schemas();
+    for spec in specs {
+        let (kind, path) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("BREADCRUMB_SCHEMAS entry '{}' is not type=path", spec))?;
+        let kind = BreadcrumbType::parse(kind.trim())
+            .ok_or_else(|| format!("BREADCRUMB_SCHEMAS has unknown breadcrumb type '{}'", kind))?;
+        let raw = fs::read_to_string(path.trim())
+            .map_err(|e| format!("cannot read breadcrumb schema for '{}' from {}: {}", kind.as_str(), path, e))?;
+        let schema: Value = serde_json::from_str(&raw)
+            .map_err(|e| format!("breadcrumb schema for '{}' is not valid JSON: {}", kind.as_str(), e))?;
+        schemas.insert(kind, Arc::new(schema::compile(&schema, kind.as_str())?));
+    }
+    Ok(schemas)
+}
+
+/// Checks each breadcrumb's `data` against the schema for its type. Malformed ones are
+/// listed in `context.invalid_breadcrumbs` (by original index) and, under
+/// [`BreadcrumbPolicy::Drop`], removed; the entry itself is always kept.
+///
+/// Returns how many breadcrumbs were malformed.
+pub fn apply(entry: &mut LogEntry, schemas: &BreadcrumbSchemas, policy: BreadcrumbPolicy) -> usize {
+    let Some(breadcrumbs) = entry.breadcrumbs.as_mut() else {
+        return 0;
+    };
+    let mut invalid = Vec::new();
+    for (index, breadcrumb) in breadcrumbs.iter().enumerate() {
+        let Some(validator) = schemas.get(&breadcrumb.breadcrumb_type) else {
+            continue;
+        };
+        let data = breadcrumb.data.clone().unwrap_or(Value::Null);
+        if let Err(e) = validator.validate(&data) {
+            invalid.push(json!({
+                "index": index,
+                "type": breadcrumb.breadcrumb_type.as_str(),
+                "error": e.to_string(),
+            }));
+        }
+    }
+    if invalid.is_empty() {
+        return 0;
+    }
+
+    if policy == BreadcrumbPolicy::Drop {
+        let mut index = 0;
+        breadcrumbs.retain(|_| {
+            let keep = !invalid.iter().any(|flagged| flagged["index"] == index);
+            index += 1;
+            keep
+        });
+    }
+    let count = invalid.len();
+    entry
+        .context
+        .get_or_insert_with(Default::default)
+        .insert("invalid_breadcrumbs".to_string(), Value::Array(invalid));
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry_with_breadcrumbs(breadcrumbs: Value) -> LogEntry {
+        serde_json::from_value(json!({
+            "level": "error",
+            "message": "checkout failed",
+            "timestamp": "2024-01-01T00:00:00Z",
+            "service": "web",
+            "breadcrumbs": breadcrumbs
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_well_formed_xhr_breadcrumb_passes() {
+        let mut entry = entry_with_breadcrumbs(json!([
+            { "timestamp": "2024-01-01T00:00:00Z", "type": "xhr", "message": "pay",
+              "data": { "url": "/api/pay", "method": "POST", "status_code": 502 } },
+            { "timestamp": "2024-01-01T00:00:01Z", "type": "click", "message": "button" }
+        ]));
+
+        assert_eq!(apply(&mut entry, &default_schemas(), BreadcrumbPolicy::Flag), 0);
+        assert!(entry.context.is_none());
+        assert_eq!(entry.breadcrumbs.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_xhr_breadcrumb_missing_fields_is_flagged_or_dropped() {
+        let breadcrumbs = json!([
+            { "timestamp": "2024-01-01T00:00:00Z", "type": "xhr", "message": "pay", "data": { "url": "/api/pay" } },
+            { "timestamp": "2024-01-01T00:00:01Z", "type": "click", "message": "button" }
+        ]);
+
+        let mut flagged = entry_with_breadcrumbs(breadcrumbs.clone());
+        assert_eq!(apply(&mut flagged, &default_schemas(), BreadcrumbPolicy::Flag), 1);
+        let invalid = &flagged.context.as_ref().unwrap()["invalid_breadcrumbs"];
+        assert_eq!(invalid[0]["index"], 0);
+        assert_eq!(invalid[0]["type"], "xhr");
+        assert!(invalid[0]["error"].as_str().unwrap().contains("method"));
+        assert_eq!(flagged.breadcrumbs.as_ref().unwrap().len(), 2);
+
+        let mut dropped = entry_with_breadcrumbs(breadcrumbs);
+        assert_eq!(apply(&mut dropped, &default_schemas(), BreadcrumbPolicy::Drop), 1);
+        let remaining = dropped.breadcrumbs.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].breadcrumb_type, BreadcrumbType::Click);
+    }
+}
@@ -0,0 +1,128 @@
+use std::io::Read;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Whether `body` opens with a zlib header (what HTTP calls `deflate`): a 32K-window
+/// deflate method byte, and a check byte making the pair a multiple of 31.
+fn is_zlib(body: &[u8]) -> bool {
+    match body {
+        [0x78, flags, ..] => (0x7800 | u16::from(*flags)) % 31 == 0,
+        _ => false,
+    }
+}
+
+/// How an ingest body is encoded, judged from its leading bytes rather than headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyFormat {
+    Gzip,
+    Deflate,
+    Zstd,
+    /// Anything else, including raw JSON (`[` / `{`), is parsed as-is.
+    Plain,
+}
+
+impl BodyFormat {
+    pub fn detect(body: &[u8]) -> Self {
+        if body.starts_with(GZIP_MAGIC) {
+            BodyFormat::Gzip
+        } else if is_zlib(body) {
+            BodyFormat::Deflate
+        } else if body.starts_with(ZSTD_MAGIC) {
+            BodyFormat::Zstd
+        } else {
+            BodyFormat::Plain
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The body is larger than the limit once decompressed.
+    TooLarge { limit: usize },
+    Corrupt(String),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::TooLarge { limit } => write!(f, "Decompressed body exceeds {} bytes", limit),
+            DecodeError::Corrupt(e) => write!(f, "Could not decompress body: {}", e),
+        }
+    }
+}
+
+/// Decompresses `body` according to its detected format. The result is held to
+/// `limit` bytes whatever the format, so a small compressed body can't expand past it.
+pub fn decode(body: Vec<u8>, limit: usize) -> Result<Vec<u8>, DecodeError> {
+    match BodyFormat::detect(&body) {
+        BodyFormat::Gzip => read_limited(flate2::read::MultiGzDecoder::new(body.as_slice()), limit),
+        BodyFormat::Deflate => read_limited(flate2::read::ZlibDecoder::new(body.as_slice()), limit),
+        BodyFormat::Zstd => {
+            let decoder = zstd::stream::read::Decoder::new(body.as_slice()).map_err(|e| DecodeError::Corrupt(e.to_string()))?;
+            read_limited(decoder, limit)
+        }
+        BodyFormat::Plain if body.len() > limit => Err(DecodeError::TooLarge { limit }),
+        BodyFormat::Plain => Ok(body),
+    }
+}
+
+fn read_limited(reader: impl Read, limit: usize) -> Result<Vec<u8>, DecodeError> {
+    let mut decoded = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|e| DecodeError::Corrupt(e.to_string()))?;
+    if decoded.len() > limit {
+        return Err(DecodeError::TooLarge { limit });
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::io::Write;
+
+    pub fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    pub fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    pub fn zstd(data: &[u8]) -> Vec<u8> {
+        zstd::stream::encode_all(data, 0).unwrap()
+    }
+
+    #[test]
+    fn test_formats_are_detected_by_magic_bytes() {
+        let json = br#"[{"message":"hi"}]"#;
+        assert_eq!(BodyFormat::detect(&gzip(json)), BodyFormat::Gzip);
+        assert_eq!(BodyFormat::detect(&deflate(json)), BodyFormat::Deflate);
+        assert_eq!(BodyFormat::detect(&zstd(json)), BodyFormat::Zstd);
+        assert_eq!(BodyFormat::detect(json), BodyFormat::Plain);
+
+        assert_eq!(decode(gzip(json), 1024).unwrap(), json);
+        assert_eq!(decode(deflate(json), 1024).unwrap(), json);
+        assert_eq!(decode(zstd(json), 1024).unwrap(), json);
+        assert_eq!(decode(json.to_vec(), 1024).unwrap(), json);
+    }
+
+    #[test]
+    fn test_decompressed_size_is_limited() {
+        let bomb = vec![b' '; 64 * 1024];
+        assert_eq!(decode(gzip(&bomb), 1024), Err(DecodeError::TooLarge { limit: 1024 }));
+        assert_eq!(decode(deflate(&bomb), 1024), Err(DecodeError::TooLarge { limit: 1024 }));
+        assert_eq!(decode(zstd(&bomb), 1024), Err(DecodeError::TooLarge { limit: 1024 }));
+        assert_eq!(decode(bomb, 1024), Err(DecodeError::TooLarge { limit: 1024 }));
+
+        let truncated = gzip(b"[]")[..6].to_vec();
+        assert!(matches!(decode(truncated, 1024), Err(DecodeError::Corrupt(_))));
+    }
+}
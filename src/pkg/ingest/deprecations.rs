@@ -0,0 +1,133 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::models::LogEntry;
+use crate::pkg::metrics::services::ServiceMetrics;
+use crate::pkg::metrics::DEPRECATED_FIELD_USES;
+
+/// A payload field clients should stop sending, named as in the JSON payload. Nested
+/// fields use dots, e.g. `context.sessionId`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct DeprecatedField {
+    pub field: String,
+    pub replacement: Option<String>,
+}
+
+impl DeprecatedField {
+    /// Parses `field` or `field=replacement`.
+    pub fn parse(item: &str) -> Option<Self> {
+        let (field, replacement) = match item.split_once('=') {
+            Some((field, replacement)) => (field.trim(), Some(replacement.trim()).filter(|r| !r.is_empty())),
+            None => (item.trim(), None),
+        };
+        (!field.is_empty()).then(|| Self {
+            field: field.to_string(),
+            replacement: replacement.map(str::to_string),
+        })
+    }
+}
+
+/// One deprecated field a request used, as reported back to the client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeprecationWarning {
+    pub field: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<String>,
+    pub message: String,
+}
+
+impl DeprecationWarning {
+    /// The value of a `Warning` header: code 299 ("miscellaneous persistent warning").
+    pub fn header_value(&self) -> String {
+        format!("299 - \"{}\"", self.message.replace('"', "'"))
+    }
+}
+
+/// Returns the deprecated fields used by any of `entries`, each once, in configuration
+/// order, counting every use per service.
+pub fn scan(entries: &[LogEntry], deprecated: &[DeprecatedField], labels: &ServiceMetrics) -> Vec<DeprecationWarning> {
+    if deprecated.is_empty() {
+        return Vec::new();
+    }
+    let mut used = vec![false; deprecated.len()];
+    for entry in entries {
+        let Ok(value) = serde_json::to_value(entry) else {
+            continue;
+        };
+        for (index, field) in deprecated.iter().enumerate() {
+            if is_present(&value, &field.field) {
+                used[index] = true;
+                DEPRECATED_FIELD_USES
+                    .with_label_values(&[&labels.label(&entry.service), &field.field])
+                    .inc();
+            }
+        }
+    }
+    deprecated
+        .iter()
+        .zip(used)
+        .filter(|(_, used)| *used)
+        .map(|(field, _)| DeprecationWarning {
+            field: field.field.clone(),
+            replacement: field.replacement.clone(),
+            message: match &field.replacement {
+                Some(replacement) => format!("Field '{}' is deprecated; use '{}' instead", field.field, replacement),
+                None => format!("Field '{}' is deprecated", field.field),
+            },
+        })
+        .collect()
+}
+
+/// Whether the dotted `path` leads to a non-null value in `value`.
+fn is_present(value: &Value, path: &str) -> bool {
+    path.split('.')
+        .try_fold(value, |value, key| value.get(key))
+        .is_some_and(|value| !value.is_null())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(extra: Value) -> LogEntry {
+        let mut value = json!({ "level": "info", "message": "m", "timestamp": "2024-01-01T00:00:00Z", "service": "web" });
+        value.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn deprecated() -> Vec<DeprecatedField> {
+        ["userContext=user", "context.sessionId=context.session_id", "reason"]
+            .iter()
+            .map(|item| DeprecatedField::parse(item).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_used_fields_are_reported_once_each() {
+        let entries = vec![
+            entry(json!({ "context": { "sessionId": "s-1" } })),
+            entry(json!({ "userContext": { "plan": "pro" }, "context": { "sessionId": "s-2" } })),
+        ];
+        let warnings = scan(&entries, &deprecated(), &ServiceMetrics::new(10));
+        let fields: Vec<_> = warnings.iter().map(|w| w.field.as_str()).collect();
+        assert_eq!(fields, ["userContext", "context.sessionId"]);
+        assert_eq!(warnings[0].message, "Field 'userContext' is deprecated; use 'user' instead");
+        assert_eq!(warnings[0].header_value(), "299 - \"Field 'userContext' is deprecated; use 'user' instead\"");
+    }
+
+    #[test]
+    fn test_nothing_is_reported_without_deprecated_fields() {
+        let entries = vec![entry(json!({ "context": { "session_id": "s-1" }, "userContext": null }))];
+        assert!(scan(&entries, &deprecated(), &ServiceMetrics::new(10)).is_empty());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            DeprecatedField::parse(" reason "),
+            Some(DeprecatedField { field: "reason".to_string(), replacement: None })
+        );
+        assert_eq!(DeprecatedField::parse("=user"), None);
+    }
+}
@@ -0,0 +1,103 @@
+use serde_json::{json, Value};
+
+use crate::models::LogEntry;
+use crate::pkg::config::{FieldLimitsConfig, OversizePolicy};
+
+/// Enforces the per-field size limits on one entry.
+///
+/// Returns the names of truncated fields (empty if everything fit), or under
+/// [`OversizePolicy::Reject`] the first oversized field as the error.
+pub fn apply(entry: &mut LogEntry, limits: &FieldLimitsConfig) -> Result<Vec<&'static str>, &'static str> {
+    let context_bytes = entry
+        .context
+        .as_ref()
+        .map_or(0, |context| serde_json::to_vec(context).map_or(0, |bytes| bytes.len()));
+    let oversized: Vec<&'static str> = [
+        ("message", entry.message.len() > limits.message_bytes),
+        ("stack", entry.stack.as_ref().is_some_and(|stack| stack.len() > limits.stack_bytes)),
+        ("context", context_bytes > limits.context_bytes),
+    ]
+    .into_iter()
+    .filter_map(|(field, over)| over.then_some(field))
+    .collect();
+
+    if oversized.is_empty() {
+        return Ok(oversized);
+    }
+    if limits.policy == OversizePolicy::Reject {
+        return Err(oversized[0]);
+    }
+
+    truncate_to_char_boundary(&mut entry.message, limits.message_bytes);
+    if let Some(stack) = entry.stack.as_mut() {
+        truncate_to_char_boundary(stack, limits.stack_bytes);
+    }
+    let context = entry.context.get_or_insert_with(Default::default);
+    if oversized.contains(&"context") {
+        // A cut-off JSON object is useless, so drop it wholesale and record how big it was.
+        context.clear();
+        context.insert("context_original_bytes".to_string(), json!(context_bytes));
+    }
+    context.insert("truncated_fields".to_string(), Value::from(oversized.clone()));
+    Ok(oversized)
+}
+
+fn truncate_to_char_boundary(value: &mut String, max_bytes: usize) {
+    if value.len() <= max_bytes {
+        return;
+    }
+    let mut end = max_bytes;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value.truncate(end);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry_with_stack(stack_bytes: usize) -> LogEntry {
+        serde_json::from_value(json!({
+            "level": "error",
+            "message": "render failed",
+            "timestamp": "2024-01-01T00:00:00Z",
+            "service": "web",
+            "context": { "route": "/cart" },
+            // Multi-byte characters make sure truncation respects char boundaries.
+            "stack": "é".repeat(stack_bytes / 2)
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_truncate_policy_keeps_entry() {
+        let limits = FieldLimitsConfig {
+            stack_bytes: 101,
+            ..FieldLimitsConfig::default()
+        };
+        let mut entry = entry_with_stack(10_000);
+
+        assert_eq!(apply(&mut entry, &limits), Ok(vec!["stack"]));
+        assert_eq!(entry.stack.as_ref().unwrap().len(), 100);
+        assert_eq!(entry.message, "render failed");
+        let context = entry.context.unwrap();
+        assert_eq!(context["route"], "/cart");
+        assert_eq!(context["truncated_fields"], json!(["stack"]));
+    }
+
+    #[test]
+    fn test_reject_policy_drops_entry() {
+        let limits = FieldLimitsConfig {
+            stack_bytes: 100,
+            policy: OversizePolicy::Reject,
+            ..FieldLimitsConfig::default()
+        };
+        let mut entry = entry_with_stack(10_000);
+        assert_eq!(apply(&mut entry, &limits), Err("stack"));
+
+        let mut small = entry_with_stack(50);
+        assert_eq!(apply(&mut small, &limits), Ok(vec![]));
+        assert!(!small.context.unwrap().contains_key("truncated_fields"));
+    }
+}
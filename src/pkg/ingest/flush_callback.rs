@@ -0,0 +1,208 @@
+use crate::models::LogEntry;
+use crate::pkg::config::FlushCallbackConfig;
+use crate::pkg::ingest::receipts::Receipt;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{redirect, Url};
+use serde::Serialize;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Where to report a queued batch's outcome, from `X-Callback-Url` on `/ingest`.
+#[derive(Debug, Clone)]
+pub struct FlushCallback {
+    pub url: Url,
+    pub batch_id: String,
+}
+
+impl FlushCallback {
+    /// Accepts absolute `http`/`https` URLs whose host is in `config.allowed_hosts`
+    /// (any host when that is empty) and, unless `config.allow_private` is set, isn't a
+    /// private or loopback address. Hostnames are checked again once resolved.
+    pub fn new(url: &str, config: &FlushCallbackConfig) -> Result<Self, String> {
+        let url = Url::parse(url.trim()).map_err(|e| format!("Invalid X-Callback-Url: {}", e))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("X-Callback-Url must be http or https, not '{}'", url.scheme()));
+        }
+        let host = url.host_str().ok_or("X-Callback-Url has no host")?;
+        if !config.allowed_hosts.is_empty() && !config.allowed_hosts.iter().any(|allowed| allowed.eq_ignore_ascii_case(host)) {
+            return Err(format!("X-Callback-Url host '{}' is not allowed", host));
+        }
+        let literal = match url.host() {
+            Some(url::Host::Ipv4(ip)) => Some(IpAddr::V4(ip)),
+            Some(url::Host::Ipv6(ip)) => Some(IpAddr::V6(ip)),
+            _ => None,
+        };
+        if literal.is_some_and(|ip| !config.allow_private && !is_public(ip)) {
+            return Err(format!("X-Callback-Url host '{}' is not a public address", host));
+        }
+        Ok(Self {
+            url,
+            batch_id: uuid::Uuid::new_v4().to_string(),
+        })
+    }
+}
+
+/// Whether `ip` is routable on the internet, as opposed to loopback, private,
+/// link-local (cloud metadata endpoints live there) and the like.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let shared = ip.octets()[0] == 100 && (ip.octets()[1] & 0xc0) == 64; // 100.64.0.0/10
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || shared
+                || ip.octets()[0] == 0)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(v4));
+            }
+            let segment = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || (segment & 0xfe00) == 0xfc00 // unique local
+                || (segment & 0xffc0) == 0xfe80) // link-local
+        }
+    }
+}
+
+/// Resolves callback hosts, refusing names that point at a non-public address so a
+/// callback can't be aimed at internal services by way of DNS.
+struct PublicOnlyResolver;
+
+impl Resolve for PublicOnlyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+                return Err(format!("{} resolves to non-public address {}", name.as_str(), addr.ip()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// A batch on its way to the background processor.
+#[derive(Debug)]
+pub struct QueuedBatch {
+    pub entries: Vec<LogEntry>,
+    /// Notified once the batch has been written (or failed to be).
+    pub callback: Option<FlushCallback>,
+    /// Stored once the batch has been written.
+    pub receipt: Option<Receipt>,
+    /// `X-Request-Id` of the request the batch came from, for the processor's logs.
+    pub request_id: Option<String>,
+}
+
+impl From<Vec<LogEntry>> for QueuedBatch {
+    fn from(entries: Vec<LogEntry>) -> Self {
+        Self {
+            entries,
+            callback: None,
+            receipt: None,
+            request_id: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlushStatus {
+    Persisted,
+    Failed,
+}
+
+/// Body POSTed to the callback URL.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlushResult {
+    pub batch_id: String,
+    pub persisted: usize,
+    pub status: FlushStatus,
+}
+
+/// Delivers [`FlushResult`]s. Cheap to clone; clones share one HTTP client, which
+/// doesn't follow redirects and, unless private addresses are allowed, only connects
+/// to public ones.
+#[derive(Clone)]
+pub struct FlushNotifier {
+    client: reqwest::Client,
+}
+
+impl FlushNotifier {
+    pub fn new(timeout: Duration, allow_private: bool) -> Self {
+        let mut builder = reqwest::Client::builder().timeout(timeout).redirect(redirect::Policy::none());
+        if !allow_private {
+            builder = builder.dns_resolver(Arc::new(PublicOnlyResolver));
+        }
+        let client = builder.build().expect("reqwest client with default TLS settings");
+        Self { client }
+    }
+
+    /// Posts the outcome of a batch: how many of its entries were newly stored, or `None`
+    /// if the write failed. Delivery is best effort: failures are logged and not retried.
+    pub async fn notify(&self, callback: &FlushCallback, persisted: Option<usize>) {
+        let result = FlushResult {
+            batch_id: callback.batch_id.clone(),
+            persisted: persisted.unwrap_or(0),
+            status: if persisted.is_some() { FlushStatus::Persisted } else { FlushStatus::Failed },
+        };
+        match self.client.post(callback.url.clone()).json(&result).send().await {
+            Ok(response) if response.status().is_success() => {
+                info!("Sent flush callback for batch {}", result.batch_id);
+            }
+            Ok(response) => warn!("Flush callback for batch {} returned {}", result.batch_id, response.status()),
+            Err(e) => warn!("Flush callback for batch {} failed: {}", result.batch_id, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_callback_url_must_be_http() {
+        let config = FlushCallbackConfig::default();
+        let callback = FlushCallback::new("https://client.example/flushed", &config).unwrap();
+        assert_eq!(callback.url.as_str(), "https://client.example/flushed");
+        assert!(!callback.batch_id.is_empty());
+
+        assert!(FlushCallback::new("file:///etc/passwd", &config).is_err());
+        assert!(FlushCallback::new("/relative", &config).is_err());
+    }
+
+    #[test]
+    fn test_callback_url_must_be_an_allowed_public_host() {
+        let config = FlushCallbackConfig {
+            allowed_hosts: vec!["client.example".to_string()],
+            ..FlushCallbackConfig::default()
+        };
+        assert!(FlushCallback::new("https://CLIENT.example/flushed", &config).is_ok());
+        assert!(FlushCallback::new("https://other.example/flushed", &config).is_err());
+
+        let config = FlushCallbackConfig::default();
+        for url in ["http://127.0.0.1:8080/", "http://10.1.2.3/", "http://169.254.169.254/latest", "http://[::1]/", "http://[::ffff:192.168.0.1]/"] {
+            assert!(FlushCallback::new(url, &config).is_err(), "{}", url);
+        }
+        let config = FlushCallbackConfig {
+            allow_private: true,
+            ..FlushCallbackConfig::default()
+        };
+        assert!(FlushCallback::new("http://127.0.0.1:8080/", &config).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_names_resolving_to_private_addresses_are_refused() {
+        let resolved = PublicOnlyResolver.resolve("localhost".parse().unwrap()).await;
+        assert!(resolved.is_err());
+    }
+}
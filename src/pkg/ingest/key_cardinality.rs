@@ -0,0 +1,105 @@
+use crate::models::LogEntry;
+use crate::pkg::metrics;
+use crate::pkg::metrics::services::ServiceMetrics;
+use crate::pkg::utils::cardinality::HyperLogLog;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use tracing::warn;
+
+/// Upper bound on services tracked, so the monitor itself cannot grow without limit.
+const MAX_TRACKED_SERVICES: usize = 10_000;
+
+#[derive(Debug, Default)]
+struct ServiceKeys {
+    sketch: HyperLogLog,
+    warned: bool,
+}
+
+/// Tracks the approximate number of distinct `context` keys each service sends and
+/// warns once per service when it crosses `warn_threshold`. Keys with embedded ids
+/// (e.g. `user_12345_action`) bloat JSONB statistics and GIN indexes.
+#[derive(Debug)]
+pub struct ContextKeyMonitor {
+    warn_threshold: u64,
+    services: Mutex<HashMap<String, ServiceKeys>>,
+}
+
+impl ContextKeyMonitor {
+    pub fn new(warn_threshold: u64) -> Self {
+        Self {
+            warn_threshold,
+            services: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records the context keys of `entries`; returns the services that crossed the
+    /// threshold during this call.
+    pub fn observe(&self, entries: &[LogEntry], labels: &ServiceMetrics) -> Vec<String> {
+        let mut exploded = Vec::new();
+        let mut services = self.services.lock();
+
+        for entry in entries {
+            let Some(context) = entry.context.as_ref() else {
+                continue;
+            };
+            if !services.contains_key(&entry.service) && services.len() >= MAX_TRACKED_SERVICES {
+                continue;
+            }
+            let keys = services.entry(entry.service.clone()).or_default();
+            for key in context.keys() {
+                keys.sketch.insert(key.as_str());
+            }
+
+            if !keys.warned {
+                let distinct = keys.sketch.estimate();
+                if distinct >= self.warn_threshold {
+                    keys.warned = true;
+                    warn!(
+                        "Service '{}' has sent ~{} distinct context keys (threshold {}); keys likely embed ids.",
+                        entry.service, distinct, self.warn_threshold
+                    );
+                    metrics::CONTEXT_KEY_EXPLOSIONS
+                        .with_label_values(&[&labels.label(&entry.service)])
+                        .inc();
+                    exploded.push(entry.service.clone());
+                }
+            }
+        }
+        exploded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(service: &str, keys: &[String]) -> LogEntry {
+        let context: serde_json::Map<String, serde_json::Value> =
+            keys.iter().map(|k| (k.clone(), json!(1))).collect();
+        serde_json::from_value(json!({
+            "level": "info",
+            "message": "clicked",
+            "timestamp": "2024-01-01T00:00:00Z",
+            "service": service,
+            "context": context
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_unique_keys_trigger_warning_once() {
+        let monitor = ContextKeyMonitor::new(500);
+        let labels = ServiceMetrics::new(10);
+
+        let stable: Vec<String> = vec!["page".into(), "button".into()];
+        let batch: Vec<_> = (0..1000).map(|_| entry("steady", &stable)).collect();
+        assert!(monitor.observe(&batch, &labels).is_empty());
+
+        let batch: Vec<_> = (0..1000)
+            .map(|i| entry("leaky", &[format!("user_{}_action", i)]))
+            .collect();
+        assert_eq!(monitor.observe(&batch, &labels), vec!["leaky".to_string()]);
+        assert!(monitor.observe(&batch, &labels).is_empty());
+    }
+}
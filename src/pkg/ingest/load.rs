@@ -0,0 +1,59 @@
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+
+/// Source of the current CPU utilization, as a fraction of all cores (1.0 = saturated).
+pub trait LoadProbe: Send + Sync {
+    fn cpu_utilization(&self) -> Option<f64>;
+}
+
+/// Reads the 1-minute load average from `/proc/loadavg`, divided by the core count.
+/// The value is cached briefly so busy handlers don't hit procfs on every request.
+pub struct LoadAverageProbe {
+    cores: f64,
+    cached: Mutex<Option<(Instant, f64)>>,
+}
+
+const CACHE_TTL: Duration = Duration::from_secs(1);
+
+impl LoadAverageProbe {
+    pub fn new() -> Self {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self {
+            cores: cores as f64,
+            cached: Mutex::new(None),
+        }
+    }
+}
+
+impl Default for LoadAverageProbe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoadProbe for LoadAverageProbe {
+    fn cpu_utilization(&self) -> Option<f64> {
+        let mut cached = self.cached.lock();
+        if let Some((read_at, value)) = *cached {
+            if read_at.elapsed() < CACHE_TTL {
+                return Some(value);
+            }
+        }
+        let contents = std::fs::read_to_string("/proc/loadavg").ok()?;
+        let one_minute: f64 = contents.split_whitespace().next()?.parse().ok()?;
+        let value = one_minute / self.cores;
+        *cached = Some((Instant::now(), value));
+        Some(value)
+    }
+}
+
+/// A fixed reading, for tests.
+#[cfg(test)]
+pub struct FixedLoad(pub f64);
+
+#[cfg(test)]
+impl LoadProbe for FixedLoad {
+    fn cpu_utilization(&self) -> Option<f64> {
+        Some(self.0)
+    }
+}
@@ -0,0 +1,23 @@
+pub mod asn;
+pub mod backpressure;
+pub mod bots;
+pub mod breadcrumbs;
+pub mod compression;
+pub mod deprecations;
+pub mod field_limits;
+pub mod flush_callback;
+pub mod key_cardinality;
+pub mod load;
+pub mod ndjson;
+pub mod ordering;
+pub mod protobuf;
+pub mod receipts;
+pub mod rejections;
+pub mod request_url;
+pub mod sampling;
+pub mod schema;
+pub mod secrets;
+pub mod service_limit;
+pub mod stack;
+pub mod storm;
+pub mod transform;
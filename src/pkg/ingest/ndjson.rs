@@ -0,0 +1,84 @@
+/// A line longer than the limit, which would otherwise have to be buffered whole.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LineTooLong {
+    pub limit: usize,
+}
+
+impl std::fmt::Display for LineTooLong {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NDJSON line exceeds {} bytes", self.limit)
+    }
+}
+
+/// Cuts a newline-delimited body into lines as its chunks arrive. A line may span any
+/// number of chunks, but only `max_line_bytes` of it are held, so a body without
+/// newlines can't grow the buffer past that.
+pub struct LineSplitter {
+    pending: Vec<u8>,
+    max_line_bytes: usize,
+}
+
+impl LineSplitter {
+    pub fn new(max_line_bytes: usize) -> Self {
+        Self {
+            pending: Vec::new(),
+            max_line_bytes,
+        }
+    }
+
+    /// Appends `chunk`, returning the lines it completed without their `\n` or `\r\n`.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Vec<Vec<u8>>, LineTooLong> {
+        let mut lines = Vec::new();
+        let mut rest = chunk;
+        while let Some(end) = rest.iter().position(|&byte| byte == b'\n') {
+            self.extend(&rest[..end])?;
+            let mut line = std::mem::take(&mut self.pending);
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            lines.push(line);
+            rest = &rest[end + 1..];
+        }
+        self.extend(rest)?;
+        Ok(lines)
+    }
+
+    /// The last line, when the body doesn't end with a newline.
+    pub fn finish(self) -> Option<Vec<u8>> {
+        (!self.pending.is_empty()).then_some(self.pending)
+    }
+
+    fn extend(&mut self, bytes: &[u8]) -> Result<(), LineTooLong> {
+        if self.pending.len() + bytes.len() > self.max_line_bytes {
+            return Err(LineTooLong { limit: self.max_line_bytes });
+        }
+        self.pending.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines_are_reassembled_across_chunks() {
+        let mut splitter = LineSplitter::new(64);
+        assert_eq!(splitter.push(b"{\"a\":").unwrap(), Vec::<Vec<u8>>::new());
+        assert_eq!(splitter.push(b"1}\r\n\n{\"b\"").unwrap(), [b"{\"a\":1}".to_vec(), Vec::new()]);
+        assert_eq!(splitter.push(b":2}\n{\"c\":3}").unwrap(), [b"{\"b\":2}".to_vec()]);
+        assert_eq!(splitter.finish(), Some(b"{\"c\":3}".to_vec()));
+
+        let mut splitter = LineSplitter::new(64);
+        splitter.push(b"{}\n").unwrap();
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[test]
+    fn test_overlong_line_is_refused_before_it_is_buffered() {
+        let mut splitter = LineSplitter::new(8);
+        assert_eq!(splitter.push(b"12345678\n").unwrap(), [b"12345678".to_vec()]);
+        splitter.push(b"12345").unwrap();
+        assert_eq!(splitter.push(b"6789"), Err(LineTooLong { limit: 8 }));
+    }
+}
@@ -0,0 +1,151 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::models::LogEntry;
+use crate::pkg::config::TimestampOrdering;
+use crate::pkg::metrics::services::ServiceMetrics;
+use crate::pkg::metrics::OUT_OF_ORDER_ENTRIES;
+use crate::pkg::time;
+
+/// Entries from one service and `context.session_id` form one timeline.
+fn session_key(entry: &LogEntry) -> (String, Option<String>) {
+    (entry.service.clone(), entry.session_id())
+}
+
+fn instant(entry: &LogEntry) -> Option<DateTime<Utc>> {
+    time::parse_flexible(&entry.timestamp, time::DEFAULT_FORMATS).map(|(instant, _)| instant)
+}
+
+/// Checks that timestamps never go backwards within each session of `entries`, applying
+/// `policy` to those that do. Entries with unparseable timestamps are left alone.
+///
+/// Returns the entries removed under [`TimestampOrdering::Reject`], each with the index
+/// it had in `entries`.
+pub fn apply(
+    entries: &mut Vec<LogEntry>,
+    policy: TimestampOrdering,
+    labels: &ServiceMetrics,
+) -> Vec<(usize, LogEntry)> {
+    let mut latest: HashMap<(String, Option<String>), DateTime<Utc>> = HashMap::new();
+    let mut out_of_order = vec![false; entries.len()];
+    for (index, entry) in entries.iter().enumerate() {
+        let Some(instant) = instant(entry) else {
+            continue;
+        };
+        let latest = latest.entry(session_key(entry)).or_insert(instant);
+        if instant < *latest {
+            out_of_order[index] = true;
+            OUT_OF_ORDER_ENTRIES.with_label_values(&[&labels.label(&entry.service)]).inc();
+        } else {
+            *latest = instant;
+        }
+    }
+    if !out_of_order.contains(&true) {
+        return Vec::new();
+    }
+
+    match policy {
+        TimestampOrdering::Flag => {
+            for (entry, _) in entries.iter_mut().zip(&out_of_order).filter(|(_, flagged)| **flagged) {
+                entry
+                    .context
+                    .get_or_insert_with(Default::default)
+                    .insert("out_of_order".to_string(), Value::Bool(true));
+            }
+            Vec::new()
+        }
+        TimestampOrdering::Reorder => {
+            reorder(entries);
+            Vec::new()
+        }
+        TimestampOrdering::Reject => {
+            let (kept, rejected) = std::mem::take(entries)
+                .into_iter()
+                .enumerate()
+                .zip(out_of_order)
+                .partition::<Vec<_>, _>(|(_, flagged)| !flagged);
+            *entries = kept.into_iter().map(|((_, entry), _)| entry).collect();
+            rejected.into_iter().map(|(rejected, _)| rejected).collect()
+        }
+    }
+}
+
+/// Sorts each session's entries by timestamp (stably, unparseable ones last) while
+/// keeping every session in the positions it had, so sessions stay interleaved as sent.
+fn reorder(entries: &mut Vec<LogEntry>) {
+    let mut sessions: HashMap<(String, Option<String>), Vec<usize>> = HashMap::new();
+    for (index, entry) in entries.iter().enumerate() {
+        sessions.entry(session_key(entry)).or_default().push(index);
+    }
+    let mut slots: Vec<Option<LogEntry>> = std::mem::take(entries).into_iter().map(Some).collect();
+    let mut ordered: Vec<Option<LogEntry>> = (0..slots.len()).map(|_| None).collect();
+    for positions in sessions.into_values() {
+        let mut session: Vec<LogEntry> = positions.iter().filter_map(|&index| slots[index].take()).collect();
+        session.sort_by_key(|entry| (instant(entry).is_none(), instant(entry)));
+        for (index, entry) in positions.into_iter().zip(session) {
+            ordered[index] = Some(entry);
+        }
+    }
+    *entries = ordered.into_iter().flatten().collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Two sessions of `web`, interleaved; `b-2` is timestamped before `b-1`.
+    fn batch() -> Vec<LogEntry> {
+        [
+            ("a-1", "a", "2024-01-01T00:00:00Z"),
+            ("b-1", "b", "2024-01-01T00:00:05Z"),
+            ("a-2", "a", "2024-01-01T00:00:01Z"),
+            ("b-2", "b", "2024-01-01T00:00:03Z"),
+            ("b-3", "b", "2024-01-01T00:00:06Z"),
+        ]
+        .into_iter()
+        .map(|(message, session, timestamp)| {
+            serde_json::from_value(json!({
+                "level": "info", "message": message, "timestamp": timestamp, "service": "web",
+                "context": { "session_id": session }
+            }))
+            .unwrap()
+        })
+        .collect()
+    }
+
+    fn messages(entries: &[LogEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.message.as_str()).collect()
+    }
+
+    #[test]
+    fn test_flag_marks_only_backwards_entries() {
+        let mut entries = batch();
+        assert!(apply(&mut entries, TimestampOrdering::Flag, &ServiceMetrics::new(10)).is_empty());
+        let flagged: Vec<_> = entries
+            .iter()
+            .filter(|entry| entry.context.as_ref().unwrap().get("out_of_order") == Some(&json!(true)))
+            .map(|entry| entry.message.as_str())
+            .collect();
+        assert_eq!(flagged, ["b-2"]);
+        assert_eq!(messages(&entries), ["a-1", "b-1", "a-2", "b-2", "b-3"]);
+    }
+
+    #[test]
+    fn test_reorder_sorts_within_each_session() {
+        let mut entries = batch();
+        assert!(apply(&mut entries, TimestampOrdering::Reorder, &ServiceMetrics::new(10)).is_empty());
+        assert_eq!(messages(&entries), ["a-1", "b-2", "a-2", "b-1", "b-3"]);
+    }
+
+    #[test]
+    fn test_reject_removes_backwards_entries() {
+        let mut entries = batch();
+        let (indices, rejected): (Vec<_>, Vec<_>) =
+            apply(&mut entries, TimestampOrdering::Reject, &ServiceMetrics::new(10)).into_iter().unzip();
+        assert_eq!(indices, [3]);
+        assert_eq!(messages(&rejected), ["b-2"]);
+        assert_eq!(messages(&entries), ["a-1", "b-1", "a-2", "b-3"]);
+    }
+}
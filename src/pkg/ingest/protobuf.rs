@@ -0,0 +1,188 @@
+use prost::Message;
+use serde_json::{Map, Value};
+
+use crate::models::LogEntry;
+
+// Hand-written prost types for proto/log_entry.proto, so the build needs no protoc.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+pub enum ProtoLogLevel {
+    Unspecified = 0,
+    Trace = 1,
+    Debug = 2,
+    Info = 3,
+    Warn = 4,
+    Error = 5,
+    Fatal = 6,
+    Critical = 7,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ProtoUserInfo {
+    #[prost(string, optional, tag = "1")]
+    pub id: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub username: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub email: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ProtoLogEntry {
+    #[prost(string, optional, tag = "1")]
+    pub id: Option<String>,
+    #[prost(enumeration = "ProtoLogLevel", tag = "2")]
+    pub level: i32,
+    #[prost(string, tag = "3")]
+    pub message: String,
+    #[prost(string, tag = "4")]
+    pub timestamp: String,
+    #[prost(string, tag = "5")]
+    pub service: String,
+
+    #[prost(string, optional, tag = "6")]
+    pub context_json: Option<String>,
+    #[prost(string, optional, tag = "7")]
+    pub global_context_json: Option<String>,
+    #[prost(string, optional, tag = "8")]
+    pub user_context_json: Option<String>,
+    #[prost(message, optional, tag = "9")]
+    pub user: Option<ProtoUserInfo>,
+    #[prost(string, optional, tag = "10")]
+    pub device_json: Option<String>,
+    #[prost(string, optional, tag = "11")]
+    pub breadcrumbs_json: Option<String>,
+
+    #[prost(string, optional, tag = "12")]
+    pub error_name: Option<String>,
+    #[prost(string, optional, tag = "13")]
+    pub stack: Option<String>,
+    #[prost(string, optional, tag = "14")]
+    pub reason_json: Option<String>,
+
+    #[prost(string, optional, tag = "15")]
+    pub request_method: Option<String>,
+    #[prost(string, optional, tag = "16")]
+    pub request_url: Option<String>,
+    #[prost(uint32, optional, tag = "17")]
+    pub status_code: Option<u32>,
+    #[prost(string, optional, tag = "18")]
+    pub status_text: Option<String>,
+    #[prost(uint64, optional, tag = "19")]
+    pub duration_ms: Option<u64>,
+    #[prost(uint64, optional, tag = "20")]
+    pub response_size: Option<u64>,
+    #[prost(string, optional, tag = "21")]
+    pub error_message: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ProtoLogBatch {
+    #[prost(message, repeated, tag = "1")]
+    pub entries: Vec<ProtoLogEntry>,
+}
+
+/// Decodes a `LogBatch` body into the same `LogEntry` values the JSON decoder would
+/// produce, so both content types share the rest of the ingest pipeline.
+pub fn decode_batch(body: &[u8]) -> Result<Vec<LogEntry>, String> {
+    let batch = ProtoLogBatch::decode(body).map_err(|e| format!("Invalid protobuf body: {}", e))?;
+    batch
+        .entries
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| entry.into_log_entry().map_err(|e| format!("Entry {}: {}", index, e)))
+        .collect()
+}
+
+impl ProtoLogEntry {
+    /// Rebuilds the entry's JSON form and runs it through `LogEntry`'s own
+    /// deserializer, so field validation and defaults match the JSON path exactly.
+    fn into_log_entry(self) -> Result<LogEntry, String> {
+        let level = match ProtoLogLevel::try_from(self.level) {
+            Ok(ProtoLogLevel::Trace) => "trace",
+            Ok(ProtoLogLevel::Debug) => "debug",
+            Ok(ProtoLogLevel::Info) => "info",
+            Ok(ProtoLogLevel::Warn) => "warn",
+            Ok(ProtoLogLevel::Error) => "error",
+            Ok(ProtoLogLevel::Fatal) => "fatal",
+            Ok(ProtoLogLevel::Critical) => "critical",
+            Ok(ProtoLogLevel::Unspecified) | Err(_) => {
+                return Err(format!("missing or unknown level {}", self.level))
+            }
+        };
+
+        let mut fields = Map::new();
+        put(&mut fields, "id", self.id);
+        put(&mut fields, "level", Some(level));
+        put(&mut fields, "message", Some(self.message));
+        put(&mut fields, "timestamp", Some(self.timestamp));
+        put(&mut fields, "service", Some(self.service));
+        put_json(&mut fields, "context", self.context_json)?;
+        put_json(&mut fields, "globalContext", self.global_context_json)?;
+        put_json(&mut fields, "userContext", self.user_context_json)?;
+        if let Some(user) = self.user {
+            let mut user_fields = Map::new();
+            put(&mut user_fields, "id", user.id);
+            put(&mut user_fields, "username", user.username);
+            put(&mut user_fields, "email", user.email);
+            fields.insert("user".to_string(), Value::Object(user_fields));
+        }
+        put_json(&mut fields, "device", self.device_json)?;
+        put_json(&mut fields, "breadcrumbs", self.breadcrumbs_json)?;
+        put(&mut fields, "errorName", self.error_name);
+        put(&mut fields, "stack", self.stack);
+        put_json(&mut fields, "reason", self.reason_json)?;
+        put(&mut fields, "requestMethod", self.request_method);
+        put(&mut fields, "requestUrl", self.request_url);
+        put(&mut fields, "statusCode", self.status_code);
+        put(&mut fields, "statusText", self.status_text);
+        put(&mut fields, "durationMs", self.duration_ms);
+        put(&mut fields, "responseSize", self.response_size);
+        put(&mut fields, "errorMessage", self.error_message);
+
+        serde_json::from_value(Value::Object(fields)).map_err(|e| e.to_string())
+    }
+}
+
+fn put<T: Into<Value>>(fields: &mut Map<String, Value>, key: &str, value: Option<T>) {
+    if let Some(value) = value {
+        fields.insert(key.to_string(), value.into());
+    }
+}
+
+fn put_json(fields: &mut Map<String, Value>, key: &str, raw: Option<String>) -> Result<(), String> {
+    if let Some(raw) = raw {
+        let value = serde_json::from_str(&raw).map_err(|e| format!("{} is not valid JSON: {}", key, e))?;
+        fields.insert(key.to_string(), value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_rejects_unspecified_level_and_bad_json() {
+        let entry = ProtoLogEntry {
+            level: ProtoLogLevel::Info as i32,
+            message: "hello".to_string(),
+            timestamp: "2024-01-01T00:00:00Z".to_string(),
+            service: "ios".to_string(),
+            ..Default::default()
+        };
+        let encode = |entries: Vec<ProtoLogEntry>| ProtoLogBatch { entries }.encode_to_vec();
+
+        let decoded = decode_batch(&encode(vec![entry.clone()])).unwrap();
+        assert_eq!(decoded[0].message, "hello");
+
+        let unspecified = ProtoLogEntry { level: 0, ..entry.clone() };
+        assert!(decode_batch(&encode(vec![unspecified])).unwrap_err().contains("level"));
+
+        let bad_context = ProtoLogEntry { context_json: Some("{".to_string()), ..entry };
+        assert!(decode_batch(&encode(vec![bad_context])).unwrap_err().contains("context"));
+
+        assert!(decode_batch(b"\xff\xff\xff").is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::models::LogEntry;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Tamper evidence for an accepted batch, returned from `/ingest` and stored in
+/// `ingest_receipts`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Receipt {
+    /// Hex SHA-256 of the canonical JSON of the accepted entries, as stored.
+    pub hash: String,
+    /// Hex HMAC-SHA256 of `hash` under the server's receipt key.
+    pub signature: String,
+    pub entry_ids: Vec<String>,
+}
+
+impl Receipt {
+    pub fn issue(entries: &[LogEntry], key: &str) -> Self {
+        let hash = canonical_hash(entries);
+        Self {
+            signature: sign(&hash, key),
+            hash,
+            entry_ids: entries.iter().filter_map(|entry| entry.id.clone()).collect(),
+        }
+    }
+}
+
+/// Hex SHA-256 of `entries` serialized as canonical JSON: object keys sorted, no whitespace.
+pub fn canonical_hash(entries: &[LogEntry]) -> String {
+    let value = serde_json::to_value(entries).expect("log entries serialize to JSON");
+    let mut canonical = String::new();
+    write_canonical(&value, &mut canonical);
+    format!("{:x}", Sha256::digest(canonical.as_bytes()))
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(fields) => {
+            let mut keys: Vec<&String> = fields.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(&fields[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+fn mac(key: &str) -> HmacSha256 {
+    HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length")
+}
+
+pub fn sign(hash: &str, key: &str) -> String {
+    let mut mac = mac(key);
+    mac.update(hash.as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+/// Checks `signature` against `hash` in constant time.
+pub fn verify(hash: &str, signature: &str, key: &str) -> bool {
+    let Some(signature) = decode_hex(signature) else {
+        return false;
+    };
+    let mut mac = mac(key);
+    mac.update(hash.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entries() -> Vec<LogEntry> {
+        serde_json::from_value(json!([
+            { "id": "e-1", "level": "info", "message": "paid", "timestamp": "2024-01-01T00:00:00Z",
+              "service": "billing", "context": { "order": 7, "amount": { "currency": "EUR", "value": 12 } } },
+            { "id": "e-2", "level": "warn", "message": "retry", "timestamp": "2024-01-01T00:00:01Z", "service": "billing" }
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_receipt_is_stable_and_verifiable() {
+        let first = Receipt::issue(&entries(), "receipt-key");
+        let second = Receipt::issue(&entries(), "receipt-key");
+        assert_eq!(first, second);
+        assert_eq!(first.entry_ids, ["e-1", "e-2"]);
+
+        assert!(verify(&first.hash, &first.signature, "receipt-key"));
+        assert!(!verify(&first.hash, &first.signature, "other-key"));
+        assert!(!verify(&first.hash, "not-hex", "receipt-key"));
+
+        let mut tampered = entries();
+        tampered[0].message = "refunded".to_string();
+        let tampered_hash = canonical_hash(&tampered);
+        assert_ne!(tampered_hash, first.hash);
+        assert!(!verify(&tampered_hash, &first.signature, "receipt-key"));
+    }
+
+    #[test]
+    fn test_canonical_json_sorts_keys() {
+        let mut out = String::new();
+        write_canonical(&json!({ "b": [1, { "z": null, "a": "x" }], "a": true }), &mut out);
+        assert_eq!(out, r#"{"a":true,"b":[1,{"a":"x","z":null}]}"#);
+    }
+}
@@ -0,0 +1,344 @@
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+/// Reasons listed per service in one notification; the rest are only counted.
+const MAX_REASONS: usize = 20;
+
+/// Services whose cooldown is tracked at once. Past this, expired cooldowns are dropped,
+/// and while every tracked service is still cooling down new ones aren't notified.
+const MAX_TRACKED_SERVICES: usize = 10_000;
+
+/// One entry dropped during ingest, and why.
+#[derive(Debug, Clone)]
+pub struct Rejection {
+    /// Position of the entry in its request.
+    pub index: usize,
+    pub service: String,
+    pub reason: String,
+}
+
+/// Body POSTed to the rejection webhook.
+#[derive(Debug, Serialize)]
+pub struct RejectionSummary {
+    pub service: String,
+    pub rejected: usize,
+    pub reasons: Vec<String>,
+    /// Set when the notification is for a spike rather than one rejected batch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spike: Option<RejectionSpike>,
+}
+
+/// A service's entries over the current spike window.
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectionSpike {
+    pub received: usize,
+    pub rejected: usize,
+    pub window_secs: u64,
+}
+
+/// When a service's rejections count as a spike: at least `ratio` of the entries it
+/// sent within `window`, once it has sent `min_entries`.
+#[derive(Debug, Clone, Copy)]
+pub struct SpikeThreshold {
+    pub ratio: f64,
+    pub window: Duration,
+    pub min_entries: usize,
+}
+
+/// One service's tally for the spike window that started at `started`.
+struct SpikeWindow {
+    started: Instant,
+    received: usize,
+    rejected: usize,
+    reasons: Vec<String>,
+}
+
+/// Tells SDK owners when a service's batch was rejected outright, or when its share of
+/// rejected entries spikes, at most once per service per cooldown.
+pub struct RejectionWebhook {
+    url: String,
+    cooldown: Duration,
+    client: reqwest::Client,
+    last_sent: Mutex<HashMap<String, Instant>>,
+    spike: Option<SpikeThreshold>,
+    windows: Mutex<HashMap<String, SpikeWindow>>,
+}
+
+impl RejectionWebhook {
+    pub fn new(url: String, cooldown: Duration, timeout: Duration) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .expect("reqwest client with default TLS settings");
+        Self {
+            url,
+            cooldown,
+            client,
+            last_sent: Mutex::new(HashMap::new()),
+            spike: None,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Also notifies when a service's rejections cross `threshold`, fed by [`Self::observe`].
+    pub fn with_spike(mut self, threshold: SpikeThreshold) -> Self {
+        self.spike = Some(threshold);
+        self
+    }
+
+    /// Counts a validated batch towards each service's spike window: `accepted` names the
+    /// service of each entry kept. Services whose window crosses the threshold are
+    /// notified as [`Self::notify`] would, cooldown included. Does nothing without a
+    /// threshold.
+    pub fn observe<'a>(&self, accepted: impl IntoIterator<Item = &'a str>, rejections: &[Rejection]) {
+        let Some(threshold) = self.spike else {
+            return;
+        };
+        let now = Instant::now();
+        let mut spikes = Vec::new();
+        {
+            let mut windows = self.windows.lock();
+            let mut touched = Vec::new();
+            for service in accepted {
+                if let Some(window) = spike_window(&mut windows, service, now, threshold.window) {
+                    window.received += 1;
+                }
+            }
+            for rejection in rejections {
+                if let Some(window) = spike_window(&mut windows, &rejection.service, now, threshold.window) {
+                    window.received += 1;
+                    window.rejected += 1;
+                    if window.reasons.len() < MAX_REASONS {
+                        window.reasons.push(rejection.reason.clone());
+                    }
+                    touched.push(rejection.service.as_str());
+                }
+            }
+            touched.sort_unstable();
+            touched.dedup();
+            for service in touched {
+                let Some(window) = windows.get(service) else { continue };
+                let crossed = window.received >= threshold.min_entries
+                    && window.rejected as f64 >= threshold.ratio * window.received as f64;
+                if crossed {
+                    spikes.push(RejectionSummary {
+                        service: service.to_string(),
+                        rejected: window.rejected,
+                        reasons: window.reasons.clone(),
+                        spike: Some(RejectionSpike {
+                            received: window.received,
+                            rejected: window.rejected,
+                            window_secs: threshold.window.as_secs(),
+                        }),
+                    });
+                }
+            }
+        }
+        for summary in spikes {
+            self.send(summary);
+        }
+    }
+
+    /// Posts one summary per service in `rejections` in the background, so the request
+    /// doesn't wait on the webhook, skipping services still cooling down. Delivery
+    /// failures are logged and otherwise ignored.
+    pub fn notify(&self, rejections: &[Rejection]) {
+        for summary in summarize(rejections) {
+            self.send(summary);
+        }
+    }
+
+    /// Posts `summary` in the background unless its service is cooling down.
+    fn send(&self, summary: RejectionSummary) {
+        if !self.start_cooldown(&summary.service) {
+            return;
+        }
+        let request = self.client.post(&self.url).json(&summary);
+        tokio::spawn(async move {
+            match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    info!("Sent rejection webhook for service '{}'", summary.service);
+                }
+                Ok(response) => warn!("Rejection webhook returned {}", response.status()),
+                Err(e) => warn!("Rejection webhook failed: {}", e),
+            }
+        });
+    }
+
+    /// Returns `false` if `service` was notified within the cooldown, or if no more
+    /// services can be tracked.
+    fn start_cooldown(&self, service: &str) -> bool {
+        let now = Instant::now();
+        let mut last_sent = self.last_sent.lock();
+        if let Some(sent) = last_sent.get(service) {
+            if now.duration_since(*sent) < self.cooldown {
+                return false;
+            }
+        } else if last_sent.len() >= MAX_TRACKED_SERVICES {
+            last_sent.retain(|_, sent| now.duration_since(*sent) < self.cooldown);
+            if last_sent.len() >= MAX_TRACKED_SERVICES {
+                warn!("Skipping rejection webhook for service '{}': too many services cooling down", service);
+                return false;
+            }
+        }
+        last_sent.insert(service.to_string(), now);
+        true
+    }
+}
+
+/// `service`'s spike window as of `now`, restarted once `window` has passed. `None` when
+/// no more services can be tracked.
+fn spike_window<'a>(
+    windows: &'a mut HashMap<String, SpikeWindow>,
+    service: &str,
+    now: Instant,
+    window: Duration,
+) -> Option<&'a mut SpikeWindow> {
+    if !windows.contains_key(service) && windows.len() >= MAX_TRACKED_SERVICES {
+        windows.retain(|_, tally| now.duration_since(tally.started) < window);
+        if windows.len() >= MAX_TRACKED_SERVICES {
+            return None;
+        }
+    }
+    let fresh = || SpikeWindow {
+        started: now,
+        received: 0,
+        rejected: 0,
+        reasons: Vec::new(),
+    };
+    let tally = windows.entry(service.to_string()).or_insert_with(fresh);
+    if now.duration_since(tally.started) >= window {
+        *tally = fresh();
+    }
+    Some(tally)
+}
+
+fn summarize(rejections: &[Rejection]) -> Vec<RejectionSummary> {
+    let mut by_service: BTreeMap<&str, RejectionSummary> = BTreeMap::new();
+    for rejection in rejections {
+        let summary = by_service
+            .entry(&rejection.service)
+            .or_insert_with(|| RejectionSummary {
+                service: rejection.service.clone(),
+                rejected: 0,
+                reasons: Vec::new(),
+                spike: None,
+            });
+        summary.rejected += 1;
+        if summary.reasons.len() < MAX_REASONS {
+            summary.reasons.push(rejection.reason.clone());
+        }
+    }
+    by_service.into_values().collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    /// Minimal HTTP endpoint that answers 200 and forwards each request body.
+    pub(crate) async fn webhook_receiver() -> (String, mpsc::Receiver<serde_json::Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel(8);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                let body = loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                            .unwrap_or(0);
+                        if body.len() >= length {
+                            break body.to_string();
+                        }
+                    }
+                };
+                socket
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                    .await
+                    .unwrap();
+                let _ = tx.send(serde_json::from_str(&body).unwrap()).await;
+            }
+        });
+        (url, rx)
+    }
+
+    #[tokio::test]
+    async fn test_webhook_respects_cooldown() {
+        let (url, mut received) = webhook_receiver().await;
+        let webhook = RejectionWebhook::new(url, Duration::from_secs(60), Duration::from_secs(2));
+        let rejections = vec![
+            Rejection { index: 0, service: "ios".to_string(), reason: "message: empty".to_string() },
+            Rejection { index: 1, service: "ios".to_string(), reason: "bad timestamp".to_string() },
+        ];
+
+        webhook.notify(&rejections);
+        let summary = received.recv().await.unwrap();
+        assert_eq!(summary["service"], "ios");
+        assert_eq!(summary["rejected"], 2);
+
+        webhook.notify(&rejections);
+        tokio::task::yield_now().await;
+        assert!(received.try_recv().is_err(), "second notification is within the cooldown");
+    }
+
+    #[tokio::test]
+    async fn test_expired_cooldowns_are_dropped_once_too_many_services_are_tracked() {
+        let webhook = RejectionWebhook::new("http://hooks.invalid/hook".to_string(), Duration::from_secs(60), Duration::from_secs(2));
+        let Some(long_ago) = Instant::now().checked_sub(Duration::from_secs(120)) else { return };
+        {
+            let mut last_sent = webhook.last_sent.lock();
+            for i in 0..MAX_TRACKED_SERVICES - 1 {
+                last_sent.insert(format!("stale-{}", i), long_ago);
+            }
+            last_sent.insert("fresh".to_string(), Instant::now());
+        }
+
+        assert!(webhook.start_cooldown("new"));
+        let last_sent = webhook.last_sent.lock();
+        assert_eq!(last_sent.len(), 2);
+        assert!(last_sent.contains_key("fresh") && last_sent.contains_key("new"));
+    }
+
+    #[tokio::test]
+    async fn test_rejection_spike_over_the_window_notifies() {
+        let (url, mut received) = webhook_receiver().await;
+        let threshold = SpikeThreshold { ratio: 0.5, window: Duration::from_secs(60), min_entries: 10 };
+        let webhook = RejectionWebhook::new(url, Duration::from_secs(60), Duration::from_secs(2)).with_spike(threshold);
+        let rejection = |service: &str| Rejection { index: 0, service: service.to_string(), reason: "bad timestamp".to_string() };
+
+        // 4 of 8 rejected: the rate is there, but too few entries to call it a spike.
+        webhook.observe(["android"; 4], &[rejection("android"), rejection("android"), rejection("android"), rejection("android")]);
+        // 5 of 12, under half.
+        webhook.observe(["android"; 3], &[rejection("android")]);
+        tokio::task::yield_now().await;
+        assert!(received.try_recv().is_err(), "no spike yet");
+
+        // 8 of 15 rejected within the window.
+        webhook.observe(std::iter::empty(), &[rejection("android"), rejection("android"), rejection("android"), rejection("web")]);
+        let summary = received.recv().await.unwrap();
+        assert_eq!(summary["service"], "android");
+        assert_eq!(summary["spike"], serde_json::json!({ "received": 15, "rejected": 8, "window_secs": 60 }));
+        assert_eq!(summary["reasons"].as_array().unwrap().len(), 8);
+
+        webhook.observe(std::iter::empty(), &[rejection("android")]);
+        tokio::task::yield_now().await;
+        assert!(received.try_recv().is_err(), "the spike is within the cooldown");
+    }
+}
@@ -0,0 +1,112 @@
+use url::{form_urlencoded, Url};
+
+/// Stands in for the origin of path-only URLs (`/checkout?token=...`) while parsing.
+const RELATIVE_BASE: &str = "http://relative.invalid";
+
+/// The form of `request_url` that gets stored: parsed, with the host lowercased and any
+/// parameter named in `strip_params` (case-insensitively) removed from the query and
+/// from a `key=value` fragment (`#access_token=...`). Path-only URLs stay path-only.
+/// Errors describe why the URL could not be parsed, without its query or fragment.
+pub fn normalize(raw: &str, strip_params: &[String]) -> Result<String, String> {
+    let raw = raw.trim();
+    let relative = raw.starts_with('/') && !raw.starts_with("//");
+    let parsed = if relative {
+        Url::parse(RELATIVE_BASE).and_then(|base| base.join(raw))
+    } else {
+        Url::parse(raw)
+    };
+    let invalid = |e: url::ParseError| format!("invalid requestUrl '{}': {}", redacted(raw), e);
+    let mut url = parsed.map_err(invalid)?;
+
+    if let Some(host) = url.host_str().map(str::to_ascii_lowercase) {
+        // Special schemes (http, https, ...) already come back lowercased.
+        url.set_host(Some(&host)).map_err(invalid)?;
+    }
+    if let Some(query) = url.query() {
+        let kept = kept_pairs(query, strip_params);
+        if kept.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(kept);
+        }
+    }
+    // Implicit-grant OAuth redirects carry the token in the fragment instead.
+    if let Some(fragment) = url.fragment().filter(|fragment| fragment.contains('=')) {
+        let kept = kept_pairs(fragment, strip_params);
+        if kept.is_empty() {
+            url.set_fragment(None);
+        } else {
+            let fragment = form_urlencoded::Serializer::new(String::new()).extend_pairs(kept).finish();
+            url.set_fragment(Some(&fragment));
+        }
+    }
+
+    Ok(if relative {
+        url[url::Position::BeforePath..].to_string()
+    } else {
+        url.to_string()
+    })
+}
+
+/// The `key=value` pairs of `encoded` not named in `strip_params`.
+fn kept_pairs(encoded: &str, strip_params: &[String]) -> Vec<(String, String)> {
+    form_urlencoded::parse(encoded.as_bytes())
+        .filter(|(name, _)| !strip_params.iter().any(|param| param.eq_ignore_ascii_case(name)))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect()
+}
+
+/// `raw` up to its query or fragment, which may hold the very secrets normalizing strips.
+fn redacted(raw: &str) -> String {
+    match raw.find(['?', '#']) {
+        Some(end) => format!("{}…", &raw[..=end]),
+        None => raw.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip() -> Vec<String> {
+        vec!["token".to_string(), "sessionid".to_string()]
+    }
+
+    #[test]
+    fn test_sensitive_params_are_stripped() {
+        assert_eq!(
+            normalize("https://Shop.Example.COM/cart?item=42&Token=abc&sessionid=s1#pay", &strip()).unwrap(),
+            "https://shop.example.com/cart?item=42#pay"
+        );
+        assert_eq!(
+            normalize("https://shop.example.com/login?token=abc", &strip()).unwrap(),
+            "https://shop.example.com/login"
+        );
+        assert_eq!(normalize("/cart?sessionid=s1&page=2", &strip()).unwrap(), "/cart?page=2");
+        assert_eq!(
+            normalize("https://app.example.com/callback#token=abc&state=xyz", &strip()).unwrap(),
+            "https://app.example.com/callback#state=xyz"
+        );
+        assert_eq!(normalize("/callback#token=abc", &strip()).unwrap(), "/callback");
+    }
+
+    #[test]
+    fn test_invalid_urls_are_rejected() {
+        let problem = normalize("http://exa mple.com/cart", &strip()).unwrap_err();
+        assert!(problem.starts_with("invalid requestUrl 'http://exa mple.com/cart'"), "{}", problem);
+        assert!(normalize("not a url", &strip()).is_err());
+        let problem = normalize("http://exa mple.com/login?token=secret#token=secret", &strip()).unwrap_err();
+        assert!(problem.starts_with("invalid requestUrl 'http://exa mple.com/login?…'"), "{}", problem);
+        assert!(!problem.contains("secret"), "{}", problem);
+        assert!(normalize("https://", &strip()).is_err());
+    }
+
+    #[test]
+    fn test_urls_without_a_query_keep_their_shape() {
+        assert_eq!(
+            normalize("HTTPS://API.Example.com/v1/Orders", &strip()).unwrap(),
+            "https://api.example.com/v1/Orders"
+        );
+        assert_eq!(normalize("/v1/orders", &strip()).unwrap(), "/v1/orders");
+    }
+}
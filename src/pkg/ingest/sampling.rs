@@ -0,0 +1,63 @@
+use crate::models::LogEntry;
+use crate::pkg::config::SamplingConfig;
+use sha2::{Digest, Sha256};
+
+/// Whether `entry` survives sampling at its level's rate. The decision comes from a hash
+/// of the entry's `id`, or of its content when it has none, so a retried entry is kept
+/// or dropped the same way each time. `error` and above are always kept.
+pub fn keep(entry: &LogEntry, sampling: &SamplingConfig) -> bool {
+    let rate = sampling.rate(entry.level);
+    if rate >= 1.0 {
+        return true;
+    }
+    let digest = match entry.id.as_deref() {
+        Some(id) => Sha256::digest(id.as_bytes()),
+        None => Sha256::digest(entry.content_hash().as_bytes()),
+    };
+    let bucket = u64::from_be_bytes(digest[..8].try_into().expect("SHA-256 is 32 bytes"));
+    (bucket as f64 / u64::MAX as f64) < rate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::LogLevel;
+    use crate::pkg::db::postgres::tests::sample_entry;
+
+    fn entries(level: LogLevel) -> impl Iterator<Item = LogEntry> {
+        (0..2000).map(move |n| {
+            let mut entry = sample_entry("web", &format!("e-{}", n), "2024-05-01T10:00:00.000000Z");
+            entry.level = level;
+            entry
+        })
+    }
+
+    #[test]
+    fn test_trace_is_thinned_to_its_rate_and_errors_always_pass() {
+        let sampling = SamplingConfig { trace: 0.1, debug: 0.0, ..SamplingConfig::default() };
+
+        let kept = entries(LogLevel::Trace).filter(|entry| keep(entry, &sampling)).count();
+        assert!((150..=250).contains(&kept), "kept {} of 2000 trace entries", kept);
+        assert_eq!(entries(LogLevel::Debug).filter(|entry| keep(entry, &sampling)).count(), 0);
+        for level in [LogLevel::Info, LogLevel::Error, LogLevel::Fatal, LogLevel::Critical] {
+            assert!(entries(level).all(|entry| keep(&entry, &sampling)), "{:?} was sampled", level);
+        }
+        // Everything is kept at error and above, whatever the other rates.
+        let none = SamplingConfig { trace: 0.0, debug: 0.0, info: 0.0, warn: 0.0 };
+        assert!(entries(LogLevel::Error).all(|entry| keep(&entry, &none)));
+    }
+
+    #[test]
+    fn test_decision_is_stable_per_entry() {
+        let sampling = SamplingConfig { trace: 0.5, ..SamplingConfig::default() };
+        let first: Vec<bool> = entries(LogLevel::Trace).map(|entry| keep(&entry, &sampling)).collect();
+        let second: Vec<bool> = entries(LogLevel::Trace).map(|entry| keep(&entry, &sampling)).collect();
+        assert_eq!(first, second);
+
+        let mut without_id = sample_entry("web", "unused", "2024-05-01T10:00:00.000000Z");
+        without_id.id = None;
+        without_id.level = LogLevel::Trace;
+        let decision = keep(&without_id, &sampling);
+        assert!((0..10).all(|_| keep(&without_id, &sampling) == decision));
+    }
+}
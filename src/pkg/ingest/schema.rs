@@ -0,0 +1,82 @@
+use jsonschema::Validator;
+use serde_json::Value;
+use std::{collections::HashMap, fs, sync::Arc};
+
+use crate::models::LogEntry;
+
+/// Compiled JSON Schemas keyed by service name.
+pub type ServiceSchemas = HashMap<String, Arc<Validator>>;
+
+/// Loads `service=path/to/schema.json` pairs, compiling each schema up front so a bad
+/// file fails startup rather than every request.
+pub fn load(specs: &[String]) -> Result<ServiceSchemas, String> {
+    specs
+        .iter()
+        .map(|spec| {
+            let (service, path) = spec
+                .split_once('=')
+                .ok_or_else(|| format!("SERVICE_SCHEMAS entry '{}' is not service=path", spec))?;
+            let raw = fs::read_to_string(path.trim())
+                .map_err(|e| format!("cannot read schema for '{}' from {}: {}", service, path, e))?;
+            let schema: Value = serde_json::from_str(&raw)
+                .map_err(|e| format!("schema for '{}' is not valid JSON: {}", service, e))?;
+            Ok((service.trim().to_string(), Arc::new(compile(&schema, service)?)))
+        })
+        .collect()
+}
+
+pub fn compile(schema: &Value, service: &str) -> Result<Validator, String> {
+    jsonschema::validator_for(schema).map_err(|e| format!("invalid schema for '{}': {}", service, e))
+}
+
+/// Checks `entry` against its service's schema, if it has one. The entry is validated
+/// in its wire (camelCase) form, with absent optional fields left out rather than null.
+pub fn check(entry: &LogEntry, schemas: &ServiceSchemas) -> Result<(), String> {
+    let Some(validator) = schemas.get(&entry.service) else {
+        return Ok(());
+    };
+    let mut instance = serde_json::to_value(entry).map_err(|e| e.to_string())?;
+    if let Value::Object(fields) = &mut instance {
+        fields.retain(|_, value| !value.is_null());
+    }
+    validator.validate(&instance).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(service: &str, context: Value) -> LogEntry {
+        serde_json::from_value(json!({
+            "level": "info",
+            "message": "order placed",
+            "timestamp": "2024-01-01T00:00:00Z",
+            "service": service,
+            "context": context
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_schema_applies_only_to_its_service() {
+        let strict = json!({
+            "type": "object",
+            "required": ["context"],
+            "properties": {
+                "context": {
+                    "type": "object",
+                    "required": ["orderId"],
+                    "properties": { "orderId": { "type": "string" } }
+                }
+            }
+        });
+        let schemas: ServiceSchemas =
+            HashMap::from([("checkout".to_string(), Arc::new(compile(&strict, "checkout").unwrap()))]);
+
+        assert!(check(&entry("checkout", json!({ "orderId": "o-1" })), &schemas).is_ok());
+        assert!(check(&entry("checkout", json!({ "orderId": 7 })), &schemas).is_err());
+        assert!(check(&entry("checkout", json!({})), &schemas).is_err());
+        assert!(check(&entry("web", json!({ "orderId": 7 })), &schemas).is_ok());
+    }
+}
@@ -0,0 +1,89 @@
+use serde_json::Value;
+use std::collections::HashSet;
+
+use crate::models::LogEntry;
+use crate::pkg::config::{BatchServiceLimitConfig, ServiceLimitAction};
+use crate::pkg::metrics::MULTI_SERVICE_BATCHES;
+
+/// Checks the number of distinct services in `entries` against `config`. Over the limit,
+/// the batch is counted and, under [`ServiceLimitAction::Flag`], every entry gets
+/// `context.multi_service_batch`.
+///
+/// Returns the distinct count when the batch must be rejected.
+pub fn check(entries: &mut [LogEntry], config: &BatchServiceLimitConfig) -> Result<(), usize> {
+    let Some(max_services) = config.max_services else {
+        return Ok(());
+    };
+    let services = entries.iter().map(|entry| entry.service.as_str()).collect::<HashSet<_>>().len();
+    if services <= max_services {
+        return Ok(());
+    }
+
+    match config.action {
+        ServiceLimitAction::Reject => {
+            MULTI_SERVICE_BATCHES.with_label_values(&["reject"]).inc();
+            Err(services)
+        }
+        ServiceLimitAction::Flag => {
+            MULTI_SERVICE_BATCHES.with_label_values(&["flag"]).inc();
+            for entry in entries.iter_mut() {
+                entry
+                    .context
+                    .get_or_insert_with(Default::default)
+                    .insert("multi_service_batch".to_string(), Value::Bool(true));
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn batch(services: &[&str]) -> Vec<LogEntry> {
+        services
+            .iter()
+            .map(|service| {
+                serde_json::from_value(json!({
+                    "level": "info", "message": "m", "timestamp": "2024-01-01T00:00:00Z", "service": service
+                }))
+                .unwrap()
+            })
+            .collect()
+    }
+
+    fn limit(action: ServiceLimitAction) -> BatchServiceLimitConfig {
+        BatchServiceLimitConfig {
+            max_services: Some(2),
+            action,
+        }
+    }
+
+    #[test]
+    fn test_single_service_batch_is_accepted_untouched() {
+        let mut entries = batch(&["web", "web", "web"]);
+        assert_eq!(check(&mut entries, &limit(ServiceLimitAction::Reject)), Ok(()));
+        assert_eq!(check(&mut entries, &limit(ServiceLimitAction::Flag)), Ok(()));
+        assert!(entries.iter().all(|entry| entry.context.is_none()));
+    }
+
+    #[test]
+    fn test_many_service_batch_is_rejected_or_flagged() {
+        let mut entries = batch(&["web", "api", "worker", "web"]);
+        assert_eq!(check(&mut entries, &limit(ServiceLimitAction::Reject)), Err(3));
+        assert!(entries.iter().all(|entry| entry.context.is_none()));
+
+        assert_eq!(check(&mut entries, &limit(ServiceLimitAction::Flag)), Ok(()));
+        assert!(entries
+            .iter()
+            .all(|entry| entry.context.as_ref().unwrap().get("multi_service_batch") == Some(&json!(true))));
+    }
+
+    #[test]
+    fn test_unset_limit_accepts_anything() {
+        let mut entries = batch(&["a", "b", "c", "d"]);
+        assert_eq!(check(&mut entries, &BatchServiceLimitConfig::default()), Ok(()));
+    }
+}
@@ -0,0 +1,116 @@
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::fs;
+
+/// A rewrite applied to every stack frame before fingerprinting.
+#[derive(Debug, Clone)]
+pub struct StackRule {
+    pub pattern: Regex,
+    pub replacement: String,
+}
+
+/// Built-in rules for the parts of a frame that change between builds and deploys.
+const DEFAULT_RULES: &[(&str, &str)] = &[
+    // Cache-busting query strings: `app.js?v=3f2a9c:10:5` -> `app.js:10:5`.
+    (r"\?[^\s:)]*", ""),
+    // Content hashes in bundle names: `main.3f2a9c1b.js`, `chunk-4f5e6a7b8c.min.js` -> `main.js`.
+    (r"[.-][0-9a-f]{8,}((?:\.min)?\.(?:m?js|css))\b", "$1"),
+    // Line and column numbers: `:10:5`, `:10`, Python's `line 42`.
+    (r":\d+(?::\d+)?\b", ""),
+    (r"\bline \d+", "line"),
+];
+
+pub fn default_rules() -> Vec<StackRule> {
+    DEFAULT_RULES
+        .iter()
+        .map(|(pattern, replacement)| StackRule {
+            pattern: Regex::new(pattern).expect("built-in stack rule compiles"),
+            replacement: replacement.to_string(),
+        })
+        .collect()
+}
+
+/// The built-in rules followed by any from `path`, one `pattern => replacement` per line.
+/// Blank lines and lines starting with `#` are ignored.
+pub fn load(path: Option<&str>) -> Result<Vec<StackRule>, String> {
+    let mut rules = default_rules();
+    let Some(path) = path else {
+        return Ok(rules);
+    };
+    let raw = fs::read_to_string(path).map_err(|e| format!("cannot read stack rules from {}: {}", path, e))?;
+    for line in raw.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let (pattern, replacement) = line
+            .split_once(" => ")
+            .ok_or_else(|| format!("stack rule '{}' is not 'pattern => replacement'", line))?;
+        let pattern = Regex::new(pattern.trim()).map_err(|e| format!("invalid stack rule '{}': {}", line, e))?;
+        rules.push(StackRule {
+            pattern,
+            replacement: replacement.trim().to_string(),
+        });
+    }
+    Ok(rules)
+}
+
+/// Rewrites each frame with `rules`, dropping blank frames and collapsing runs of
+/// identical frames (deep recursion) into one.
+pub fn normalize(stack: &str, rules: &[StackRule]) -> String {
+    let mut frames: Vec<String> = Vec::new();
+    for frame in stack.lines() {
+        let mut frame = frame.trim().to_string();
+        for rule in rules {
+            frame = rule.pattern.replace_all(&frame, rule.replacement.as_str()).into_owned();
+        }
+        if !frame.is_empty() && frames.last() != Some(&frame) {
+            frames.push(frame);
+        }
+    }
+    frames.join("\n")
+}
+
+/// Hex SHA-256 of the normalized stack, equal for stacks that differ only in volatile parts.
+pub fn fingerprint(stack: &str, rules: &[StackRule]) -> String {
+    format!("{:x}", Sha256::digest(normalize(stack, rules).as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STACK: &str = "TypeError: Cannot read properties of undefined (reading 'total')
+    at computeTotal (https://shop.example/static/js/main.3f2a9c1b.js?v=171:2:10432)
+    at renderCart (https://shop.example/static/js/chunk-4f5e6a7b8c.min.js:1:882)
+    at HTMLButtonElement.onClick (https://shop.example/static/js/main.3f2a9c1b.js:2:9911)";
+
+    #[test]
+    fn test_stacks_differing_only_in_volatile_parts_match() {
+        let rules = default_rules();
+        let redeployed = STACK
+            .replace("main.3f2a9c1b.js?v=171:2:10432", "main.9be01d77.js?v=172:2:10587")
+            .replace("chunk-4f5e6a7b8c.min.js:1:882", "chunk-0a1b2c3d4e.min.js:1:901")
+            .replace("main.3f2a9c1b.js:2:9911", "main.9be01d77.js:3:12");
+
+        assert_eq!(fingerprint(STACK, &rules), fingerprint(&redeployed, &rules));
+        assert_eq!(
+            normalize(STACK, &rules).lines().nth(1),
+            Some("at computeTotal (https://shop.example/static/js/main.js)")
+        );
+    }
+
+    #[test]
+    fn test_different_stacks_do_not_match() {
+        let rules = default_rules();
+        let other_function = STACK.replace("at renderCart", "at renderCheckout");
+        let other_error = STACK.replace("reading 'total'", "reading 'items'");
+
+        assert_ne!(fingerprint(STACK, &rules), fingerprint(&other_function, &rules));
+        assert_ne!(fingerprint(STACK, &rules), fingerprint(&other_error, &rules));
+    }
+
+    #[test]
+    fn test_recursive_frames_are_collapsed() {
+        let rules = default_rules();
+        let shallow = "RangeError: too deep\n    at walk (tree.js:4:3)\n    at main (index.js:1:1)";
+        let deep = "RangeError: too deep\n    at walk (tree.js:4:3)\n    at walk (tree.js:4:3)\n    at walk (tree.js:4:3)\n    at main (index.js:1:1)";
+        assert_eq!(fingerprint(shallow, &rules), fingerprint(deep, &rules));
+    }
+}
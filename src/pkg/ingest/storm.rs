@@ -0,0 +1,192 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::models::LogEntry;
+use crate::pkg::config::StormConfig;
+use crate::pkg::metrics::services::ServiceMetrics;
+use crate::pkg::metrics::LOG_STORMS_DETECTED;
+use crate::pkg::time;
+
+/// Digit runs vary between otherwise identical messages (ids, counters, durations).
+static DIGITS: Lazy<Regex> = Lazy::new(|| Regex::new(r"\d+").expect("valid digits regex"));
+
+/// Hex SHA-256 identifying near-identical entries: same service, level, error name and
+/// stack fingerprint, and the same message once digits are ignored.
+pub fn signature(entry: &LogEntry) -> String {
+    let mut hasher = Sha256::new();
+    for part in [
+        entry.service.as_str(),
+        entry.level.as_str(),
+        entry.error_name.as_deref().unwrap_or_default(),
+        entry.stack_fingerprint.as_deref().unwrap_or_default(),
+        &DIGITS.replace_all(&entry.message, "#"),
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+struct Collapse {
+    until: Instant,
+    started_at: String,
+    suppressed: u64,
+    /// The latest suppressed entry.
+    sample: Option<LogEntry>,
+}
+
+#[derive(Default)]
+struct SignatureState {
+    /// Arrivals within the window, oldest first.
+    arrivals: VecDeque<Instant>,
+    collapse: Option<Collapse>,
+}
+
+/// Watches for signatures arriving faster than `threshold` per `window` and collapses
+/// each such storm for `cooldown`: one representative is stored when it starts and one
+/// summary (a sample carrying the suppressed count) once the cooldown is over.
+pub struct StormDetector {
+    threshold: usize,
+    window: Duration,
+    cooldown: Duration,
+    signatures: Mutex<HashMap<String, SignatureState>>,
+}
+
+impl StormDetector {
+    pub fn new(config: &StormConfig) -> Self {
+        Self {
+            threshold: config.threshold,
+            window: Duration::from_secs(config.window_secs),
+            cooldown: Duration::from_secs(config.cooldown_secs),
+            signatures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the entry to store, or `None` when its signature is collapsed. The entry
+    /// that starts a storm is kept as its representative, marked with `context.storm`.
+    pub fn observe(&self, mut entry: LogEntry, now: Instant, labels: &ServiceMetrics) -> Option<LogEntry> {
+        let signature = signature(&entry);
+        let mut signatures = self.signatures.lock().unwrap();
+        let state = signatures.entry(signature.clone()).or_default();
+
+        if let Some(collapse) = state.collapse.as_mut().filter(|collapse| now < collapse.until) {
+            collapse.suppressed += 1;
+            collapse.sample = Some(entry);
+            return None;
+        }
+
+        while state.arrivals.front().is_some_and(|at| now.duration_since(*at) >= self.window) {
+            state.arrivals.pop_front();
+        }
+        state.arrivals.push_back(now);
+        if state.arrivals.len() <= self.threshold || state.collapse.is_some() {
+            return Some(entry);
+        }
+
+        LOG_STORMS_DETECTED.with_label_values(&[&labels.label(&entry.service)]).inc();
+        state.arrivals.clear();
+        state.collapse = Some(Collapse {
+            until: now + self.cooldown,
+            started_at: time::to_storage_string(chrono::Utc::now()),
+            suppressed: 0,
+            sample: None,
+        });
+        entry
+            .context
+            .get_or_insert_with(Default::default)
+            .insert("storm".to_string(), json!({ "signature": signature, "collapsed": true }));
+        Some(entry)
+    }
+
+    /// Ends the storms whose cooldown is over, returning a summary for each that
+    /// suppressed anything: its latest entry, under a new id, with `context.storm` holding
+    /// the suppressed count. Also forgets signatures that have gone quiet.
+    pub fn release_expired(&self, now: Instant) -> Vec<LogEntry> {
+        let mut summaries = Vec::new();
+        let mut signatures = self.signatures.lock().unwrap();
+        signatures.retain(|signature, state| {
+            if state.collapse.as_ref().is_some_and(|collapse| now >= collapse.until) {
+                let collapse = state.collapse.take().expect("checked above");
+                if let Some(mut sample) = collapse.sample {
+                    sample.id = Some(uuid::Uuid::new_v4().to_string());
+                    sample.context.get_or_insert_with(Default::default).insert(
+                        "storm".to_string(),
+                        json!({
+                            "signature": signature,
+                            "suppressed": collapse.suppressed,
+                            "since": collapse.started_at,
+                            "until": time::to_storage_string(chrono::Utc::now()),
+                        }),
+                    );
+                    summaries.push(sample);
+                }
+            }
+            let recent = state.arrivals.back().is_some_and(|at| now.duration_since(*at) < self.window);
+            state.collapse.is_some() || recent
+        });
+        summaries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn marker(entry: &LogEntry) -> Option<&Value> {
+        entry.context.as_ref()?.get("storm")
+    }
+
+    fn entry(message: &str) -> LogEntry {
+        serde_json::from_value(json!({
+            "level": "error", "message": message, "timestamp": "2024-01-01T00:00:00Z", "service": "checkout"
+        }))
+        .unwrap()
+    }
+
+    fn detector() -> StormDetector {
+        StormDetector::new(&StormConfig {
+            enabled: true,
+            threshold: 3,
+            window_secs: 10,
+            cooldown_secs: 30,
+        })
+    }
+
+    #[test]
+    fn test_near_identical_messages_share_a_signature() {
+        assert_eq!(signature(&entry("retry 17 failed after 250ms")), signature(&entry("retry 18 failed after 990ms")));
+        assert_ne!(signature(&entry("retry 17 failed")), signature(&entry("payment 17 failed")));
+    }
+
+    #[test]
+    fn test_storm_collapses_and_releases_after_cooldown() {
+        let storms = detector();
+        let labels = ServiceMetrics::new(10);
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+
+        let kept: Vec<Option<LogEntry>> =
+            (0..10).map(|i| storms.observe(entry(&format!("retry {} failed", i)), at(i * 100), &labels)).collect();
+        assert!(kept[..3].iter().all(|entry| entry.as_ref().is_some_and(|entry| marker(entry).is_none())));
+        assert_eq!(marker(kept[3].as_ref().unwrap()).unwrap()["collapsed"], json!(true), "the fourth starts the storm");
+        assert!(kept[4..].iter().all(Option::is_none));
+
+        assert!(storms.observe(entry("inventory lookup failed"), at(1_000), &labels).is_some(), "other signatures are unaffected");
+        assert!(storms.release_expired(at(29_000)).is_empty(), "still cooling down");
+
+        let summaries = storms.release_expired(at(31_000));
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(marker(&summaries[0]).unwrap()["suppressed"], json!(6));
+        assert_eq!(summaries[0].message, "retry 9 failed", "the latest suppressed entry is the sample");
+
+        let after = storms.observe(entry("retry 10 failed"), at(31_500), &labels).expect("released signatures are kept again");
+        assert!(marker(&after).is_none());
+        assert!(storms.release_expired(at(45_000)).is_empty());
+    }
+}
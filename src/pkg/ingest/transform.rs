@@ -0,0 +1,192 @@
+use rhai::{Dynamic, Engine, Scope, AST};
+use std::cell::Cell;
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::models::LogEntry;
+
+/// Longest string, array or map a script may build, so it can't exhaust memory.
+const MAX_VALUE_SIZE: usize = 64 * 1024;
+
+thread_local! {
+    /// When the script running on this thread has to stop; checked by the engine's
+    /// progress callback.
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// A per-entry transformation supplied by operators (`TRANSFORM_SCRIPT`), written in
+/// [Rhai](https://rhai.rs). The script sees the entry as the map `entry`, with the
+/// entry's JSON (camelCase) keys, and may change it in place; setting `drop` to `true`
+/// drops the entry:
+///
+/// ```text
+/// if entry.context == () { entry.context = #{}; }
+/// entry.context.region = "eu-west-1";
+/// if entry.service == "checkout" { entry.context.team = #{ name: "payments" }; }
+/// if entry.message.starts_with("healthcheck") { drop = true; }
+/// ```
+///
+/// Each run is limited to `max_operations` engine operations and to the time budget,
+/// and can't print, import modules or `eval`. An entry whose run fails or runs out of
+/// either is kept unchanged.
+#[derive(Debug, Clone)]
+pub struct Script {
+    engine: Arc<Engine>,
+    ast: Arc<AST>,
+    budget: Duration,
+}
+
+impl Script {
+    /// Reads and compiles the script at `path`.
+    pub fn load(path: &str, max_operations: u64, budget: Duration) -> Result<Self, String> {
+        let raw = fs::read_to_string(path).map_err(|e| format!("cannot read transform script {}: {}", path, e))?;
+        Self::parse(&raw, max_operations, budget)
+    }
+
+    pub fn parse(source: &str, max_operations: u64, budget: Duration) -> Result<Self, String> {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(max_operations.max(1))
+            .set_max_modules(0)
+            .set_max_call_levels(16)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(MAX_VALUE_SIZE)
+            .set_max_array_size(MAX_VALUE_SIZE)
+            .set_max_map_size(MAX_VALUE_SIZE)
+            .on_print(|_| {})
+            .on_debug(|_, _, _| {})
+            .on_progress(|_| {
+                let expired = DEADLINE.get().is_some_and(|deadline| Instant::now() >= deadline);
+                expired.then(|| Dynamic::from("time budget exceeded"))
+            });
+        engine.disable_symbol("eval");
+        let ast = engine
+            .compile(source)
+            .map_err(|e| format!("transform script: {}", e))?;
+        Ok(Self {
+            engine: Arc::new(engine),
+            ast: Arc::new(ast),
+            budget,
+        })
+    }
+
+    /// Runs the script over `entry`, returning `None` if it dropped the entry. An entry the
+    /// script can't finish within its limits, or leaves in a shape that is no longer a
+    /// valid entry, is returned unchanged.
+    pub fn apply(&self, entry: LogEntry) -> Option<LogEntry> {
+        let value = match rhai::serde::to_dynamic(&entry) {
+            Ok(value) => value,
+            Err(e) => {
+                warn!("Could not hand entry {:?} to the transform script ({}); keeping it unchanged", entry.id, e);
+                return Some(entry);
+            }
+        };
+        let mut scope = Scope::new();
+        scope.push("entry", value).push("drop", false);
+
+        DEADLINE.set(Some(Instant::now() + self.budget));
+        let result = self.engine.run_ast_with_scope(&mut scope, &self.ast);
+        DEADLINE.set(None);
+        if let Err(e) = result {
+            warn!("Transform script failed on entry {:?} ({}); keeping it unchanged", entry.id, e);
+            return Some(entry);
+        }
+
+        if scope.get_value::<bool>("drop") == Some(true) {
+            return None;
+        }
+        let value = scope.get_value::<Dynamic>("entry").unwrap_or_default();
+        match rhai::serde::from_dynamic::<LogEntry>(&value) {
+            Ok(mut transformed) => {
+                // Server-side enrichment isn't read back from the script.
+                transformed.source_asn = entry.source_asn;
+                transformed.source_org = entry.source_org;
+                transformed.stack_fingerprint = entry.stack_fingerprint;
+                transformed.content_hash = entry.content_hash;
+                Some(transformed)
+            }
+            Err(e) => {
+                warn!("Transform script produced an invalid entry from {:?} ({}); keeping it unchanged", entry.id, e);
+                Some(entry)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn script(source: &str) -> Script {
+        Script::parse(source, 10_000, Duration::from_millis(50)).unwrap()
+    }
+
+    fn entry(service: &str, message: &str) -> LogEntry {
+        serde_json::from_value(json!({
+            "level": "info", "message": message, "timestamp": "2024-01-01T00:00:00Z", "service": service
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_script_adds_context() {
+        let script = script(
+            r#"
+            // tag payments traffic
+            if entry.context == () { entry.context = #{}; }
+            entry.context.region = "eu-west-1";
+            if entry.service == "checkout" { entry.context.team = #{ name: "payments" }; }
+            "#,
+        );
+
+        let tagged = script.apply(entry("checkout", "paid")).unwrap();
+        let context = tagged.context.unwrap();
+        assert_eq!(context["region"], json!("eu-west-1"));
+        assert_eq!(context["team"], json!({ "name": "payments" }));
+
+        let other = script.apply(entry("search", "query")).unwrap();
+        assert!(!other.context.unwrap().contains_key("team"));
+    }
+
+    #[test]
+    fn test_script_drops_matching_entries() {
+        let script = script(
+            r#"
+            if entry.message.starts_with("healthcheck") { drop = true; }
+            if entry.context != () { entry.context.remove("token"); }
+            "#,
+        );
+
+        assert!(script.apply(entry("web", "healthcheck ok")).is_none());
+        let mut kept = entry("web", "page view");
+        kept.context = Some([("token".to_string(), json!("abc"))].into_iter().collect());
+        let kept = script.apply(kept).unwrap();
+        assert_eq!(kept.message, "page view");
+        assert!(kept.context.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_results_and_scripts() {
+        let script = script(r#"entry.level = "catastrophic";"#);
+        assert_eq!(script.apply(entry("web", "m")).unwrap().level.as_str(), "info", "kept unchanged");
+
+        let err = Script::parse("if entry.service = { drop = true; }", 100, Duration::from_millis(5)).unwrap_err();
+        assert!(err.starts_with("transform script"), "{}", err);
+        assert!(Script::parse(r#"eval("drop = true")"#, 100, Duration::from_millis(5)).is_err());
+    }
+
+    #[test]
+    fn test_runaway_scripts_are_stopped() {
+        let looping = "loop { entry.message += \"x\"; }";
+        let by_operations = Script::parse(looping, 1_000, Duration::from_secs(5)).unwrap();
+        assert_eq!(by_operations.apply(entry("web", "m")).unwrap().message, "m");
+
+        let by_time = Script::parse("loop { }", u64::MAX, Duration::from_millis(20)).unwrap();
+        let started = Instant::now();
+        assert_eq!(by_time.apply(entry("web", "m")).unwrap().message, "m");
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
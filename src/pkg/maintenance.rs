@@ -0,0 +1,58 @@
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::warn;
+
+/// Shared maintenance-mode switch. While on, ingest is refused and the background
+/// processor holds off writing, leaving batches queued. Cheap to clone; clones share
+/// one switch.
+#[derive(Clone)]
+pub struct MaintenanceMode {
+    state: Arc<watch::Sender<bool>>,
+}
+
+impl MaintenanceMode {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            state: Arc::new(watch::Sender::new(enabled)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        *self.state.borrow()
+    }
+
+    pub fn set(&self, enabled: bool) {
+        if self.state.send_replace(enabled) != enabled {
+            warn!("Maintenance mode {}.", if enabled { "enabled" } else { "disabled" });
+        }
+    }
+
+    /// Resolves immediately when maintenance mode is off, otherwise once it is turned off.
+    pub async fn wait_until_off(&self) {
+        let mut state = self.state.subscribe();
+        // The sender lives in `self`, so the channel can't close while we wait.
+        let _ = state.wait_for(|enabled| !enabled).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_waiters_resume_when_turned_off() {
+        let maintenance = MaintenanceMode::new(true);
+        let waiter = tokio::spawn({
+            let maintenance = maintenance.clone();
+            async move { maintenance.wait_until_off().await }
+        });
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished(), "writes stay paused while enabled");
+
+        maintenance.set(false);
+        tokio::time::timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap();
+        assert!(!maintenance.is_enabled());
+    }
+}
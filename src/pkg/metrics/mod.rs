@@ -0,0 +1,242 @@
+use once_cell::sync::Lazy;
+use prometheus::{core::Collector, Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+use std::future::Future;
+
+pub mod services;
+pub mod statsd;
+
+/// Registry holding every metric the service exports.
+pub static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+/// Log entries through ingest validation, by `received`, `accepted` or `rejected`.
+pub static INGEST_ENTRIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("eagle_ingest_entries_total", "Log entries received at ingest, by outcome"),
+            &["outcome"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Validated batches handed to a queue (`queued`) or turned away (`dropped`): shed
+/// under load, or refused because the queue was closed.
+pub static INGEST_BATCHES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("eagle_ingest_batches_total", "Ingest batches by whether they were queued or dropped"),
+            &["outcome"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Batches waiting in each ingest queue (`live`, `bulk`, `priority`).
+pub static QUEUE_DEPTH: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register(
+        IntGaugeVec::new(
+            Opts::new("eagle_queue_depth", "Batches waiting in each ingest queue"),
+            &["queue"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Batch writes to a sink, by sink and `success` or `failure`. Each retry counts.
+pub static SINK_WRITES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("eagle_sink_writes_total", "Batch writes to a sink, by sink and result"),
+            &["sink", "result"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Time each batch write to a sink took, by sink.
+pub static SINK_WRITE_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new("eagle_sink_write_duration_seconds", "Time to write one batch to a sink"),
+            &["sink"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Services whose context keys exceeded the configured cardinality threshold.
+pub static CONTEXT_KEY_EXPLOSIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "eagle_context_key_explosions_total",
+                "Times a service crossed the distinct context key threshold",
+            ),
+            &["service"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Entries dropped because their user agent matched a bot pattern.
+pub static BOT_ENTRIES_DROPPED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "eagle_bot_entries_dropped_total",
+                "Log entries dropped because they came from a bot user agent",
+            ),
+            &["service"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Entries dropped by per-level sampling (`SAMPLE_RATES`).
+pub static ENTRIES_SAMPLED_OUT: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "eagle_entries_sampled_out_total",
+                "Log entries dropped by per-level sampling",
+            ),
+            &["service", "level"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Entries rejected by their service's JSON Schema.
+pub static SCHEMA_VIOLATIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "eagle_schema_violations_total",
+                "Log entries rejected for not matching their service's schema",
+            ),
+            &["service"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Read-through query cache lookups, by endpoint and `hit` / `miss`.
+pub static QUERY_CACHE_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "eagle_query_cache_requests_total",
+                "Query cache lookups by endpoint and outcome",
+            ),
+            &["endpoint", "result"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Entries whose timestamp went backwards within their batch's session.
+pub static OUT_OF_ORDER_ENTRIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "eagle_out_of_order_entries_total",
+                "Log entries timestamped earlier than a preceding entry from the same session",
+            ),
+            &["service"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Batches spanning more distinct services than `BATCH_MAX_SERVICES`.
+pub static MULTI_SERVICE_BATCHES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "eagle_multi_service_batches_total",
+                "Ingest batches over the distinct-service limit, by the action taken",
+            ),
+            &["action"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Entries still sending a field listed in `DEPRECATED_FIELDS`.
+pub static DEPRECATED_FIELD_USES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "eagle_deprecated_field_uses_total",
+                "Ingested entries using a deprecated payload field, by service and field",
+            ),
+            &["service", "field"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Storms of near-identical entries that switched their signature into collapse mode.
+pub static LOG_STORMS_DETECTED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "eagle_log_storms_detected_total",
+                "Bursts of near-identical log entries collapsed into a summary, by service",
+            ),
+            &["service"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Entries the transform script dropped, by service.
+pub static TRANSFORM_DROPPED_ENTRIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "eagle_transform_dropped_entries_total",
+                "Log entries dropped by the transform script, by service",
+            ),
+            &["service"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Batches set aside for replay after every attempt to write them failed.
+pub static DEAD_LETTERED_BATCHES: Lazy<IntCounter> = Lazy::new(|| {
+    register(
+        IntCounter::new(
+            "eagle_dead_lettered_batches_total",
+            "Batches dead-lettered after every attempt to write them failed",
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Times `write`, a batch write to `sink`, and counts its result.
+pub async fn observe_sink_write<T, E>(sink: &str, write: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    let timer = SINK_WRITE_SECONDS.with_label_values(&[sink]).start_timer();
+    let written = write.await;
+    timer.observe_duration();
+    let result = if written.is_ok() { "success" } else { "failure" };
+    SINK_WRITES.with_label_values(&[sink, result]).inc();
+    written
+}
+
+fn register<C: Collector + Clone + 'static>(collector: C) -> C {
+    REGISTRY
+        .register(Box::new(collector.clone()))
+        .expect("metric registered twice");
+    collector
+}
+
+/// Renders every registered metric in the Prometheus text exposition format.
+pub fn render_prometheus() -> String {
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer) {
+        tracing::error!("Failed to encode metrics: {:?}", e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
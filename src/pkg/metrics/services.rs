@@ -0,0 +1,157 @@
+use crate::models::{LogEntry, LogLevel};
+use crate::pkg::ingest::rejections::Rejection;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use prometheus::{IntCounterVec, Opts};
+use std::collections::HashSet;
+
+use super::register;
+
+/// Label for services beyond the cardinality cap.
+pub const OTHER_SERVICE: &str = "other";
+
+/// Entries accepted by ingest validation, by service.
+pub static SERVICE_ENTRIES_INGESTED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "eagle_service_entries_ingested_total",
+                "Log entries accepted at ingest, by service",
+            ),
+            &["service"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Accepted entries at `error` level or above, by service.
+pub static SERVICE_ERROR_ENTRIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "eagle_service_error_entries_total",
+                "Accepted log entries at error level or above, by service",
+            ),
+            &["service"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Entries rejected during ingest validation, by service.
+pub static SERVICE_ENTRIES_REJECTED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new(
+                "eagle_service_entries_rejected_total",
+                "Log entries rejected at ingest, by service",
+            ),
+            &["service"],
+        )
+        .expect("valid metric"),
+    )
+});
+
+/// Per-service ingest counters. The first `max_services` services seen get their own
+/// label; later ones share `other`, so a misbehaving client can't explode the series count.
+/// Every other metric labelled by service takes its label from [`ServiceMetrics::label`] too.
+#[derive(Debug)]
+pub struct ServiceMetrics {
+    max_services: usize,
+    labeled: Mutex<HashSet<String>>,
+}
+
+impl ServiceMetrics {
+    pub fn new(max_services: usize) -> Self {
+        Self {
+            max_services,
+            labeled: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// The label `service` is counted under.
+    pub fn label(&self, service: &str) -> String {
+        let mut labeled = self.labeled.lock();
+        if labeled.contains(service) {
+            return service.to_string();
+        }
+        if labeled.len() < self.max_services {
+            labeled.insert(service.to_string());
+            return service.to_string();
+        }
+        OTHER_SERVICE.to_string()
+    }
+
+    /// Counts one validated batch: the entries it accepted and the ones it rejected.
+    pub fn record(&self, accepted: &[LogEntry], rejections: &[Rejection]) {
+        for entry in accepted {
+            let label = self.label(&entry.service);
+            SERVICE_ENTRIES_INGESTED.with_label_values(&[&label]).inc();
+            if entry.level >= LogLevel::Error {
+                SERVICE_ERROR_ENTRIES.with_label_values(&[&label]).inc();
+            }
+        }
+        for rejection in rejections {
+            let label = self.label(&rejection.service);
+            SERVICE_ENTRIES_REJECTED.with_label_values(&[&label]).inc();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(service: &str, level: &str) -> LogEntry {
+        serde_json::from_value(json!({
+            "level": level,
+            "message": "hello",
+            "timestamp": "2024-01-01T00:00:00Z",
+            "service": service,
+        }))
+        .unwrap()
+    }
+
+    fn count(counter: &IntCounterVec, service: &str) -> u64 {
+        counter.with_label_values(&[service]).get()
+    }
+
+    #[test]
+    fn test_counts_per_service() {
+        let metrics = ServiceMetrics::new(10);
+        let (checkout, search) = ("metrics-test-checkout", "metrics-test-search");
+        metrics.record(
+            &[entry(checkout, "info"), entry(checkout, "error"), entry(search, "fatal")],
+            &[Rejection {
+                index: 3,
+                service: search.to_string(),
+                reason: "bad".to_string(),
+            }],
+        );
+
+        assert_eq!(count(&SERVICE_ENTRIES_INGESTED, checkout), 2);
+        assert_eq!(count(&SERVICE_ERROR_ENTRIES, checkout), 1);
+        assert_eq!(count(&SERVICE_ENTRIES_REJECTED, checkout), 0);
+        assert_eq!(count(&SERVICE_ENTRIES_INGESTED, search), 1);
+        assert_eq!(count(&SERVICE_ERROR_ENTRIES, search), 1);
+        assert_eq!(count(&SERVICE_ENTRIES_REJECTED, search), 1);
+    }
+
+    #[test]
+    fn test_services_over_the_cap_count_as_other() {
+        let metrics = ServiceMetrics::new(2);
+        let before = count(&SERVICE_ENTRIES_INGESTED, OTHER_SERVICE);
+        let services = ["metrics-cap-a", "metrics-cap-b", "metrics-cap-c", "metrics-cap-d"];
+        let entries: Vec<_> = services.iter().map(|service| entry(service, "info")).collect();
+        metrics.record(&entries, &[]);
+        // Services already labeled keep their label.
+        metrics.record(&[entry("metrics-cap-a", "info")], &[]);
+
+        assert_eq!(count(&SERVICE_ENTRIES_INGESTED, "metrics-cap-a"), 2);
+        assert_eq!(count(&SERVICE_ENTRIES_INGESTED, "metrics-cap-b"), 1);
+        assert_eq!(count(&SERVICE_ENTRIES_INGESTED, "metrics-cap-c"), 0);
+        assert_eq!(count(&SERVICE_ENTRIES_INGESTED, "metrics-cap-d"), 0);
+        assert!(count(&SERVICE_ENTRIES_INGESTED, OTHER_SERVICE) >= before + 2);
+    }
+}
@@ -0,0 +1,145 @@
+use metrics::Label;
+use metrics_exporter_statsd::{StatsdBuilder, StatsdError, StatsdRecorder};
+use prometheus::proto::{MetricFamily, MetricType};
+use prometheus::Registry;
+use std::collections::HashMap;
+use std::net::UdpSocket;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Pushes the contents of a Prometheus registry to a StatsD agent through a
+/// `metrics-exporter-statsd` recorder, which sends DogStatsD tags for the labels.
+///
+/// Counters are sent as deltas since the previous push; gauges as their current value;
+/// histograms as `<name>.sum` / `<name>.count` gauges, since individual observations
+/// are not retained.
+pub struct StatsdExporter {
+    registry: Registry,
+    recorder: StatsdRecorder,
+    previous: HashMap<String, f64>,
+}
+
+impl StatsdExporter {
+    pub fn new(registry: Registry, recorder: StatsdRecorder) -> Self {
+        Self {
+            registry,
+            recorder,
+            previous: HashMap::new(),
+        }
+    }
+
+    /// A recorder sending one datagram per metric to `target`, with names prefixed by
+    /// `prefix`.
+    pub fn udp_recorder(target: &str, prefix: &str) -> Result<StatsdRecorder, StatsdError> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_nonblocking(true)?;
+        let sink = cadence::UdpMetricSink::from(target, socket)?;
+        StatsdBuilder::from("", 0).with_sink(sink).build(Some(prefix))
+    }
+
+    /// Records the current registry contents on the StatsD recorder.
+    pub fn push(&mut self) {
+        let families = self.registry.gather();
+        let previous = &mut self.previous;
+        metrics::with_local_recorder(&self.recorder, || {
+            for family in &families {
+                record_family(family, previous);
+            }
+        });
+    }
+
+    /// Pushes every `interval`, forever.
+    pub async fn run(mut self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            self.push();
+        }
+    }
+}
+
+fn record_family(family: &MetricFamily, previous: &mut HashMap<String, f64>) {
+    let name = family.get_name().to_string();
+    for metric in family.get_metric() {
+        let labels: Vec<Label> = metric
+            .get_label()
+            .iter()
+            .map(|label| Label::new(label.get_name().to_string(), label.get_value().to_string()))
+            .collect();
+
+        match family.get_field_type() {
+            MetricType::COUNTER => {
+                let value = metric.get_counter().get_value();
+                let key = format!("{}{:?}", name, labels);
+                let delta = value - previous.insert(key, value).unwrap_or(0.0);
+                if delta > 0.0 {
+                    metrics::counter!(name.clone(), labels).increment(delta as u64);
+                }
+            }
+            MetricType::GAUGE => {
+                metrics::gauge!(name.clone(), labels).set(metric.get_gauge().get_value());
+            }
+            MetricType::HISTOGRAM => {
+                let histogram = metric.get_histogram();
+                metrics::gauge!(format!("{}.sum", name), labels.clone()).set(histogram.get_sample_sum());
+                metrics::gauge!(format!("{}.count", name), labels).set(histogram.get_sample_count() as f64);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Starts pushing `registry` to `target` every `interval`, unless the recorder can't be
+/// set up.
+pub fn spawn(registry: Registry, target: &str, prefix: &str, interval: Duration) {
+    match StatsdExporter::udp_recorder(target, prefix) {
+        Ok(recorder) => {
+            info!("StatsD exporter pushing to {} every {:?}.", target, interval);
+            tokio::spawn(StatsdExporter::new(registry, recorder).run(interval));
+        }
+        Err(e) => error!("StatsD exporter could not reach {}: {}", target, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{IntCounterVec, IntGauge, Opts};
+
+    #[tokio::test]
+    async fn test_pushes_counter_deltas_and_gauges_over_udp() {
+        let registry = Registry::new();
+        let counter = IntCounterVec::new(Opts::new("ingested_total", "test"), &["service"]).unwrap();
+        let gauge = IntGauge::new("queue_depth", "test").unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        registry.register(Box::new(gauge.clone())).unwrap();
+
+        let receiver = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = receiver.local_addr().unwrap().to_string();
+        let recorder = StatsdExporter::udp_recorder(&target, "eagle").unwrap();
+        let mut exporter = StatsdExporter::new(registry, recorder);
+        let receive = |count: usize| {
+            let receiver = &receiver;
+            async move {
+                let mut received = Vec::new();
+                let mut buf = [0u8; 512];
+                for _ in 0..count {
+                    let (len, _) = receiver.recv_from(&mut buf).await.unwrap();
+                    received.push(String::from_utf8_lossy(&buf[..len]).to_string());
+                }
+                received.sort();
+                received
+            }
+        };
+
+        counter.with_label_values(&["web"]).inc_by(3);
+        gauge.set(7);
+        exporter.push();
+        assert_eq!(receive(2).await, ["eagle.ingested_total:3|c|#service:web", "eagle.queue_depth:7|g"]);
+
+        // Only the increase since the last push is sent.
+        counter.with_label_values(&["web"]).inc_by(2);
+        exporter.push();
+        assert_eq!(receive(2).await, ["eagle.ingested_total:2|c|#service:web", "eagle.queue_depth:7|g"]);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::models::{ErrorCode, ErrorResponse};
+use actix_web::{
+    body::EitherBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+    Error, HttpMessage, HttpResponse,
+};
+use futures::future::{ok, Ready};
+use futures_util::future::LocalBoxFuture;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Alternative to `Authorization: Bearer` for clients that already use that header.
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Rejects requests that don't present one of the configured API keys, either as
+/// `Authorization: Bearer <key>` or `X-API-Key: <key>`.
+///
+/// Paths in the exempt list skip the check; an entry ending in `*` exempts every path
+/// starting with the rest of it.
+#[derive(Clone)]
+pub struct ApiKeyAuth {
+    /// Keyed by SHA-256 digest, so lookups don't compare the secrets byte by byte; the
+    /// value is the service a per-service key is issued to.
+    keys: Arc<HashMap<[u8; 32], Option<String>>>,
+    exempt_paths: Arc<Vec<String>>,
+}
+
+/// The service whose key authenticated the request, in the request extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticatedService(pub String);
+
+/// Hex SHA-256 of the key that authenticated the request, in the request extensions,
+/// so the key can identify the client without being kept around.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticatedKey(pub String);
+
+impl ApiKeyAuth {
+    pub fn new<I: IntoIterator<Item = String>>(keys: I, exempt_paths: Vec<String>) -> Self {
+        Self {
+            keys: Arc::new(keys.into_iter().map(|key| (digest(&key), None)).collect()),
+            exempt_paths: Arc::new(exempt_paths),
+        }
+    }
+
+    /// Also accepts each key in `service_keys` (service name to key), recording the
+    /// request as coming from that service.
+    pub fn with_service_keys(mut self, service_keys: &HashMap<String, String>) -> Self {
+        let keys = Arc::make_mut(&mut self.keys);
+        for (service, key) in service_keys {
+            keys.insert(digest(key), Some(service.clone()));
+        }
+        self
+    }
+
+    fn is_exempt(&self, path: &str) -> bool {
+        self.exempt_paths.iter().any(|exempt| match exempt.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => path == exempt,
+        })
+    }
+
+    /// The matching key's digest and service (`None` for a key without one), or `None`
+    /// when no presented key matches.
+    fn authenticate(&self, req: &ServiceRequest) -> Option<([u8; 32], Option<String>)> {
+        let headers = req.headers();
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let api_key = headers.get(API_KEY_HEADER).and_then(|value| value.to_str().ok());
+        [bearer, api_key]
+            .into_iter()
+            .flatten()
+            .find_map(|key| {
+                let digest = digest(key.trim());
+                self.keys.get(&digest).map(|service| (digest, service.clone()))
+            })
+    }
+}
+
+fn digest(key: &str) -> [u8; 32] {
+    Sha256::digest(key.as_bytes()).into()
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ApiKeyAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ApiKeyAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ApiKeyAuthMiddleware {
+            service,
+            auth: self.clone(),
+        })
+    }
+}
+
+pub struct ApiKeyAuthMiddleware<S> {
+    service: S,
+    auth: ApiKeyAuth,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiKeyAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let authenticated = self.auth.authenticate(&req);
+        if let Some((digest, service)) = &authenticated {
+            let key_hash = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+            req.extensions_mut().insert(AuthenticatedKey(key_hash));
+            if let Some(service) = service {
+                req.extensions_mut().insert(AuthenticatedService(service.clone()));
+            }
+        }
+        if authenticated.is_some() || self.auth.is_exempt(req.path()) {
+            let fut = self.service.call(req);
+            return Box::pin(async move { Ok(fut.await?.map_into_left_body()) });
+        }
+
+        let response = HttpResponse::from(ErrorResponse::new(ErrorCode::Unauthorized, "Missing or invalid API key"));
+        Box::pin(async move { Ok(req.into_response(response).map_into_right_body()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, web, App, HttpRequest};
+
+    #[actix_web::test]
+    async fn test_requires_a_valid_key_outside_exempt_paths() {
+        let auth = ApiKeyAuth::new(
+            ["key-one".to_string(), "key-two".to_string()],
+            vec!["/health".to_string(), "/public/*".to_string()],
+        );
+        let app = test::init_service(
+            App::new()
+                .wrap(auth)
+                .route("/ingest", web::post().to(HttpResponse::Accepted))
+                .route("/health", web::get().to(HttpResponse::Ok))
+                .route("/public/status", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/ingest")
+            .insert_header((header::AUTHORIZATION, "Bearer key-one"));
+        assert_eq!(test::call_service(&app, req.to_request()).await.status(), StatusCode::ACCEPTED);
+        let req = test::TestRequest::post().uri("/ingest").insert_header((API_KEY_HEADER, "key-two"));
+        assert_eq!(test::call_service(&app, req.to_request()).await.status(), StatusCode::ACCEPTED);
+
+        let res = test::call_service(&app, test::TestRequest::post().uri("/ingest").to_request()).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["status"], "failed");
+        assert_eq!(body["code"], "unauthorized");
+        assert_eq!(body["message"], "Missing or invalid API key");
+
+        let req = test::TestRequest::post()
+            .uri("/ingest")
+            .insert_header((header::AUTHORIZATION, "Bearer key-three"));
+        assert_eq!(test::call_service(&app, req.to_request()).await.status(), StatusCode::UNAUTHORIZED);
+
+        for path in ["/health", "/public/status"] {
+            let res = test::call_service(&app, test::TestRequest::get().uri(path).to_request()).await;
+            assert_eq!(res.status(), StatusCode::OK, "{}", path);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_service_keys_record_their_service() {
+        let service_keys = HashMap::from([("checkout".to_string(), "checkout-key".to_string())]);
+        let auth = ApiKeyAuth::new(["shared-key".to_string()], Vec::new()).with_service_keys(&service_keys);
+        let app = test::init_service(App::new().wrap(auth).route(
+            "/whoami",
+            web::get().to(|req: HttpRequest| async move {
+                match req.extensions().get::<AuthenticatedService>() {
+                    Some(service) => HttpResponse::Ok().body(service.0.clone()),
+                    None => HttpResponse::Ok().body("anonymous"),
+                }
+            }),
+        ))
+        .await;
+        let whoami = |key: &'static str| {
+            let app = &app;
+            async move {
+                let req = test::TestRequest::get().uri("/whoami").insert_header((API_KEY_HEADER, key));
+                test::call_and_read_body(app, req.to_request()).await
+            }
+        };
+
+        assert_eq!(whoami("checkout-key").await, "checkout");
+        assert_eq!(whoami("shared-key").await, "anonymous");
+    }
+}
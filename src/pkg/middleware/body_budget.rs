@@ -0,0 +1,157 @@
+use crate::models::{ErrorCode, ErrorResponse};
+use actix_web::{
+    body::EitherBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+    Error, HttpResponse,
+};
+use futures::future::{ok, Ready};
+use futures_util::future::LocalBoxFuture;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Caps the total bytes of request bodies being buffered at once, across all workers.
+///
+/// Each request reserves its `Content-Length` (or `unknown_length_bytes` when the body
+/// is chunked) for as long as it is being handled; when the budget is used up the
+/// request is turned away with a 503 before any of its body is read.
+#[derive(Clone)]
+pub struct BodyBudget {
+    permits: Arc<Semaphore>,
+    capacity: usize,
+    unknown_length_bytes: usize,
+}
+
+impl BodyBudget {
+    pub fn new(capacity: usize, unknown_length_bytes: usize) -> Self {
+        let capacity = capacity.clamp(1, Semaphore::MAX_PERMITS);
+        Self {
+            permits: Arc::new(Semaphore::new(capacity)),
+            capacity,
+            unknown_length_bytes,
+        }
+    }
+
+    /// Reserves `bytes` of the budget, or `None` if not enough is free. A body larger
+    /// than the whole budget reserves all of it, so it can still get through alone.
+    pub fn reserve(&self, bytes: usize) -> Option<OwnedSemaphorePermit> {
+        let bytes = bytes.min(self.capacity).min(u32::MAX as usize) as u32;
+        self.permits.clone().try_acquire_many_owned(bytes).ok()
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for BodyBudget
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = BodyBudgetMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(BodyBudgetMiddleware {
+            service,
+            budget: self.clone(),
+        })
+    }
+}
+
+pub struct BodyBudgetMiddleware<S> {
+    service: S,
+    budget: BodyBudget,
+}
+
+impl<S, B> Service<ServiceRequest> for BodyBudgetMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let content_length = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok());
+        let chunked = req.headers().contains_key(header::TRANSFER_ENCODING);
+        let bytes = match content_length {
+            Some(length) => length,
+            None if chunked => self.budget.unknown_length_bytes,
+            None => 0,
+        };
+        if bytes == 0 {
+            let fut = self.service.call(req);
+            return Box::pin(async move { Ok(fut.await?.map_into_left_body()) });
+        }
+
+        match self.budget.reserve(bytes) {
+            Some(permit) => {
+                let fut = self.service.call(req);
+                Box::pin(async move {
+                    let res = fut.await;
+                    drop(permit);
+                    Ok(res?.map_into_left_body())
+                })
+            }
+            None => {
+                let response = HttpResponse::from(
+                    ErrorResponse::new(ErrorCode::Busy, "Too many request bodies in flight. Retry shortly").retrying_after(1),
+                );
+                Box::pin(async move { Ok(req.into_response(response).map_into_right_body()) })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, web, App, HttpResponse};
+
+    #[actix_web::test]
+    async fn test_exhausted_budget_returns_503() {
+        let budget = BodyBudget::new(1000, 1000);
+        let app = test::init_service(
+            App::new()
+                .wrap(budget.clone())
+                .route("/ingest", web::post().to(|body: web::Bytes| async move {
+                    HttpResponse::Ok().body(body)
+                })),
+        )
+        .await;
+        let post = || async {
+            let req = test::TestRequest::post()
+                .uri("/ingest")
+                .insert_header((header::CONTENT_LENGTH, 200))
+                .set_payload(vec![b'x'; 200])
+                .to_request();
+            test::call_service(&app, req).await
+        };
+
+        // Stand-in for bodies already being buffered by other requests.
+        let in_flight = budget.reserve(900).unwrap();
+        let res = post().await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers().get(header::RETRY_AFTER).unwrap(), "1");
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["code"], "busy");
+
+        drop(in_flight);
+        assert_eq!(post().await.status(), StatusCode::OK);
+        // The permit is returned once the request completes.
+        assert!(budget.reserve(1000).is_some());
+    }
+}
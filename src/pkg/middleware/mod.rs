@@ -1,2 +1,6 @@
+pub mod auth;
+pub mod body_budget;
 pub mod cors;
 pub mod rate_limiter;
+pub mod request_id;
+pub mod signature;
@@ -1,30 +1,261 @@
+use crate::models::{ErrorCode, ErrorResponse};
+use crate::pkg::client_ip::client_ip;
+use crate::pkg::config::RateLimitAlgorithm;
+use crate::pkg::db::postgres;
+use crate::pkg::middleware::auth::AuthenticatedKey;
 use crate::pkg::utils::bucket::TokenBucket;
+use crate::pkg::utils::sliding_window::SlidingWindow;
 use actix_web::{
+    body::EitherBody,
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
-    error::ErrorTooManyRequests,
-    Error,
+    Error, HttpMessage, HttpResponse,
 };
 use futures::future::{ok, Ready};
 use futures_util::future::LocalBoxFuture;
+use sqlx::{Pool, Postgres};
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
 use std::task::{Context, Poll};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info};
+
+/// Capacity and refill interval of one client's limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit {
+    pub capacity: i64,
+    pub fill_interval: Duration,
+}
+
+/// Per-tenant limits from the `tenant_limits` table, keyed by the hex SHA-256 of the
+/// tenant's API key. Cheap to clone; every clone sees each refresh.
+#[derive(Debug, Clone, Default)]
+pub struct TenantLimits {
+    limits: Arc<RwLock<HashMap<String, Limit>>>,
+}
+
+impl TenantLimits {
+    fn get(&self, key_hash: &str) -> Option<Limit> {
+        self.limits.read().unwrap().get(key_hash).copied()
+    }
+
+    /// Replaces the cached limits with the table's contents, returning how many there are.
+    pub async fn refresh(&self, pool: &Pool<Postgres>) -> Result<usize, sqlx::Error> {
+        let limits: HashMap<String, Limit> = postgres::load_tenant_limits(pool)
+            .await?
+            .into_iter()
+            .map(|(key_hash, capacity, fill_interval_secs)| {
+                let limit = Limit {
+                    capacity,
+                    fill_interval: Duration::from_secs(fill_interval_secs.max(1) as u64),
+                };
+                (key_hash, limit)
+            })
+            .collect();
+        let count = limits.len();
+        *self.limits.write().unwrap() = limits;
+        Ok(count)
+    }
+
+    /// Refreshes every `interval`, keeping the previous limits when a refresh fails.
+    pub async fn refresh_every(self, pool: Arc<Pool<Postgres>>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match self.refresh(&pool).await {
+                Ok(count) => info!("Loaded {} tenant rate limits.", count),
+                Err(e) => error!("Failed to refresh tenant rate limits: {:?}", e),
+            }
+        }
+    }
+}
+
+/// One client's limiter state under the configured algorithm.
+#[derive(Clone)]
+enum ClientState {
+    Bucket(Arc<Mutex<TokenBucket>>),
+    Window(Arc<Mutex<SlidingWindow>>),
+}
+
+impl ClientState {
+    fn new(algorithm: RateLimitAlgorithm, limit: Limit) -> Self {
+        match algorithm {
+            RateLimitAlgorithm::TokenBucket => ClientState::Bucket(TokenBucket::new(limit.fill_interval, limit.capacity)),
+            RateLimitAlgorithm::SlidingWindow => {
+                ClientState::Window(SlidingWindow::new(limit.fill_interval, limit.capacity.max(0) as usize))
+            }
+        }
+    }
+
+    /// Debits `cost`, or returns how long to wait before retrying.
+    fn take(&self, cost: i64) -> Result<(), Duration> {
+        match self {
+            ClientState::Bucket(bucket) => {
+                let mut bucket = bucket.lock().unwrap();
+                if bucket.take_available(cost) {
+                    Ok(())
+                } else {
+                    Err(bucket.retry_after(cost))
+                }
+            }
+            ClientState::Window(window) => {
+                let mut window = window.lock().unwrap();
+                if window.take_available(cost.max(0) as usize) {
+                    Ok(())
+                } else {
+                    Err(window.retry_after(cost.max(0) as usize))
+                }
+            }
+        }
+    }
+}
+
+/// A client's state along with the limit it was created for, so a changed tenant limit
+/// takes effect with a fresh bucket, and when it was last used.
+struct Client {
+    limit: Limit,
+    state: ClientState,
+    last_access: Instant,
+}
+
+type Clients = Arc<Mutex<HashMap<String, Client>>>;
+
+/// Client buckets shared by the limiters of every worker, so a client's limit holds
+/// across workers and `/admin/status` can count them. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct Buckets(Clients);
+
+impl Buckets {
+    pub fn count(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    /// Drops clients not seen for `idle_ttl` as of `now`, returning how many. A client is
+    /// also kept until its limit's `fill_interval` has passed, by when its bucket would
+    /// have refilled anyway, so eviction never hands a drained client a full one early.
+    /// Only the map lock is taken: a request clones its client's state and releases the
+    /// map before locking the bucket, so an evicted bucket still in use just finishes
+    /// that request and the client's next one starts a fresh, full bucket.
+    pub fn evict_idle(&self, idle_ttl: Duration, now: Instant) -> usize {
+        let mut clients = self.0.lock().unwrap();
+        let before = clients.len();
+        clients.retain(|_, client| {
+            now.saturating_duration_since(client.last_access) < idle_ttl.max(client.limit.fill_interval)
+        });
+        before - clients.len()
+    }
+
+    /// Evicts idle clients every `interval`, so one-off addresses don't accumulate.
+    pub async fn sweep_every(self, idle_ttl: Duration, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let evicted = self.evict_idle(idle_ttl, Instant::now());
+            if evicted > 0 {
+                debug!("Evicted {} idle rate limit buckets.", evicted);
+            }
+        }
+    }
+}
+
+/// Picks the bucket a request is counted against.
+pub type ClientKey = Arc<dyn Fn(&ServiceRequest) -> String + Send + Sync>;
+
+/// Every client address gets its own bucket. The address is the peer's, or the forwarded
+/// one when the peer is one of `trusted_proxies`, so a made-up `X-Forwarded-For` doesn't
+/// get a fresh bucket.
+pub fn by_ip(trusted_proxies: Vec<IpAddr>) -> impl Fn(&ServiceRequest) -> String + Send + Sync {
+    move |req| match client_ip(req.request(), &trusted_proxies) {
+        Some(ip) => ip.to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+/// [`by_ip`], for a limiter in front of `ApiKeyAuth`; prefixed so its buckets stay apart
+/// from those of a limiter behind it sharing the same [`Buckets`].
+pub fn by_ip_before_auth(trusted_proxies: Vec<IpAddr>) -> impl Fn(&ServiceRequest) -> String + Send + Sync {
+    let by_ip = by_ip(trusted_proxies);
+    move |req| format!("ip:{}", by_ip(req))
+}
+
+/// Requests authenticated by `ApiKeyAuth` share a bucket per key, wherever they come
+/// from; others fall back to [`by_ip`]. Needs the limiter to run inside `ApiKeyAuth`.
+pub fn by_api_key_or_ip(trusted_proxies: Vec<IpAddr>) -> impl Fn(&ServiceRequest) -> String + Send + Sync {
+    let by_ip = by_ip(trusted_proxies);
+    move |req| match req.extensions().get::<AuthenticatedKey>() {
+        Some(key) => format!("key:{}", key.0),
+        None => by_ip(req),
+    }
+}
 
 pub struct RateLimiter {
-    fill_interval: Duration,
-    capacity: i64,
-    buckets: Arc<Mutex<HashMap<String, Arc<Mutex<TokenBucket>>>>>,
+    client_key: ClientKey,
+    algorithm: RateLimitAlgorithm,
+    default_limit: Limit,
+    route_costs: Arc<HashMap<String, i64>>,
+    /// Longest prefix first, so the most specific route wins.
+    route_limits: Arc<Vec<(String, Limit)>>,
+    tenants: Option<TenantLimits>,
+    buckets: Clients,
 }
 
 impl RateLimiter {
-    pub fn new(fill_interval: Duration, capacity: i64) -> Self {
+    /// `client_key` names the bucket for each request, e.g. [`by_ip`].
+    pub fn new<F>(fill_interval: Duration, capacity: i64, client_key: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> String + Send + Sync + 'static,
+    {
         Self {
-            fill_interval,
-            capacity,
+            client_key: Arc::new(client_key),
+            algorithm: RateLimitAlgorithm::TokenBucket,
+            default_limit: Limit { capacity, fill_interval },
+            route_costs: Arc::new(HashMap::new()),
+            route_limits: Arc::new(Vec::new()),
+            tenants: None,
             buckets: Arc::new(Mutex::new(HashMap::new())),
         }
     }
+
+    pub fn with_buckets(mut self, buckets: Buckets) -> Self {
+        self.buckets = buckets.0;
+        self
+    }
+
+    /// Limits requests `ApiKeyAuth` authenticated per API key rather than by the client
+    /// key, at the key's entry in `tenants` or else the default limit. The key is the one
+    /// `ApiKeyAuth` matched, so it is the same bucket whether it came as a Bearer token
+    /// or `X-Api-Key`, and a client can't earn fresh buckets by making keys up. Needs
+    /// the limiter to run inside `ApiKeyAuth`.
+    pub fn with_tenant_limits(mut self, tenants: TenantLimits) -> Self {
+        self.tenants = Some(tenants);
+        self
+    }
+
+    /// Tokens debited per request by exact path; unlisted paths cost one. A cost above
+    /// the bucket capacity is capped so the route is still reachable with a full bucket.
+    pub fn with_route_costs(mut self, route_costs: HashMap<String, i64>) -> Self {
+        self.route_costs = Arc::new(route_costs);
+        self
+    }
+
+    /// Limits paths under `prefix` (the path itself or anything below it) by `limit`
+    /// instead, in a bucket of their own per client. The route limit also replaces a
+    /// tenant's limit on those paths.
+    pub fn route(mut self, prefix: impl Into<String>, limit: Limit) -> Self {
+        let prefix = prefix.into();
+        let routes = Arc::make_mut(&mut self.route_limits);
+        routes.retain(|(existing, _)| *existing != prefix);
+        routes.push((prefix, limit));
+        routes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// With [`RateLimitAlgorithm::SlidingWindow`], `capacity` is the number of requests
+    /// allowed in any `fill_interval`.
+    pub fn with_algorithm(mut self, algorithm: RateLimitAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
 }
 
 impl<S, B> Transform<S, ServiceRequest> for RateLimiter
@@ -33,7 +264,7 @@ where
     S::Future: 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type Transform = RateLimiterMiddleware<S>;
     type InitError = ();
@@ -42,8 +273,12 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ok(RateLimiterMiddleware {
             service,
-            fill_interval: self.fill_interval,
-            capacity: self.capacity,
+            client_key: self.client_key.clone(),
+            algorithm: self.algorithm,
+            default_limit: self.default_limit,
+            route_costs: self.route_costs.clone(),
+            route_limits: self.route_limits.clone(),
+            tenants: self.tenants.clone(),
             buckets: self.buckets.clone(),
         })
     }
@@ -51,9 +286,40 @@ where
 
 pub struct RateLimiterMiddleware<S> {
     service: S,
-    fill_interval: Duration,
-    capacity: i64,
-    buckets: Arc<Mutex<HashMap<String, Arc<Mutex<TokenBucket>>>>>,
+    client_key: ClientKey,
+    algorithm: RateLimitAlgorithm,
+    default_limit: Limit,
+    route_costs: Arc<HashMap<String, i64>>,
+    route_limits: Arc<Vec<(String, Limit)>>,
+    tenants: Option<TenantLimits>,
+    buckets: Clients,
+}
+
+impl<S> RateLimiterMiddleware<S> {
+    /// The key `req` is limited under, and its limit.
+    fn client(&self, req: &ServiceRequest) -> (String, Limit) {
+        let (client, limit) = self.client_limit(req);
+        let path = req.path();
+        let route = self.route_limits.iter().find(|(prefix, _)| {
+            path.strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/') || prefix.ends_with('/'))
+        });
+        match route {
+            Some((prefix, route_limit)) => (format!("{} {}", client, prefix), *route_limit),
+            None => (client, limit),
+        }
+    }
+
+    /// The client's own key and limit, before any route limit.
+    fn client_limit(&self, req: &ServiceRequest) -> (String, Limit) {
+        if let Some(tenants) = self.tenants.as_ref() {
+            if let Some(AuthenticatedKey(key_hash)) = req.extensions().get::<AuthenticatedKey>() {
+                let limit = tenants.get(key_hash).unwrap_or(self.default_limit);
+                return (format!("tenant:{}", key_hash), limit);
+            }
+        }
+        ((self.client_key)(req), self.default_limit)
+    }
 }
 
 impl<S, B> Service<ServiceRequest> for RateLimiterMiddleware<S>
@@ -62,7 +328,7 @@ where
     S::Future: 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
 
@@ -71,33 +337,405 @@ where
     }
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let client_ip = req
-            .connection_info()
-            .realip_remote_addr()
-            .unwrap_or("unknown")
-            .to_string();
+        let (client, limit) = self.client(&req);
+        let cost = self
+            .route_costs
+            .get(req.path())
+            .copied()
+            .unwrap_or(1)
+            .min(limit.capacity);
+        let now = Instant::now();
         let mut buckets = self.buckets.lock().unwrap();
 
-        let bucket = buckets
-            .entry(client_ip.clone())
-            .or_insert_with(|| TokenBucket::new(self.fill_interval, self.capacity));
+        let entry = buckets.entry(client).or_insert_with(|| Client {
+            limit,
+            state: ClientState::new(self.algorithm, limit),
+            last_access: now,
+        });
+        if entry.limit != limit {
+            entry.limit = limit;
+            entry.state = ClientState::new(self.algorithm, limit);
+        }
+        entry.last_access = now;
+        let state = entry.state.clone();
+        drop(buckets);
+
+        match state.take(cost) {
+            Ok(()) => {
+                let fut = self.service.call(req);
+                Box::pin(async move {
+                    let res = fut.await?;
+                    Ok(res.map_into_left_body())
+                })
+            }
+            Err(retry_after) => {
+                let response = too_many_requests(retry_after);
+                Box::pin(async move { Ok(req.into_response(response).map_into_right_body()) })
+            }
+        }
+    }
+}
 
-        let mut bucket = bucket.lock().unwrap();
+/// The 429 sent to a throttled client: `Retry-After` in whole seconds, rounded up so a
+/// client that honours it finds enough tokens waiting for the request's cost.
+fn too_many_requests(retry_after: Duration) -> HttpResponse {
+    let secs = (retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)).max(1);
+    let message = format!("Too many requests. Retry after {} seconds", secs);
+    ErrorResponse::new(ErrorCode::RateLimited, message).retry_after(secs)
+}
 
-        if bucket.take_available(1) {
-            let fut = self.service.call(req);
-            Box::pin(async move {
-                let res = fut.await?;
-                Ok(res)
-            })
-        } else {
-            let retry_after = bucket.retry_after();
-            return Box::pin(async move {
-                Err(ErrorTooManyRequests(format!(
-                    "Too many requests. Retry after {}",
-                    retry_after.as_secs_f64()
-                )))
-            });
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkg::middleware::auth::ApiKeyAuth;
+    use actix_web::{
+        http::{header, StatusCode},
+        test, web, App, HttpResponse,
+    };
+    use sha2::{Digest, Sha256};
+
+    const API_KEY_HEADER: &str = "x-api-key";
+
+    #[actix_web::test]
+    async fn test_ingest_debits_configured_cost() {
+        let costs = HashMap::from([("/ingest".to_string(), 5)]);
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::new(Duration::from_secs(3600), 6, by_ip(Vec::new())).with_route_costs(costs))
+                .route("/ingest", web::post().to(HttpResponse::Accepted))
+                .route("/health", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let status = |req: test::TestRequest| {
+            let app = &app;
+            async move { test::call_service(app, req.to_request()).await.status() }
+        };
+
+        assert_eq!(status(test::TestRequest::post().uri("/ingest")).await, StatusCode::ACCEPTED);
+        // One token left: not enough for another ingest, enough for one health check.
+        assert_eq!(status(test::TestRequest::post().uri("/ingest")).await, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(status(test::TestRequest::get().uri("/health")).await, StatusCode::OK);
+        assert_eq!(status(test::TestRequest::get().uri("/health")).await, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn test_throttled_request_gets_retry_after_and_json() {
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::new(Duration::from_secs(30), 1, by_ip(Vec::new())))
+                .route("/health", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/health").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = test::call_service(&app, test::TestRequest::get().uri("/health").to_request()).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = res.headers().get(header::RETRY_AFTER).unwrap().to_str().unwrap().parse().unwrap();
+        assert!((29..=30).contains(&retry_after), "Retry-After was {}", retry_after);
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["status"], "error");
+        assert_eq!(body["code"], "rate_limited");
+        assert!(body["message"].as_str().unwrap().starts_with("Too many requests"));
+        assert_eq!(body.as_object().unwrap().len(), 3);
+    }
+
+    #[actix_web::test]
+    async fn test_retry_after_covers_the_routes_cost() {
+        let costs = HashMap::from([("/ingest".to_string(), 5)]);
+        for algorithm in [RateLimitAlgorithm::TokenBucket, RateLimitAlgorithm::SlidingWindow] {
+            let app = test::init_service(
+                App::new()
+                    .wrap(
+                        RateLimiter::new(Duration::from_secs(60), 6, by_ip(Vec::new()))
+                            .with_route_costs(costs.clone())
+                            .with_algorithm(algorithm),
+                    )
+                    .route("/ingest", web::post().to(HttpResponse::Accepted)),
+            )
+            .await;
+
+            let res = test::call_service(&app, test::TestRequest::post().uri("/ingest").to_request()).await;
+            assert_eq!(res.status(), StatusCode::ACCEPTED);
+            let res = test::call_service(&app, test::TestRequest::post().uri("/ingest").to_request()).await;
+            assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+            // One of the five tokens is there; the wait is for the other four, not the next one.
+            let retry_after: u64 = res.headers().get(header::RETRY_AFTER).unwrap().to_str().unwrap().parse().unwrap();
+            let expected = match algorithm {
+                RateLimitAlgorithm::TokenBucket => 40,
+                RateLimitAlgorithm::SlidingWindow => 60,
+            };
+            assert!((expected - 1..=expected).contains(&retry_after), "{:?}: Retry-After was {}", algorithm, retry_after);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_tenants_are_limited_by_their_database_limit() {
+        let Some(pool) = postgres::tests::test_pool().await else { return };
+        let gold_key = uuid::Uuid::new_v4().to_string();
+        sqlx::query("INSERT INTO tenant_limits (api_key_sha256, capacity, fill_interval_secs) VALUES ($1, 5, 3600)")
+            .bind(format!("{:x}", Sha256::digest(gold_key.as_bytes())))
+            .execute(&pool)
+            .await
+            .unwrap();
+        let tenants = TenantLimits::default();
+        assert!(tenants.refresh(&pool).await.unwrap() >= 1);
+
+        let plain_key = uuid::Uuid::new_v4().to_string();
+        let auth = ApiKeyAuth::new([gold_key.clone(), plain_key.clone()], vec!["/health".to_string()]);
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::new(Duration::from_secs(3600), 2, by_ip(Vec::new())).with_tenant_limits(tenants))
+                .wrap(auth)
+                .route("/health", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let status = |api_key: String, bearer: bool| {
+            let app = &app;
+            async move {
+                let req = test::TestRequest::get().uri("/health");
+                let req = if bearer {
+                    req.insert_header((header::AUTHORIZATION, format!("Bearer {}", api_key)))
+                } else {
+                    req.insert_header((API_KEY_HEADER, api_key))
+                };
+                test::call_service(app, req.to_request()).await.status()
+            }
+        };
+
+        // Either way of presenting the key draws on the tenant's one bucket.
+        for bearer in [true, false, true, false, true] {
+            assert_eq!(status(gold_key.clone(), bearer).await, StatusCode::OK);
+        }
+        assert_eq!(status(gold_key.clone(), false).await, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(status(gold_key.clone(), true).await, StatusCode::TOO_MANY_REQUESTS);
+
+        // A key without a row gets the default limit, still per key.
+        for _ in 0..2 {
+            assert_eq!(status(plain_key.clone(), false).await, StatusCode::OK);
+        }
+        assert_eq!(status(plain_key.clone(), true).await, StatusCode::TOO_MANY_REQUESTS);
+
+        // Keys that don't authenticate aren't tenants, so making one up for each request
+        // doesn't get a fresh bucket.
+        for _ in 0..2 {
+            assert_eq!(status(uuid::Uuid::new_v4().to_string(), false).await, StatusCode::OK);
+        }
+        assert_eq!(status(uuid::Uuid::new_v4().to_string(), false).await, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn test_api_keys_from_one_address_get_separate_buckets() {
+        let client_keys: [(ClientKey, bool); 2] =
+            [(Arc::new(by_api_key_or_ip(Vec::new())), false), (Arc::new(by_ip(Vec::new())), true)];
+        for (client_key, shared) in client_keys {
+            let auth = ApiKeyAuth::new(["key-a".to_string(), "key-b".to_string()], Vec::new());
+            let app = test::init_service(
+                App::new()
+                    .wrap(RateLimiter::new(Duration::from_secs(3600), 1, move |req| client_key(req)))
+                    .wrap(auth)
+                    .route("/health", web::get().to(HttpResponse::Ok)),
+            )
+            .await;
+            let status = |api_key: &'static str| {
+                let app = &app;
+                async move {
+                    let req = test::TestRequest::get()
+                        .uri("/health")
+                        .peer_addr("203.0.113.7:4000".parse().unwrap())
+                        .insert_header((API_KEY_HEADER, api_key));
+                    test::call_service(app, req.to_request()).await.status()
+                }
+            };
+
+            assert_eq!(status("key-a").await, StatusCode::OK);
+            let expected = if shared { StatusCode::TOO_MANY_REQUESTS } else { StatusCode::OK };
+            assert_eq!(status("key-b").await, expected, "shared bucket: {}", shared);
+            assert_eq!(status("key-a").await, StatusCode::TOO_MANY_REQUESTS);
         }
     }
+
+    #[actix_web::test]
+    async fn test_address_limit_before_auth_throttles_failed_authentication() {
+        let buckets = Buckets::default();
+        let auth = ApiKeyAuth::new(["key-a".to_string(), "key-b".to_string()], Vec::new());
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::new(Duration::from_secs(3600), 1, by_api_key_or_ip(Vec::new())).with_buckets(buckets.clone()))
+                .wrap(auth)
+                .wrap(RateLimiter::new(Duration::from_secs(3600), 4, by_ip_before_auth(Vec::new())).with_buckets(buckets.clone()))
+                .route("/health", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let status = |api_key: &'static str| {
+            let app = &app;
+            async move {
+                let req = test::TestRequest::get()
+                    .uri("/health")
+                    .peer_addr("203.0.113.7:4000".parse().unwrap())
+                    .insert_header((API_KEY_HEADER, api_key));
+                test::call_service(app, req.to_request()).await.status()
+            }
+        };
+
+        assert_eq!(status("key-a").await, StatusCode::OK);
+        assert_eq!(status("key-b").await, StatusCode::OK);
+        assert_eq!(status("wrong").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status("wrong").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status("wrong").await, StatusCode::TOO_MANY_REQUESTS);
+        // The address, one bucket per key and none for the failures behind auth.
+        assert_eq!(buckets.count(), 3);
+    }
+
+    #[actix_web::test]
+    async fn test_forwarded_for_only_picks_the_bucket_behind_a_trusted_proxy() {
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::new(Duration::from_secs(3600), 1, by_ip_before_auth(vec!["10.0.0.1".parse().unwrap()])))
+                .route("/health", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let status = |peer: &'static str, forwarded_for: &'static str| {
+            let app = &app;
+            async move {
+                let req = test::TestRequest::get()
+                    .uri("/health")
+                    .peer_addr(peer.parse().unwrap())
+                    .insert_header(("X-Forwarded-For", forwarded_for));
+                test::call_service(app, req.to_request()).await.status()
+            }
+        };
+
+        // A client making up a new address for each request still draws on its own bucket.
+        assert_eq!(status("203.0.113.7:4000", "198.51.100.1").await, StatusCode::OK);
+        assert_eq!(status("203.0.113.7:4000", "198.51.100.2").await, StatusCode::TOO_MANY_REQUESTS);
+
+        // Behind the proxy, each forwarded client gets its own.
+        assert_eq!(status("10.0.0.1:5000", "198.51.100.1").await, StatusCode::OK);
+        assert_eq!(status("10.0.0.1:5000", "198.51.100.2").await, StatusCode::OK);
+        assert_eq!(status("10.0.0.1:5000", "198.51.100.1").await, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn test_routes_exhaust_their_own_limits_independently() {
+        let limit = |capacity, secs| Limit { capacity, fill_interval: Duration::from_secs(secs) };
+        let app = test::init_service(
+            App::new()
+                .wrap(
+                    RateLimiter::new(Duration::from_secs(3600), 1, by_ip(Vec::new()))
+                        .route("/ingest", limit(3, 3600))
+                        .route("/logs", limit(2, 3600)),
+                )
+                .route("/ingest", web::post().to(HttpResponse::Accepted))
+                .route("/logs", web::get().to(HttpResponse::Ok))
+                .route("/logs/stats", web::get().to(HttpResponse::Ok))
+                .route("/logsearch", web::get().to(HttpResponse::Ok))
+                .route("/health", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let status = |req: test::TestRequest| {
+            let app = &app;
+            async move { test::call_service(app, req.to_request()).await.status() }
+        };
+
+        for _ in 0..3 {
+            assert_eq!(status(test::TestRequest::post().uri("/ingest")).await, StatusCode::ACCEPTED);
+        }
+        assert_eq!(status(test::TestRequest::post().uri("/ingest")).await, StatusCode::TOO_MANY_REQUESTS);
+
+        // `/logs` and everything below it share one bucket, untouched by `/ingest`.
+        assert_eq!(status(test::TestRequest::get().uri("/logs")).await, StatusCode::OK);
+        assert_eq!(status(test::TestRequest::get().uri("/logs/stats")).await, StatusCode::OK);
+        assert_eq!(status(test::TestRequest::get().uri("/logs")).await, StatusCode::TOO_MANY_REQUESTS);
+
+        // Other paths, including ones that only share the prefix's characters, get the default.
+        assert_eq!(status(test::TestRequest::get().uri("/logsearch")).await, StatusCode::OK);
+        assert_eq!(status(test::TestRequest::get().uri("/health")).await, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn test_sliding_window_allows_exactly_limit_requests() {
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::new(Duration::from_millis(300), 3, by_ip(Vec::new())).with_algorithm(RateLimitAlgorithm::SlidingWindow))
+                .route("/health", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let status = || async {
+            test::call_service(&app, test::TestRequest::get().uri("/health").to_request()).await.status()
+        };
+
+        // Idle time before the first request earns no burst on top of the limit.
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        for _ in 0..3 {
+            assert_eq!(status().await, StatusCode::OK);
+        }
+        assert_eq!(status().await, StatusCode::TOO_MANY_REQUESTS);
+
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        for _ in 0..3 {
+            assert_eq!(status().await, StatusCode::OK);
+        }
+        assert_eq!(status().await, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn test_limiters_sharing_buckets_share_each_clients_limit() {
+        let buckets = Buckets::default();
+        let worker = || {
+            test::init_service(
+                App::new()
+                    .wrap(RateLimiter::new(Duration::from_secs(3600), 2, by_ip(Vec::new())).with_buckets(buckets.clone()))
+                    .route("/health", web::get().to(HttpResponse::Ok)),
+            )
+        };
+        let (first, second) = (worker().await, worker().await);
+        for (app, expected) in [(&first, StatusCode::OK), (&second, StatusCode::OK), (&first, StatusCode::TOO_MANY_REQUESTS)] {
+            let status = test::call_service(app, test::TestRequest::get().uri("/health").to_request()).await.status();
+            assert_eq!(status, expected);
+        }
+        assert_eq!(buckets.count(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_idle_buckets_are_evicted_and_recreated_full() {
+        let buckets = Buckets::default();
+        let app = test::init_service(
+            App::new()
+                .wrap(RateLimiter::new(Duration::from_secs(3600), 1, by_ip(Vec::new())).with_buckets(buckets.clone()))
+                .route("/health", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let status = |addr: &'static str| {
+            let app = &app;
+            async move {
+                let req = test::TestRequest::get().uri("/health").peer_addr(addr.parse().unwrap());
+                test::call_service(app, req.to_request()).await.status()
+            }
+        };
+
+        assert_eq!(status("203.0.113.1:4000").await, StatusCode::OK);
+        assert_eq!(status("203.0.113.2:4000").await, StatusCode::OK);
+        let idle_since = Instant::now();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert_eq!(status("203.0.113.3:4000").await, StatusCode::OK);
+        assert_eq!(status("203.0.113.1:4000").await, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(buckets.count(), 3);
+
+        // Past the TTL but not the hour a bucket takes to refill, a drained client is kept.
+        let ttl = Duration::from_secs(60);
+        assert_eq!(buckets.evict_idle(ttl, idle_since + ttl), 0);
+        assert_eq!(buckets.count(), 3);
+
+        // An hour on, only the clients not seen since `idle_since` have been idle that long.
+        let refill = Duration::from_secs(3600);
+        assert_eq!(buckets.evict_idle(ttl, idle_since + refill), 1);
+        assert_eq!(buckets.count(), 2);
+        assert_eq!(buckets.evict_idle(ttl, Instant::now() + refill), 2);
+        assert_eq!(buckets.count(), 0);
+
+        // An evicted client starts over with a full bucket.
+        assert_eq!(status("203.0.113.1:4000").await, StatusCode::OK);
+        assert_eq!(buckets.count(), 1);
+    }
 }
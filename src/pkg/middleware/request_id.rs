@@ -0,0 +1,165 @@
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    error::InternalError,
+    http::header::{HeaderName, HeaderValue},
+    Error, HttpMessage,
+};
+use futures::future::{ok, Ready};
+use futures_util::future::LocalBoxFuture;
+use std::task::{Context, Poll};
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied id that is kept; longer ones are replaced.
+const MAX_LEN: usize = 128;
+
+/// The id of the request being handled, in its extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// The client's `X-Request-Id` if it is usable (printable ASCII, at most 128 bytes),
+    /// otherwise a new UUID.
+    fn from_request(req: &ServiceRequest) -> Self {
+        let presented = req
+            .headers()
+            .get(&REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|id| !id.is_empty() && id.len() <= MAX_LEN && id.bytes().all(|b| b.is_ascii_graphic()));
+        match presented {
+            Some(id) => RequestId(id.to_string()),
+            None => RequestId(uuid::Uuid::new_v4().to_string()),
+        }
+    }
+}
+
+/// Gives every request an id: it is stored as a [`RequestId`] extension, recorded as
+/// the `request_id` field of a span around the handler, and echoed in `X-Request-Id`.
+///
+/// Errors from the services it wraps are rendered into their responses here, so those
+/// carry the header too.
+pub struct RequestIds;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestIds
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestIdMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestIdMiddleware { service })
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let request_id = RequestId::from_request(&req);
+        let span = tracing::info_span!("request", request_id = %request_id.0);
+        // Checked to be visible ASCII (or a UUID), so it is always a valid header value.
+        let header_value = HeaderValue::from_str(&request_id.0).ok();
+        req.extensions_mut().insert(request_id);
+
+        let fut = span.in_scope(|| self.service.call(req));
+        Box::pin(
+            async move {
+                // The request is gone by the time an error comes back, so the error stays
+                // one, carrying the response the server would have rendered for it.
+                let mut res = match fut.await {
+                    Ok(res) => res,
+                    Err(e) => {
+                        let mut response = e.error_response();
+                        if let Some(value) = header_value {
+                            response.headers_mut().insert(REQUEST_ID_HEADER, value);
+                        }
+                        return Err(InternalError::from_response(e, response).into());
+                    }
+                };
+                if let Some(value) = header_value {
+                    res.headers_mut().insert(REQUEST_ID_HEADER, value);
+                }
+                Ok(res)
+            }
+            .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{error::ErrorForbidden, http::StatusCode, test, web, App, HttpRequest, HttpResponse};
+
+    async fn echo_extension(req: HttpRequest) -> HttpResponse {
+        match req.extensions().get::<RequestId>() {
+            Some(id) => HttpResponse::Ok().body(id.0.clone()),
+            None => HttpResponse::Ok().finish(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_request_id_is_kept_or_generated_and_echoed() {
+        let app = test::init_service(App::new().wrap(RequestIds).route("/ingest", web::post().to(echo_extension))).await;
+
+        let req = test::TestRequest::post().uri("/ingest").insert_header((REQUEST_ID_HEADER, "trace-42"));
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.headers().get(&REQUEST_ID_HEADER).unwrap(), "trace-42");
+        assert_eq!(test::read_body(res).await, "trace-42");
+
+        let res = test::call_service(&app, test::TestRequest::post().uri("/ingest").to_request()).await;
+        let generated = res.headers().get(&REQUEST_ID_HEADER).unwrap().to_str().unwrap().to_string();
+        assert!(uuid::Uuid::parse_str(&generated).is_ok());
+        assert_eq!(test::read_body(res).await, generated.as_str());
+
+        for unusable in ["has space".to_string(), "x".repeat(MAX_LEN + 1)] {
+            let req = test::TestRequest::post().uri("/ingest").insert_header((REQUEST_ID_HEADER, unusable.as_str()));
+            let res = test::call_service(&app, req.to_request()).await;
+            let id = res.headers().get(&REQUEST_ID_HEADER).unwrap().to_str().unwrap();
+            assert!(uuid::Uuid::parse_str(id).is_ok(), "{:?} was kept", unusable);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_inner_errors_carry_the_request_id() {
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(|_req, _srv| futures::future::err::<ServiceResponse, _>(ErrorForbidden("no")))
+                .wrap(RequestIds)
+                .route("/ingest", web::post().to(echo_extension)),
+        )
+        .await;
+
+        let req = test::TestRequest::post().uri("/ingest").insert_header((REQUEST_ID_HEADER, "trace-43"));
+        let Err(e) = app.call(req.to_request()).await else {
+            panic!("the inner error was swallowed");
+        };
+        // What the server sends for the error.
+        let res = e.error_response();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(res.headers().get(&REQUEST_ID_HEADER).unwrap(), "trace-43");
+    }
+}
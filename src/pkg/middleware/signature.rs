@@ -0,0 +1,221 @@
+use crate::models::{ErrorCode, ErrorResponse};
+use actix_web::{
+    body::EitherBody,
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    web::BytesMut,
+    Error, HttpMessage, HttpResponse,
+};
+use futures::future::{ok, Ready};
+use futures::StreamExt;
+use futures_util::future::LocalBoxFuture;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const SIGNATURE_HEADER: &str = "x-signature";
+pub const TIMESTAMP_HEADER: &str = "x-signature-timestamp";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Verifies signed requests: `X-Signature-Timestamp` holds the Unix time in seconds at
+/// which the client signed, and `X-Signature` the hex HMAC-SHA256, under the shared
+/// secret, of `<timestamp>.<body>` with the body exactly as sent (before any
+/// decompression). Unsigned, mismatched and stale requests get a 401.
+#[derive(Clone)]
+pub struct SignatureVerifier {
+    secret: Arc<str>,
+    tolerance: Duration,
+    max_body_bytes: usize,
+}
+
+impl SignatureVerifier {
+    pub fn new(secret: &str, tolerance: Duration, max_body_bytes: usize) -> Self {
+        Self {
+            secret: Arc::from(secret),
+            tolerance,
+            max_body_bytes,
+        }
+    }
+
+    fn check(&self, timestamp: &str, signature: &str, body: &[u8], now: SystemTime) -> Result<(), &'static str> {
+        let signed_at = timestamp
+            .parse::<u64>()
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+            .map_err(|_| "Invalid signature timestamp")?;
+        let skew = now.duration_since(signed_at).unwrap_or_else(|e| e.duration());
+        if skew > self.tolerance {
+            return Err("Stale signature timestamp");
+        }
+        let signature = decode_hex(signature).ok_or("Invalid signature")?;
+        signed_mac(&self.secret, timestamp, body)
+            .verify_slice(&signature)
+            .map_err(|_| "Invalid signature")
+    }
+}
+
+/// The MAC that `X-Signature` must hold for `body` signed at `timestamp`.
+fn signed_mac(secret: &str, timestamp: &str, body: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
+impl<S, B> Transform<S, ServiceRequest> for SignatureVerifier
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = SignatureVerifierMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(SignatureVerifierMiddleware {
+            service: Rc::new(service),
+            verifier: self.clone(),
+        })
+    }
+}
+
+pub struct SignatureVerifierMiddleware<S> {
+    service: Rc<S>,
+    verifier: SignatureVerifier,
+}
+
+impl<S, B> Service<ServiceRequest> for SignatureVerifierMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let verifier = self.verifier.clone();
+        Box::pin(async move {
+            let refuse = |req: ServiceRequest, code: ErrorCode, message: String| {
+                Ok(req.into_response(HttpResponse::from(ErrorResponse::new(code, message))).map_into_right_body())
+            };
+            let header = |name: &str| req.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+            let (Some(timestamp), Some(signature)) = (header(TIMESTAMP_HEADER), header(SIGNATURE_HEADER)) else {
+                return refuse(req, ErrorCode::Unauthorized, "Missing request signature".to_string());
+            };
+
+            // The whole body is needed before the handler sees any of it, so buffer it
+            // here (within the route's body limit) and hand the handler a copy.
+            let mut payload = req.take_payload();
+            let mut body = BytesMut::new();
+            while let Some(chunk) = payload.next().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => return refuse(req, ErrorCode::BadRequest, e.to_string()),
+                };
+                if body.len() + chunk.len() > verifier.max_body_bytes {
+                    return refuse(req, ErrorCode::PayloadTooLarge, "Request body is too large".to_string());
+                }
+                body.extend_from_slice(&chunk);
+            }
+            if let Err(reason) = verifier.check(&timestamp, &signature, &body, SystemTime::now()) {
+                return refuse(req, ErrorCode::Unauthorized, reason.to_string());
+            }
+
+            req.set_payload(Payload::from(body.freeze()));
+            Ok(service.call(req).await?.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test, web, App, HttpResponse};
+
+    fn sign(secret: &str, timestamp: u64, body: &[u8]) -> String {
+        format!("{:x}", signed_mac(secret, &timestamp.to_string(), body).finalize().into_bytes())
+    }
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    #[actix_web::test]
+    async fn test_verifies_signed_bodies() {
+        let app = test::init_service(
+            App::new()
+                .wrap(SignatureVerifier::new("shared-secret", Duration::from_secs(300), 1024))
+                .route("/ingest", web::post().to(|body: web::Bytes| async move { HttpResponse::Ok().body(body) })),
+        )
+        .await;
+        let body = br#"[{"level":"info","message":"m"}]"#;
+        let post = |timestamp: u64, signature: String, body: &'static [u8]| {
+            let app = &app;
+            async move {
+                let req = test::TestRequest::post()
+                    .uri("/ingest")
+                    .insert_header((TIMESTAMP_HEADER, timestamp.to_string()))
+                    .insert_header((SIGNATURE_HEADER, signature))
+                    .set_payload(body)
+                    .to_request();
+                let res = test::call_service(app, req).await;
+                (res.status(), test::read_body(res).await.to_vec())
+            }
+        };
+
+        let (status, echoed) = post(now(), sign("shared-secret", now(), body), body).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(echoed, body, "the handler still sees the whole body");
+
+        let tampered = br#"[{"level":"info","message":"M"}]"#;
+        assert_eq!(post(now(), sign("shared-secret", now(), body), tampered).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(post(now(), sign("other-secret", now(), body), body).await.0, StatusCode::UNAUTHORIZED);
+
+        let stale = now() - 600;
+        assert_eq!(post(stale, sign("shared-secret", stale, body), body).await.0, StatusCode::UNAUTHORIZED);
+
+        let (status, refused) = post(stale, sign("shared-secret", stale, body), body).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let refused: serde_json::Value = serde_json::from_slice(&refused).unwrap();
+        assert_eq!(refused["code"], "unauthorized");
+        assert_eq!(refused["message"], "Stale signature timestamp");
+
+        let req = test::TestRequest::post().uri("/ingest").set_payload(&body[..]).to_request();
+        let status = test::call_service(&app, req).await.status();
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "unsigned requests are refused");
+    }
+
+    #[actix_web::test]
+    async fn test_timestamp_tolerance_applies_both_ways() {
+        let verifier = SignatureVerifier::new("s", Duration::from_secs(60), 1024);
+        let at = |secs: u64| UNIX_EPOCH + Duration::from_secs(secs);
+        let signature = sign("s", 1_000, b"{}");
+        assert!(verifier.check("1000", &signature, b"{}", at(1_059)).is_ok());
+        assert_eq!(verifier.check("1000", &signature, b"{}", at(1_061)), Err("Stale signature timestamp"));
+        assert_eq!(verifier.check("1000", &signature, b"{}", at(939)), Err("Stale signature timestamp"));
+        assert_eq!(verifier.check("soon", &signature, b"{}", at(1_000)), Err("Invalid signature timestamp"));
+    }
+}
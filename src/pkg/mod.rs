@@ -1,3 +1,17 @@
+pub mod client_ip;
+pub mod config;
+pub mod ingest;
+pub mod metrics;
 pub mod middleware;
 mod utils;
-pub mod db;
\ No newline at end of file
+pub mod db;
+pub mod dead_letters;
+pub mod export;
+pub mod id;
+pub mod maintenance;
+pub mod retry;
+pub mod sink;
+pub mod status;
+pub mod tail;
+pub mod timeline;
+pub mod time;
\ No newline at end of file
@@ -0,0 +1,150 @@
+use crate::models::LogEntry;
+use crate::pkg::dead_letters::DirectoryDeadLetters;
+use crate::pkg::metrics;
+use crate::pkg::sink::SinkError;
+use std::fmt::Display;
+use std::future::Future;
+use std::time::Duration;
+use tracing::{error, warn};
+
+/// How the background processor retries a batch it failed to write.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts in all, the first included.
+    pub attempts: u32,
+    /// Wait before the first retry; doubled for each one after.
+    pub backoff: Duration,
+}
+
+/// An error from a write [`write_with_retries`] can make.
+pub trait WriteError: Display {
+    /// Whether the write already retried and dead-lettered what failed itself, as a
+    /// `MultiSink` does per sink; such an error is returned as it is.
+    fn handled(&self) -> bool {
+        false
+    }
+}
+
+impl WriteError for String {}
+
+impl WriteError for SinkError {
+    fn handled(&self) -> bool {
+        matches!(self, SinkError::Sinks { .. })
+    }
+}
+
+/// Writes `batch` with `write`, retrying a failed write with exponential backoff. A batch
+/// that still fails after the last attempt is dead-lettered so it can be replayed once
+/// the database recovers, and the final error returned.
+pub async fn write_with_retries<F, Fut, T, E>(
+    batch: Vec<LogEntry>,
+    policy: &RetryPolicy,
+    dead_letters: Option<&DirectoryDeadLetters>,
+    write: F,
+) -> Result<T, E>
+where
+    F: Fn(Vec<LogEntry>) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: WriteError,
+{
+    let mut attempt = 1;
+    let error = loop {
+        match write(batch.clone()).await {
+            Ok(written) => return Ok(written),
+            Err(e) if e.handled() => return Err(e),
+            Err(e) if attempt < policy.attempts => {
+                let backoff = policy.backoff * 2u32.saturating_pow(attempt - 1);
+                warn!(
+                    "Attempt {} of {} to write {} entries failed ({}); retrying in {:?}.",
+                    attempt,
+                    policy.attempts,
+                    batch.len(),
+                    e,
+                    backoff
+                );
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+            }
+            Err(e) => break e,
+        }
+    };
+    let Some(dead_letters) = dead_letters else {
+        error!("Dropping a batch of {} entries after {} failed writes: {}", batch.len(), attempt, error);
+        return Err(error);
+    };
+    match dead_letters.store(&error.to_string(), &batch).await {
+        Ok(()) => {
+            metrics::DEAD_LETTERED_BATCHES.inc();
+            warn!("Dead-lettered a batch of {} entries after {} failed writes.", batch.len(), attempt);
+        }
+        Err(e) => error!("Failed to dead-letter a batch of {} entries; it is lost: {}", batch.len(), e),
+    }
+    Err(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkg::db::postgres::tests::sample_entry;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            attempts: 3,
+            backoff: Duration::from_millis(1),
+        }
+    }
+
+    /// A write that fails its first `failures` attempts, counting them all in `attempts`.
+    fn flaky<'a>(failures: u32, attempts: &'a AtomicU32) -> impl Fn(Vec<LogEntry>) -> futures::future::Ready<Result<(), String>> + 'a {
+        move |_| {
+            let attempt = attempts.fetch_add(1, Ordering::SeqCst) + 1;
+            futures::future::ready(if attempt <= failures { Err("pool timed out".to_string()) } else { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_third_attempt_succeeds_after_two_failures() {
+        let attempts = AtomicU32::new(0);
+        let batch = vec![sample_entry("web", "e-1", "2024-05-01T10:00:00Z")];
+
+        write_with_retries(batch, &policy(), None, flaky(2, &attempts)).await.unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_batch_is_dead_lettered_once_attempts_run_out() {
+        let dir = std::env::temp_dir().join(format!("dead-letters-{}", uuid::Uuid::new_v4()));
+        let dead_letters = DirectoryDeadLetters::new(&dir);
+        let attempts = AtomicU32::new(0);
+        let batch = vec![sample_entry("web", "e-1", "2024-05-01T10:00:00Z")];
+        let before = metrics::DEAD_LETTERED_BATCHES.get();
+
+        let result = write_with_retries(batch, &policy(), Some(&dead_letters), flaky(3, &attempts)).await;
+        assert_eq!(result, Err("pool timed out".to_string()));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+        let stored: serde_json::Value = serde_json::from_slice(&std::fs::read(&files[0]).unwrap()).unwrap();
+        assert_eq!(stored["entries"][0]["id"], "e-1");
+        assert!(metrics::DEAD_LETTERED_BATCHES.get() > before);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_errors_handled_by_the_sink_are_not_retried() {
+        let dir = std::env::temp_dir().join(format!("dead-letters-{}", uuid::Uuid::new_v4()));
+        let dead_letters = DirectoryDeadLetters::new(&dir);
+        let attempts = AtomicU32::new(0);
+        let batch = vec![sample_entry("web", "e-1", "2024-05-01T10:00:00Z")];
+        let write = |_| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            futures::future::ready(Err::<usize, _>(SinkError::Sinks { failed: Vec::new(), total: 2 }))
+        };
+
+        let result = write_with_retries(batch, &policy(), Some(&dead_letters), write).await;
+        assert!(matches!(result, Err(SinkError::Sinks { .. })));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,288 @@
+use super::{LogSink, SinkError};
+use crate::models::LogEntry;
+use crate::pkg::config::ElasticSinkConfig;
+use crate::pkg::time;
+use chrono::format::{Item, StrftimeItems};
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::warn;
+
+/// Name of the index template installed by [`ElasticSink::install_template`].
+const TEMPLATE_NAME: &str = "eagle-logs";
+
+/// One entry the `_bulk` API refused.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkFailure {
+    /// Position of the entry in the batch.
+    pub position: usize,
+    pub id: Option<String>,
+    pub status: u16,
+    pub reason: String,
+}
+
+/// Indexes batches through the `_bulk` API, one document per entry with the entry's
+/// `id` as `_id`, so a retried batch overwrites rather than duplicates.
+pub struct ElasticSink {
+    client: reqwest::Client,
+    url: String,
+    index_pattern: String,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl ElasticSink {
+    pub fn new(config: &ElasticSinkConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()
+            .expect("reqwest client with default TLS settings");
+        Self {
+            client,
+            url: config.url.clone(),
+            index_pattern: config.index_pattern.clone(),
+            username: config.username.clone(),
+            password: config.password.clone(),
+        }
+    }
+
+    /// Creates or replaces an index template covering every index the pattern can
+    /// produce, mapping `timestamp` as a date and `context` / `device` as objects.
+    /// They hold a single object per entry, so `object` rather than `nested`, which
+    /// would need nested queries to search.
+    pub async fn install_template(&self) -> Result<(), SinkError> {
+        let template = json!({
+            "index_patterns": [index_wildcard(&self.index_pattern)],
+            "template": {
+                "mappings": {
+                    "properties": {
+                        "timestamp": { "type": "date" },
+                        "context": { "type": "object" },
+                        "device": { "type": "object" },
+                    }
+                }
+            }
+        });
+        let url = format!("{}/_index_template/{}", self.url, TEMPLATE_NAME);
+        self.authenticated(self.client.put(url))
+            .json(&template)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(SinkError::Http)?;
+        Ok(())
+    }
+
+    /// The index for `entry`, from its timestamp; unparseable timestamps go to the
+    /// index for the current time.
+    fn index_for(&self, entry: &LogEntry) -> String {
+        let instant = time::parse_flexible(&entry.timestamp, time::DEFAULT_FORMATS)
+            .map(|(instant, _)| instant)
+            .unwrap_or_else(chrono::Utc::now);
+        instant.format(&self.index_pattern).to_string()
+    }
+
+    fn authenticated(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.username {
+            Some(username) => request.basic_auth(username, self.password.as_ref()),
+            None => request,
+        }
+    }
+
+    fn bulk_body(&self, batch: &[LogEntry]) -> Result<Vec<u8>, serde_json::Error> {
+        let mut body = Vec::new();
+        for entry in batch {
+            let action = json!({ "index": { "_index": self.index_for(entry), "_id": entry.id } });
+            serde_json::to_writer(&mut body, &action)?;
+            body.push(b'\n');
+            serde_json::to_writer(&mut body, entry)?;
+            body.push(b'\n');
+        }
+        Ok(body)
+    }
+}
+
+impl LogSink for ElasticSink {
+    fn name(&self) -> &'static str {
+        "elasticsearch"
+    }
+
+    fn write(&self, batch: Vec<LogEntry>) -> BoxFuture<'_, Result<usize, SinkError>> {
+        Box::pin(async move {
+            if batch.is_empty() {
+                return Ok(0);
+            }
+            let body = self.bulk_body(&batch).map_err(SinkError::Serialize)?;
+            let response: BulkResponse = self
+                .authenticated(self.client.post(format!("{}/_bulk", self.url)))
+                .header(reqwest::header::CONTENT_TYPE, "application/x-ndjson")
+                .body(body)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .map_err(SinkError::Http)?
+                .json()
+                .await
+                .map_err(SinkError::Http)?;
+            let failed = response.failures(&batch);
+            if failed.is_empty() {
+                return Ok(batch.len());
+            }
+            for failure in &failed {
+                warn!("Bulk indexing rejected entry {:?} ({}): {}", failure.id, failure.status, failure.reason);
+            }
+            Err(SinkError::BulkRejected { failed, total: batch.len() })
+        })
+    }
+}
+
+/// The parts of a `_bulk` response needed to find failed items.
+#[derive(Debug, Deserialize)]
+struct BulkResponse {
+    errors: bool,
+    /// One single-key object per action, keyed by the action (`index`).
+    items: Vec<HashMap<String, BulkItem>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BulkItem {
+    status: u16,
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+}
+
+impl BulkResponse {
+    /// Items come back in request order, so positions line up with `batch`.
+    fn failures(&self, batch: &[LogEntry]) -> Vec<BulkFailure> {
+        if !self.errors {
+            return Vec::new();
+        }
+        self.items
+            .iter()
+            .enumerate()
+            .filter_map(|(position, item)| {
+                let item = item.values().next()?;
+                if item.status < 300 {
+                    return None;
+                }
+                let reason = match &item.error {
+                    Some(error) => error["reason"].as_str().map(str::to_string).unwrap_or_else(|| error.to_string()),
+                    None => format!("status {}", item.status),
+                };
+                Some(BulkFailure {
+                    position,
+                    id: batch.get(position).and_then(|entry| entry.id.clone()),
+                    status: item.status,
+                    reason,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The pattern with every `strftime` field replaced by `*`: `logs-%Y.%m.%d` becomes
+/// `logs-*.*.*`.
+fn index_wildcard(pattern: &str) -> String {
+    let mut wildcard = String::new();
+    for item in StrftimeItems::new(pattern) {
+        match item {
+            Item::Literal(text) | Item::Space(text) => wildcard.push_str(text),
+            Item::OwnedLiteral(text) | Item::OwnedSpace(text) => wildcard.push_str(&text),
+            _ if wildcard.ends_with('*') => {}
+            _ => wildcard.push('*'),
+        }
+    }
+    wildcard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkg::db::postgres::tests::sample_entry;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// Answers every request with `response` as JSON and passes on the request bodies.
+    async fn bulk_endpoint(response: &'static str) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel(8);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                let body = loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                            .unwrap_or(0);
+                        if body.len() >= length {
+                            break body.to_string();
+                        }
+                    }
+                };
+                let reply = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    response.len(),
+                    response
+                );
+                socket.write_all(reply.as_bytes()).await.unwrap();
+                let _ = tx.send(body).await;
+            }
+        });
+        (url, rx)
+    }
+
+    #[tokio::test]
+    async fn test_partial_bulk_failure_names_the_failed_items() {
+        let (url, mut requests) = bulk_endpoint(
+            r#"{"took":3,"errors":true,"items":[
+                {"index":{"_index":"logs-2024.05.01","_id":"e-1","status":201,"result":"created"}},
+                {"index":{"_index":"logs-2024.05.02","_id":"e-2","status":400,
+                  "error":{"type":"mapper_parsing_exception","reason":"failed to parse field [timestamp]"}}}
+            ]}"#,
+        )
+        .await;
+        let sink = ElasticSink::new(&ElasticSinkConfig { url, ..ElasticSinkConfig::default() });
+        let batch = vec![
+            sample_entry("web", "e-1", "2024-05-01T10:00:00.000000Z"),
+            sample_entry("web", "e-2", "2024-05-02T23:59:59.000000Z"),
+        ];
+
+        let result = sink.write(batch).await;
+        let Err(SinkError::BulkRejected { failed, total }) = result else {
+            panic!("expected a partial failure, got {:?}", result);
+        };
+        assert_eq!(total, 2);
+        assert_eq!(
+            failed,
+            [BulkFailure {
+                position: 1,
+                id: Some("e-2".to_string()),
+                status: 400,
+                reason: "failed to parse field [timestamp]".to_string(),
+            }]
+        );
+
+        let body = requests.recv().await.unwrap();
+        let lines: Vec<serde_json::Value> = body.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], json!({ "index": { "_index": "logs-2024.05.01", "_id": "e-1" } }));
+        assert_eq!(lines[2], json!({ "index": { "_index": "logs-2024.05.02", "_id": "e-2" } }));
+        assert_eq!(lines[3]["message"], "message e-2");
+    }
+
+    #[test]
+    fn test_index_wildcard_covers_every_rollover() {
+        assert_eq!(index_wildcard("logs-%Y.%m.%d"), "logs-*.*.*");
+        assert_eq!(index_wildcard("logs-%Y%m"), "logs-*");
+        assert_eq!(index_wildcard("logs"), "logs");
+    }
+}
@@ -0,0 +1,91 @@
+use super::{LogSink, SinkError};
+use crate::models::LogEntry;
+use crate::pkg::config::KafkaSinkConfig;
+use futures::future::{try_join_all, BoxFuture};
+use rdkafka::{
+    error::KafkaError,
+    producer::{FutureProducer, FutureRecord},
+    util::Timeout,
+    ClientConfig,
+};
+
+/// Produces each entry as a JSON message to one topic, keyed by service so a
+/// service's entries stay in order on a single partition.
+pub struct KafkaSink {
+    producer: FutureProducer,
+    topic: String,
+}
+
+impl KafkaSink {
+    /// Connects lazily: an unreachable broker only shows up as failed writes.
+    pub fn new(config: &KafkaSinkConfig) -> Result<Self, KafkaError> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", &config.brokers)
+            .set("message.timeout.ms", config.timeout_ms.to_string())
+            .create()?;
+        Ok(Self {
+            producer,
+            topic: config.topic.clone(),
+        })
+    }
+}
+
+impl LogSink for KafkaSink {
+    fn name(&self) -> &'static str {
+        "kafka"
+    }
+
+    fn write(&self, batch: Vec<LogEntry>) -> BoxFuture<'_, Result<usize, SinkError>> {
+        Box::pin(async move {
+            let messages = batch
+                .iter()
+                .map(|entry| Ok((entry.service.as_str(), serde_json::to_vec(entry)?)))
+                .collect::<Result<Vec<_>, serde_json::Error>>()
+                .map_err(SinkError::Serialize)?;
+            // `message.timeout.ms` bounds each delivery, so waiting for queue room can't hang.
+            let deliveries = messages.iter().map(|(key, payload)| {
+                self.producer
+                    .send(FutureRecord::to(&self.topic).key(*key).payload(payload), Timeout::Never)
+            });
+            try_join_all(deliveries)
+                .await
+                .map_err(|(e, _)| SinkError::Kafka(e))?;
+            Ok(batch.len())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rdkafka::producer::Producer;
+    use rdkafka::types::{RDKafkaApiKey, RDKafkaRespErr};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_writes_deliver_and_undeliverable_batches_fail() {
+        // librdkafka's in-process mock broker (whose startup notice is silenced), so the
+        // test needs no Kafka and dials nothing.
+        let sink = KafkaSink {
+            producer: ClientConfig::new()
+                .set("test.mock.num.brokers", "1")
+                .set("log_level", "4")
+                .set("message.timeout.ms", "2000")
+                .create()
+                .unwrap(),
+            topic: "logs".to_string(),
+        };
+        let entry = crate::pkg::db::postgres::tests::sample_entry("web", "e-1", "2024-01-01T00:00:00Z");
+        let write = |batch| tokio::time::timeout(Duration::from_secs(10), sink.write(batch));
+
+        assert_eq!(write(vec![entry.clone()]).await.unwrap().unwrap(), 1);
+        assert!(write(Vec::new()).await.unwrap().is_ok());
+
+        sink.producer.client().mock_cluster().unwrap().request_errors(
+            RDKafkaApiKey::Produce,
+            &[RDKafkaRespErr::RD_KAFKA_RESP_ERR_TOPIC_AUTHORIZATION_FAILED; 8],
+        );
+        let result = write(vec![entry]).await.unwrap();
+        assert!(matches!(result, Err(SinkError::Kafka(_))), "{:?}", result);
+    }
+}
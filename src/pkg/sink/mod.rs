@@ -0,0 +1,117 @@
+use crate::models::LogEntry;
+use futures::future::BoxFuture;
+
+pub mod elastic;
+pub mod kafka;
+pub mod multi;
+pub mod postgres;
+pub mod s3;
+
+#[derive(Debug)]
+pub enum SinkError {
+    Postgres(sqlx::Error),
+    Kafka(rdkafka::error::KafkaError),
+    Http(reqwest::Error),
+    /// Some entries of a bulk request were refused; the others were written.
+    BulkRejected {
+        failed: Vec<elastic::BulkFailure>,
+        total: usize,
+    },
+    Serialize(serde_json::Error),
+    /// An object upload failed, after any retries.
+    Upload(String),
+    /// Some sinks of a `MultiSink` failed, by name.
+    Sinks {
+        failed: Vec<(&'static str, SinkError)>,
+        total: usize,
+    },
+}
+
+impl std::fmt::Display for SinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SinkError::Postgres(e) => write!(f, "PostgreSQL insert failed: {}", e),
+            SinkError::Kafka(e) => write!(f, "Kafka produce failed: {}", e),
+            SinkError::Http(e) => write!(f, "HTTP request failed: {}", e),
+            SinkError::BulkRejected { failed, total } => {
+                write!(f, "{} of {} entries were rejected by the bulk API", failed.len(), total)
+            }
+            SinkError::Serialize(e) => write!(f, "Failed to serialize log entry: {}", e),
+            SinkError::Upload(e) => write!(f, "Object upload failed: {}", e),
+            SinkError::Sinks { failed, total } => {
+                write!(f, "{} of {} sinks failed", failed.len(), total)?;
+                for (sink, e) in failed {
+                    write!(f, "; {}: {}", sink, e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SinkError {}
+
+/// A destination for batches of validated entries. A write succeeds only once the
+/// whole batch has been accepted by the destination.
+pub trait LogSink: Send + Sync {
+    /// Short name for logs, e.g. `postgres`.
+    fn name(&self) -> &'static str;
+
+    /// Returns how many entries the destination newly stored, which is fewer than the
+    /// batch when it already had some from an earlier attempt.
+    fn write(&self, batch: Vec<LogEntry>) -> BoxFuture<'_, Result<usize, SinkError>>;
+
+    /// Writes out anything the sink is holding back. Called once the processor has
+    /// drained its queues; sinks that write through need not override it.
+    fn flush(&self) -> BoxFuture<'_, Result<(), SinkError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    /// Records every batch it is given, failing instead while `fail` is set.
+    #[derive(Clone, Default)]
+    pub(crate) struct MockSink {
+        pub(crate) batches: Arc<Mutex<Vec<Vec<LogEntry>>>>,
+        pub(crate) fail: bool,
+    }
+
+    impl LogSink for MockSink {
+        fn name(&self) -> &'static str {
+            "mock"
+        }
+
+        fn write(&self, batch: Vec<LogEntry>) -> BoxFuture<'_, Result<usize, SinkError>> {
+            Box::pin(async move {
+                if self.fail {
+                    return Err(SinkError::Postgres(sqlx::Error::PoolTimedOut));
+                }
+                let count = batch.len();
+                self.batches.lock().push(batch);
+                Ok(count)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writes_dispatch_through_the_trait_object() {
+        let mock = MockSink::default();
+        let sink: Box<dyn LogSink> = Box::new(mock.clone());
+        let entry = |id: &str| crate::pkg::db::postgres::tests::sample_entry("web", id, "2024-01-01T00:00:00Z");
+        assert_eq!(sink.write(vec![entry("e-1"), entry("e-2")]).await.unwrap(), 2);
+        assert_eq!(sink.write(Vec::new()).await.unwrap(), 0);
+
+        assert_eq!(sink.name(), "mock");
+        let sizes: Vec<_> = mock.batches.lock().iter().map(Vec::len).collect();
+        assert_eq!(sizes, [2, 0]);
+        assert_eq!(mock.batches.lock()[0][0].id.as_deref(), Some("e-1"));
+
+        let failing: Box<dyn LogSink> = Box::new(MockSink { fail: true, ..MockSink::default() });
+        assert!(matches!(failing.write(Vec::new()).await, Err(SinkError::Postgres(_))));
+    }
+}
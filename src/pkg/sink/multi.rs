@@ -0,0 +1,301 @@
+use super::{LogSink, SinkError};
+use crate::models::LogEntry;
+use crate::pkg::config::SinkFailurePolicy;
+use crate::pkg::retry::RetryPolicy;
+use futures::future::{join_all, BoxFuture};
+use tracing::{error, warn};
+
+/// Where a batch goes when one sink of a [`MultiSink`] fails to write it.
+pub trait DeadLetters: Send + Sync {
+    fn store<'a>(&'a self, sink: &'a str, error: &'a str, batch: &'a [LogEntry]) -> BoxFuture<'a, Result<(), SinkError>>;
+}
+
+/// Writes every batch to all of its sinks concurrently, retrying only the sinks that
+/// failed, so the others never see the batch twice. Each sink that still fails after the
+/// last attempt has its copy dead-lettered once, so it can be replayed to that sink alone;
+/// `policy` decides whether the write as a whole fails. The write is not worth retrying
+/// as a whole either way.
+pub struct MultiSink {
+    /// The first is the primary, whose count a write reports.
+    sinks: Vec<Box<dyn LogSink>>,
+    policy: SinkFailurePolicy,
+    retry: RetryPolicy,
+    dead_letters: Box<dyn DeadLetters>,
+}
+
+impl MultiSink {
+    pub fn new(
+        sinks: Vec<Box<dyn LogSink>>,
+        policy: SinkFailurePolicy,
+        retry: RetryPolicy,
+        dead_letters: Box<dyn DeadLetters>,
+    ) -> Self {
+        Self { sinks, policy, retry, dead_letters }
+    }
+
+    /// Applies the policy to the per-sink results, in sink order. A write that passes
+    /// counts what the primary stored, or the first sink after it that stored the batch
+    /// when the primary failed; a sink that only buffers the batch counts 0.
+    fn outcome(&self, results: Vec<Result<usize, SinkError>>) -> Result<usize, SinkError> {
+        let total = self.sinks.len();
+        let mut written = None;
+        let mut failed = Vec::new();
+        for (sink, result) in self.sinks.iter().zip(results) {
+            match result {
+                Ok(count) => {
+                    written.get_or_insert(count);
+                }
+                Err(e) => failed.push((sink.name(), e)),
+            }
+        }
+        let fails = match self.policy {
+            SinkFailurePolicy::FailIfAny => !failed.is_empty(),
+            SinkFailurePolicy::FailIfAll => failed.len() == total,
+            SinkFailurePolicy::BestEffort => false,
+        };
+        if fails {
+            Err(SinkError::Sinks { failed, total })
+        } else {
+            Ok(written.unwrap_or(0))
+        }
+    }
+}
+
+impl LogSink for MultiSink {
+    fn name(&self) -> &'static str {
+        "multi"
+    }
+
+    fn write(&self, batch: Vec<LogEntry>) -> BoxFuture<'_, Result<usize, SinkError>> {
+        Box::pin(async move {
+            let mut results: Vec<_> = join_all(self.sinks.iter().map(|sink| sink.write(batch.clone()))).await;
+            for attempt in 1..self.retry.attempts {
+                let pending: Vec<usize> = (0..results.len()).filter(|&i| results[i].is_err()).collect();
+                if pending.is_empty() {
+                    break;
+                }
+                let backoff = self.retry.backoff * 2u32.saturating_pow(attempt - 1);
+                for &i in &pending {
+                    if let Err(e) = &results[i] {
+                        warn!(
+                            "Attempt {} of {} to write {} entries to {} failed ({}); retrying in {:?}.",
+                            attempt,
+                            self.retry.attempts,
+                            batch.len(),
+                            self.sinks[i].name(),
+                            e,
+                            backoff
+                        );
+                    }
+                }
+                tokio::time::sleep(backoff).await;
+                let retried = join_all(pending.iter().map(|&i| self.sinks[i].write(batch.clone()))).await;
+                for (i, result) in pending.into_iter().zip(retried) {
+                    results[i] = result;
+                }
+            }
+            for (sink, result) in self.sinks.iter().zip(&results) {
+                let Err(e) = result else { continue };
+                warn!("Sink {} failed to write a batch of {} entries: {}", sink.name(), batch.len(), e);
+                let reason = e.to_string();
+                if let Err(dead_letter_error) = self.dead_letters.store(sink.name(), &reason, &batch).await {
+                    error!("Failed to dead-letter a batch for {}: {}", sink.name(), dead_letter_error);
+                }
+            }
+            self.outcome(results)
+        })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<(), SinkError>> {
+        Box::pin(async move {
+            let results = join_all(self.sinks.iter().map(|sink| sink.flush())).await;
+            let failed: Vec<_> = self
+                .sinks
+                .iter()
+                .zip(results)
+                .filter_map(|(sink, result)| result.err().map(|e| (sink.name(), e)))
+                .collect();
+            if failed.is_empty() {
+                Ok(())
+            } else {
+                Err(SinkError::Sinks { failed, total: self.sinks.len() })
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkg::db::postgres::tests::sample_entry;
+    use crate::pkg::sink::tests::MockSink;
+    use parking_lot::Mutex;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Sink name and entry ids.
+    type DeadLetter = (String, Vec<String>);
+
+    /// Records every dead-lettered batch.
+    #[derive(Clone, Default)]
+    struct MockDeadLetters {
+        stored: Arc<Mutex<Vec<DeadLetter>>>,
+    }
+
+    impl DeadLetters for MockDeadLetters {
+        fn store<'a>(&'a self, sink: &'a str, _error: &'a str, batch: &'a [LogEntry]) -> BoxFuture<'a, Result<(), SinkError>> {
+            Box::pin(async move {
+                let ids = batch.iter().filter_map(|entry| entry.id.clone()).collect();
+                self.stored.lock().push((sink.to_string(), ids));
+                Ok(())
+            })
+        }
+    }
+
+    /// A sink with its own name, so dead letters can tell the sinks apart.
+    struct Named(&'static str, MockSink);
+
+    impl LogSink for Named {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn write(&self, batch: Vec<LogEntry>) -> BoxFuture<'_, Result<usize, SinkError>> {
+            self.1.write(batch)
+        }
+    }
+
+    /// Fails its first `failures` writes, then writes through to the mock.
+    struct Flaky {
+        failures: u32,
+        attempts: AtomicU32,
+        mock: MockSink,
+    }
+
+    impl LogSink for Flaky {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        fn write(&self, batch: Vec<LogEntry>) -> BoxFuture<'_, Result<usize, SinkError>> {
+            let attempt = self.attempts.fetch_add(1, Ordering::SeqCst) + 1;
+            if attempt <= self.failures {
+                return Box::pin(async { Err(SinkError::Upload("connection reset".to_string())) });
+            }
+            self.mock.write(batch)
+        }
+    }
+
+    fn batch() -> Vec<LogEntry> {
+        vec![
+            sample_entry("web", "e-1", "2024-05-01T10:00:00.000000Z"),
+            sample_entry("web", "e-2", "2024-05-01T10:00:01.000000Z"),
+        ]
+    }
+
+    fn retry() -> RetryPolicy {
+        RetryPolicy {
+            attempts: 3,
+            backoff: Duration::from_millis(1),
+        }
+    }
+
+    fn multi(sinks: &[(&'static str, &MockSink)], policy: SinkFailurePolicy, dead_letters: &MockDeadLetters) -> MultiSink {
+        let sinks = sinks
+            .iter()
+            .map(|(name, mock)| Box::new(Named(name, (*mock).clone())) as Box<dyn LogSink>)
+            .collect();
+        MultiSink::new(sinks, policy, retry(), Box::new(dead_letters.clone()))
+    }
+
+    #[tokio::test]
+    async fn test_every_sink_gets_the_batch() {
+        let (postgres, s3) = (MockSink::default(), MockSink::default());
+        let dead_letters = MockDeadLetters::default();
+        let sink = multi(&[("postgres", &postgres), ("s3", &s3)], SinkFailurePolicy::FailIfAny, &dead_letters);
+
+        sink.write(batch()).await.unwrap();
+        for mock in [&postgres, &s3] {
+            let batches = mock.batches.lock();
+            assert_eq!(batches.len(), 1);
+            assert_eq!(batches[0][1].id.as_deref(), Some("e-2"));
+        }
+        assert!(dead_letters.stored.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_best_effort_dead_letters_only_the_failed_sink() {
+        let postgres = MockSink::default();
+        let s3 = MockSink { fail: true, ..MockSink::default() };
+        let dead_letters = MockDeadLetters::default();
+        let sink = multi(&[("postgres", &postgres), ("s3", &s3)], SinkFailurePolicy::BestEffort, &dead_letters);
+
+        sink.write(batch()).await.unwrap();
+        assert_eq!(postgres.batches.lock().len(), 1);
+        assert_eq!(*dead_letters.stored.lock(), [("s3".to_string(), vec!["e-1".to_string(), "e-2".to_string()])]);
+    }
+
+    #[tokio::test]
+    async fn test_policy_decides_whether_a_partial_failure_fails() {
+        let ok = MockSink::default();
+        let failing = MockSink { fail: true, ..MockSink::default() };
+        let dead_letters = MockDeadLetters::default();
+
+        let any = multi(&[("postgres", &ok), ("s3", &failing)], SinkFailurePolicy::FailIfAny, &dead_letters);
+        let result = any.write(batch()).await;
+        assert!(matches!(&result, Err(SinkError::Sinks { failed, total: 2 }) if failed.len() == 1 && failed[0].0 == "s3"));
+
+        let all = multi(&[("postgres", &ok), ("s3", &failing)], SinkFailurePolicy::FailIfAll, &dead_letters);
+        assert!(all.write(batch()).await.is_ok());
+        let all = multi(&[("kafka", &failing), ("s3", &failing)], SinkFailurePolicy::FailIfAll, &dead_letters);
+        assert!(all.write(batch()).await.is_err());
+        assert_eq!(dead_letters.stored.lock().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_only_failed_sinks_are_retried() {
+        let (postgres, flaky, failing) = (MockSink::default(), MockSink::default(), MockSink { fail: true, ..MockSink::default() });
+        let dead_letters = MockDeadLetters::default();
+        let sinks: Vec<Box<dyn LogSink>> = vec![
+            Box::new(Named("postgres", postgres.clone())),
+            Box::new(Flaky { failures: 1, attempts: AtomicU32::new(0), mock: flaky.clone() }),
+            Box::new(Named("s3", failing.clone())),
+        ];
+        let sink = MultiSink::new(sinks, SinkFailurePolicy::FailIfAny, retry(), Box::new(dead_letters.clone()));
+
+        let result = sink.write(batch()).await;
+        assert!(matches!(&result, Err(SinkError::Sinks { failed, total: 3 }) if failed.len() == 1 && failed[0].0 == "s3"));
+        // The sinks that succeeded hold the batch once, however often the others were retried.
+        assert_eq!(postgres.batches.lock().len(), 1);
+        assert_eq!(flaky.batches.lock().len(), 1);
+        assert_eq!(*dead_letters.stored.lock(), [("s3".to_string(), vec!["e-1".to_string(), "e-2".to_string()])]);
+    }
+
+    #[tokio::test]
+    async fn test_write_counts_what_the_primary_stored() {
+        /// Holds the batch back, as the S3 sink does until a part is full.
+        struct Buffering;
+
+        impl LogSink for Buffering {
+            fn name(&self) -> &'static str {
+                "s3"
+            }
+
+            fn write(&self, _batch: Vec<LogEntry>) -> BoxFuture<'_, Result<usize, SinkError>> {
+                Box::pin(async { Ok(0) })
+            }
+        }
+
+        let dead_letters = MockDeadLetters::default();
+        let sinks: Vec<Box<dyn LogSink>> = vec![Box::new(Named("postgres", MockSink::default())), Box::new(Buffering)];
+        let sink = MultiSink::new(sinks, SinkFailurePolicy::FailIfAny, retry(), Box::new(dead_letters.clone()));
+        assert_eq!(sink.write(batch()).await.unwrap(), 2);
+
+        let failing = MockSink { fail: true, ..MockSink::default() };
+        let sinks: Vec<Box<dyn LogSink>> =
+            vec![Box::new(Named("postgres", failing)), Box::new(Named("kafka", MockSink::default())), Box::new(Buffering)];
+        let sink = MultiSink::new(sinks, SinkFailurePolicy::BestEffort, retry(), Box::new(dead_letters));
+        assert_eq!(sink.write(batch()).await.unwrap(), 2);
+    }
+}
@@ -0,0 +1,55 @@
+use super::{multi::DeadLetters, LogSink, SinkError};
+use crate::models::LogEntry;
+use crate::pkg::db::postgres;
+use futures::future::BoxFuture;
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+
+/// Inserts batches into the `logs` table, one transaction per batch.
+pub struct PostgresSink {
+    pool: Arc<Pool<Postgres>>,
+    /// See `Config::normalize_devices`.
+    normalize_devices: bool,
+}
+
+impl PostgresSink {
+    pub fn new(pool: Arc<Pool<Postgres>>, normalize_devices: bool) -> Self {
+        Self { pool, normalize_devices }
+    }
+}
+
+impl LogSink for PostgresSink {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn write(&self, batch: Vec<LogEntry>) -> BoxFuture<'_, Result<usize, SinkError>> {
+        Box::pin(async move {
+            postgres::insert_log_entries(&self.pool, batch, self.normalize_devices)
+                .await
+                .map(|inserted| inserted as usize)
+                .map_err(SinkError::Postgres)
+        })
+    }
+}
+
+/// Keeps dead letters in the `sink_dead_letters` table.
+pub struct PostgresDeadLetters {
+    pool: Arc<Pool<Postgres>>,
+}
+
+impl PostgresDeadLetters {
+    pub fn new(pool: Arc<Pool<Postgres>>) -> Self {
+        Self { pool }
+    }
+}
+
+impl DeadLetters for PostgresDeadLetters {
+    fn store<'a>(&'a self, sink: &'a str, error: &'a str, batch: &'a [LogEntry]) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move {
+            postgres::insert_dead_letter(&self.pool, sink, error, batch)
+                .await
+                .map_err(SinkError::Postgres)
+        })
+    }
+}
\ No newline at end of file
@@ -0,0 +1,385 @@
+use super::{LogSink, SinkError};
+use crate::models::LogEntry;
+use crate::pkg::config::S3SinkConfig;
+use crate::pkg::time;
+use aws_sdk_s3::{error::DisplayErrorContext, primitives::ByteStream};
+use flate2::{write::GzEncoder, Compression};
+use futures::future::BoxFuture;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// Wait before the first upload retry; doubled for each one after.
+const RETRY_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Where archived objects go; S3 in production, a stand-in in tests.
+pub trait ObjectStore: Send + Sync {
+    fn put(&self, key: &str, body: Vec<u8>) -> BoxFuture<'_, Result<(), SinkError>>;
+}
+
+/// An S3 bucket, or any S3-compatible store when an endpoint is configured.
+pub struct S3Store {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+}
+
+impl S3Store {
+    pub async fn new(config: &S3SinkConfig) -> Self {
+        let mut loader = aws_config::defaults(aws_config::BehaviorVersion::latest());
+        if let Some(region) = config.region.clone() {
+            loader = loader.region(aws_sdk_s3::config::Region::new(region));
+        }
+        let shared = loader.load().await;
+        let mut builder = aws_sdk_s3::config::Builder::from(&shared);
+        if let Some(endpoint) = config.endpoint.as_deref() {
+            // MinIO and friends don't serve virtual-hosted bucket names.
+            builder = builder.endpoint_url(endpoint).force_path_style(true);
+        }
+        Self {
+            client: aws_sdk_s3::Client::from_conf(builder.build()),
+            bucket: config.bucket.clone(),
+        }
+    }
+}
+
+impl ObjectStore for S3Store {
+    fn put(&self, key: &str, body: Vec<u8>) -> BoxFuture<'_, Result<(), SinkError>> {
+        let request = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type("application/x-ndjson")
+            .content_encoding("gzip")
+            .body(ByteStream::from(body));
+        Box::pin(async move {
+            request
+                .send()
+                .await
+                .map_err(|e| SinkError::Upload(DisplayErrorContext(e).to_string()))?;
+            Ok(())
+        })
+    }
+}
+
+/// The NDJSON of one object waiting to be archived.
+#[derive(Default)]
+struct Object {
+    ndjson: Vec<u8>,
+    entries: usize,
+}
+
+/// Entries waiting to be archived, per service and day.
+#[derive(Default)]
+struct Buffer {
+    objects: HashMap<(String, String), Object>,
+    bytes: usize,
+}
+
+struct Archive {
+    store: Arc<dyn ObjectStore>,
+    buffer: Mutex<Buffer>,
+    flush_bytes: usize,
+    upload_retries: u32,
+    max_buffered_bytes: usize,
+}
+
+/// Archives entries as gzipped NDJSON objects keyed `service/YYYY-MM-DD/uuid.ndjson.gz`,
+/// by each entry's timestamp. Entries are buffered and uploaded once `flush_bytes` of
+/// them are waiting or every `flush_interval_secs`, whichever comes first; a write
+/// counts only the entries it uploaded, so one that just buffers its batch returns 0.
+///
+/// A failed upload is retried with backoff, then its object goes back into the buffer
+/// for the next flush while the buffer stays within `max_buffered_bytes`. Objects
+/// beyond that are dropped, and the write (or interval flush) that dropped them fails.
+pub struct S3Sink {
+    archive: Arc<Archive>,
+}
+
+impl S3Sink {
+    /// Must be called within a Tokio runtime, which runs the interval flushes until the
+    /// sink is dropped.
+    pub fn new(store: Arc<dyn ObjectStore>, config: &S3SinkConfig) -> Self {
+        let archive = Arc::new(Archive {
+            store,
+            buffer: Mutex::default(),
+            flush_bytes: config.flush_bytes,
+            upload_retries: config.upload_retries,
+            max_buffered_bytes: config.max_buffered_bytes,
+        });
+        tokio::spawn(flush_periodically(
+            Arc::downgrade(&archive),
+            Duration::from_secs(config.flush_interval_secs),
+        ));
+        Self { archive }
+    }
+}
+
+async fn flush_periodically(archive: Weak<Archive>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let Some(archive) = archive.upgrade() else {
+            break;
+        };
+        if let Err(e) = archive.flush().await {
+            error!("Interval flush to object storage lost entries: {}", e);
+        }
+    }
+}
+
+impl Archive {
+    /// Buffers `batch`; returns whether the buffer has reached `flush_bytes`.
+    fn buffer(&self, batch: &[LogEntry]) -> Result<bool, SinkError> {
+        let mut buffer = self.buffer.lock();
+        for entry in batch {
+            let key = (entry.service.replace('/', "_"), day_of(entry));
+            let object = buffer.objects.entry(key).or_default();
+            let before = object.ndjson.len();
+            serde_json::to_writer(&mut object.ndjson, entry).map_err(SinkError::Serialize)?;
+            object.ndjson.push(b'\n');
+            object.entries += 1;
+            let added = object.ndjson.len() - before;
+            buffer.bytes += added;
+        }
+        Ok(buffer.bytes >= self.flush_bytes)
+    }
+
+    /// Uploads everything buffered, returning how many entries were archived. Objects
+    /// that fail to upload are kept for the next flush while there is room; the last
+    /// error is returned if any had to be dropped.
+    async fn flush(&self) -> Result<usize, SinkError> {
+        let objects = std::mem::take(&mut *self.buffer.lock()).objects;
+        let mut archived = 0;
+        let mut result = Ok(());
+        for ((service, day), object) in objects {
+            let key = format!("{}/{}/{}.ndjson.gz", service, day, uuid::Uuid::new_v4());
+            let uploaded = match gzip(&object.ndjson) {
+                Ok(body) => self.upload(&key, body).await,
+                Err(e) => Err(SinkError::Upload(format!("gzip failed: {}", e))),
+            };
+            let mut buffer = self.buffer.lock();
+            match uploaded {
+                Ok(()) => {
+                    info!("Archived {} bytes of logs to {}.", object.ndjson.len(), key);
+                    archived += object.entries;
+                }
+                Err(e) if buffer.bytes + object.ndjson.len() <= self.max_buffered_bytes => {
+                    warn!("Keeping archive object {} for the next flush after failed upload: {}", key, e);
+                    buffer.bytes += object.ndjson.len();
+                    // Ahead of anything buffered since, so entries stay in order.
+                    let kept = buffer.objects.entry((service, day)).or_default();
+                    let mut ndjson = object.ndjson;
+                    ndjson.append(&mut kept.ndjson);
+                    kept.ndjson = ndjson;
+                    kept.entries += object.entries;
+                }
+                Err(e) => {
+                    error!("Dropping archive object {} of {} entries after failed upload: {}", key, object.entries, e);
+                    result = Err(e);
+                }
+            }
+        }
+        result.map(|()| archived)
+    }
+
+    async fn upload(&self, key: &str, body: Vec<u8>) -> Result<(), SinkError> {
+        let mut attempt = 0;
+        loop {
+            match self.store.put(key, body.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < self.upload_retries => {
+                    let backoff = RETRY_BACKOFF * 2u32.saturating_pow(attempt);
+                    warn!("Upload of {} failed ({}); retrying in {:?}.", key, e, backoff);
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl LogSink for S3Sink {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    fn write(&self, batch: Vec<LogEntry>) -> BoxFuture<'_, Result<usize, SinkError>> {
+        Box::pin(async move {
+            if self.archive.buffer(&batch)? {
+                return self.archive.flush().await;
+            }
+            Ok(0)
+        })
+    }
+
+    /// Fails if anything is still buffered afterwards, since there is no later flush.
+    fn flush(&self) -> BoxFuture<'_, Result<(), SinkError>> {
+        Box::pin(async move {
+            self.archive.flush().await?;
+            match self.archive.buffer.lock().bytes {
+                0 => Ok(()),
+                left => Err(SinkError::Upload(format!("{} bytes of logs are still buffered", left))),
+            }
+        })
+    }
+}
+
+/// `YYYY-MM-DD` of the entry's timestamp, or of today when it can't be parsed.
+fn day_of(entry: &LogEntry) -> String {
+    time::parse_flexible(&entry.timestamp, time::DEFAULT_FORMATS)
+        .map(|(instant, _)| instant)
+        .unwrap_or_else(chrono::Utc::now)
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkg::db::postgres::tests::sample_entry;
+    use std::io::Read;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Records uploads, failing the first `failures` attempts.
+    #[derive(Default)]
+    struct MockStore {
+        objects: Mutex<Vec<(String, Vec<u8>)>>,
+        failures: AtomicUsize,
+    }
+
+    impl ObjectStore for MockStore {
+        fn put(&self, key: &str, body: Vec<u8>) -> BoxFuture<'_, Result<(), SinkError>> {
+            let key = key.to_string();
+            Box::pin(async move {
+                let failing = self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+                if failing.is_ok() {
+                    return Err(SinkError::Upload("503 Slow Down".to_string()));
+                }
+                self.objects.lock().push((key, body));
+                Ok(())
+            })
+        }
+    }
+
+    fn entry(id: &str) -> LogEntry {
+        sample_entry("web", id, "2024-05-01T10:00:00.000000Z")
+    }
+
+    fn config(flush_bytes: usize, flush_interval_secs: u64) -> S3SinkConfig {
+        S3SinkConfig {
+            bucket: "archive".to_string(),
+            flush_bytes,
+            flush_interval_secs,
+            upload_retries: 1,
+            ..S3SinkConfig::default()
+        }
+    }
+
+    fn gunzip_lines(body: &[u8]) -> Vec<serde_json::Value> {
+        let mut text = String::new();
+        flate2::read::GzDecoder::new(body).read_to_string(&mut text).unwrap();
+        text.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_full_buffer_uploads_gzipped_ndjson_per_service_and_day() {
+        let store = Arc::new(MockStore::default());
+        let sink = S3Sink::new(store.clone(), &config(1, 3600));
+        // The first attempt fails and the retry succeeds.
+        store.failures.store(1, Ordering::SeqCst);
+        let batch = vec![
+            sample_entry("web", "e-1", "2024-05-01T10:00:00.000000Z"),
+            sample_entry("web", "e-2", "2024-05-01T11:00:00.000000Z"),
+            sample_entry("api", "e-3", "2024-05-02T00:00:00.000000Z"),
+        ];
+        assert_eq!(sink.write(batch).await.unwrap(), 3);
+
+        let mut objects = store.objects.lock().clone();
+        objects.sort();
+        assert_eq!(objects.len(), 2);
+        let key_shape = regex::Regex::new(r"^(web/2024-05-01|api/2024-05-02)/[0-9a-f-]{36}\.ndjson\.gz$").unwrap();
+        for (key, _) in &objects {
+            assert!(key_shape.is_match(key), "{}", key);
+        }
+        assert!(objects[0].0.starts_with("api/2024-05-02/"));
+        let web = gunzip_lines(&objects[1].1);
+        assert_eq!(web.iter().map(|e| e["id"].as_str().unwrap()).collect::<Vec<_>>(), ["e-1", "e-2"]);
+        assert_eq!(web[0]["service"], "web");
+    }
+
+    #[tokio::test]
+    async fn test_buffered_entries_flush_on_interval_and_on_demand() {
+        let store = Arc::new(MockStore::default());
+        let sink = S3Sink::new(store.clone(), &config(usize::MAX, 1));
+        assert_eq!(sink.write(vec![entry("e-1")]).await.unwrap(), 0, "only buffered");
+        assert!(store.objects.lock().is_empty(), "below the size threshold");
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while store.objects.lock().is_empty() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("the interval flush never ran");
+
+        assert_eq!(sink.write(vec![entry("e-2")]).await.unwrap(), 0);
+        sink.flush().await.unwrap();
+        let objects = store.objects.lock().clone();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(gunzip_lines(&objects[1].1)[0]["id"], "e-2");
+    }
+
+    #[tokio::test]
+    async fn test_failed_uploads_are_kept_for_the_next_flush() {
+        let store = Arc::new(MockStore::default());
+        let sink = S3Sink::new(store.clone(), &config(1, 3600));
+        store.failures.store(2, Ordering::SeqCst);
+        assert_eq!(sink.write(vec![entry("e-1")]).await.unwrap(), 0);
+        assert!(store.objects.lock().is_empty());
+
+        assert_eq!(sink.write(vec![entry("e-2")]).await.unwrap(), 2);
+        let objects = store.objects.lock().clone();
+        assert_eq!(objects.len(), 1);
+        let ids: Vec<_> = gunzip_lines(&objects[0].1).iter().map(|e| e["id"].clone()).collect();
+        assert_eq!(ids, ["e-1", "e-2"]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_uploads_beyond_the_buffer_limit_are_dropped() {
+        let store = Arc::new(MockStore::default());
+        let sink = S3Sink::new(
+            store.clone(),
+            &S3SinkConfig {
+                max_buffered_bytes: 1,
+                ..config(1, 3600)
+            },
+        );
+        store.failures.store(2, Ordering::SeqCst);
+        let result = sink.write(vec![entry("e-1")]).await;
+        assert!(matches!(result, Err(SinkError::Upload(_))), "{:?}", result);
+        sink.flush().await.unwrap();
+        assert!(store.objects.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_final_flush_fails_while_entries_are_left_buffered() {
+        let store = Arc::new(MockStore::default());
+        let sink = S3Sink::new(store.clone(), &config(usize::MAX, 3600));
+        sink.write(vec![entry("e-1")]).await.unwrap();
+        store.failures.store(2, Ordering::SeqCst);
+        assert!(matches!(sink.flush().await, Err(SinkError::Upload(_))));
+        sink.flush().await.unwrap();
+        assert_eq!(store.objects.lock().len(), 1);
+    }
+}
@@ -0,0 +1,223 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+use crate::pkg::time;
+
+#[derive(Default)]
+struct Counters {
+    running: AtomicBool,
+    batches_processed: AtomicU64,
+    batches_failed: AtomicU64,
+    entries_persisted: AtomicU64,
+    last_batch_at: Mutex<Option<DateTime<Utc>>>,
+}
+
+/// What the background processor reports to `/admin/status`. Cheap to clone; clones
+/// share one set of counters.
+#[derive(Clone, Default)]
+pub struct ProcessorStats {
+    counters: Arc<Counters>,
+}
+
+/// Marks the processor as running until dropped, including by a panic unwinding the task.
+pub struct RunningGuard(ProcessorStats);
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.0.counters.running.store(false, Ordering::Relaxed);
+    }
+}
+
+impl ProcessorStats {
+    pub fn start(&self) -> RunningGuard {
+        self.counters.running.store(true, Ordering::Relaxed);
+        RunningGuard(self.clone())
+    }
+
+    /// Counts a batch of `entries` that was persisted, or failed to be.
+    pub fn record(&self, entries: usize, persisted: bool) {
+        let counters = &self.counters;
+        if persisted {
+            counters.batches_processed.fetch_add(1, Ordering::Relaxed);
+            counters.entries_persisted.fetch_add(entries as u64, Ordering::Relaxed);
+        } else {
+            counters.batches_failed.fetch_add(1, Ordering::Relaxed);
+        }
+        *counters.last_batch_at.lock().unwrap() = Some(Utc::now());
+    }
+
+    pub fn snapshot(&self) -> ProcessorStatus {
+        let counters = &self.counters;
+        ProcessorStatus {
+            running: counters.running.load(Ordering::Relaxed),
+            batches_processed: counters.batches_processed.load(Ordering::Relaxed),
+            batches_failed: counters.batches_failed.load(Ordering::Relaxed),
+            entries_persisted: counters.entries_persisted.load(Ordering::Relaxed),
+            last_batch_at: counters.last_batch_at.lock().unwrap().map(time::to_storage_string),
+        }
+    }
+}
+
+#[derive(Default)]
+struct StartupFlags {
+    database_connected: AtomicBool,
+    schema_initialized: AtomicBool,
+}
+
+/// Startup progress behind `/readyz`, set by `main` as each step completes. Cheap to
+/// clone; clones share the flags.
+#[derive(Clone, Default)]
+pub struct Readiness {
+    flags: Arc<StartupFlags>,
+}
+
+impl Readiness {
+    pub fn mark_database_connected(&self) {
+        self.flags.database_connected.store(true, Ordering::Relaxed);
+    }
+
+    pub fn mark_schema_initialized(&self) {
+        self.flags.schema_initialized.store(true, Ordering::Relaxed);
+    }
+
+    /// Ready once the pool connected and the schema is migrated, for as long as the
+    /// background processor runs.
+    pub fn report(&self, processor: &ProcessorStats) -> ReadinessReport {
+        let database_connected = self.flags.database_connected.load(Ordering::Relaxed);
+        let schema_initialized = self.flags.schema_initialized.load(Ordering::Relaxed);
+        let processor_running = processor.counters.running.load(Ordering::Relaxed);
+        ReadinessReport {
+            ready: database_connected && schema_initialized && processor_running,
+            database_connected,
+            schema_initialized,
+            processor_running,
+        }
+    }
+}
+
+/// Body of `GET /readyz`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadinessReport {
+    pub ready: bool,
+    pub database_connected: bool,
+    pub schema_initialized: bool,
+    pub processor_running: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessorStatus {
+    pub running: bool,
+    pub batches_processed: u64,
+    pub batches_failed: u64,
+    pub entries_persisted: u64,
+    pub last_batch_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueStatus {
+    pub depth: usize,
+    pub capacity: usize,
+}
+
+impl QueueStatus {
+    pub fn of<T>(queue: &mpsc::Sender<T>) -> Self {
+        let capacity = queue.max_capacity();
+        Self {
+            depth: capacity - queue.capacity(),
+            capacity,
+        }
+    }
+}
+
+/// Body of `GET /health`.
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    /// `healthy`; `degraded` while the live queue is full; `unhealthy` (served with a
+    /// 503) when the database doesn't answer.
+    pub status: &'static str,
+    pub database: DatabaseHealth,
+    pub queue: QueueHealth,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseHealth {
+    pub reachable: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The live ingest queue.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueHealth {
+    #[serde(flatten)]
+    pub queue: QueueStatus,
+    pub has_capacity: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueuesStatus {
+    pub priority: QueueStatus,
+    pub live: QueueStatus,
+    pub bulk: QueueStatus,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseStatus {
+    pub connections: u32,
+    pub idle: usize,
+    pub max_connections: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RateLimiterStatus {
+    pub buckets: usize,
+}
+
+/// The `/admin/status` snapshot.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusReport {
+    pub queues: QueuesStatus,
+    pub processor: ProcessorStatus,
+    pub database: DatabaseStatus,
+    pub rate_limiter: RateLimiterStatus,
+    pub maintenance: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_processor_stats_count_batches_and_track_the_task() {
+        let stats = ProcessorStats::default();
+        let running = stats.start();
+        stats.record(3, true);
+        stats.record(2, false);
+
+        let snapshot = stats.snapshot();
+        assert!(snapshot.running);
+        assert_eq!((snapshot.batches_processed, snapshot.batches_failed, snapshot.entries_persisted), (1, 1, 3));
+        assert!(snapshot.last_batch_at.is_some());
+
+        drop(running);
+        assert!(!stats.snapshot().running);
+    }
+
+    #[test]
+    fn test_queue_status_reports_depth() {
+        let (tx, _rx) = mpsc::channel::<()>(4);
+        tx.try_send(()).unwrap();
+        let status = QueueStatus::of(&tx);
+        assert_eq!((status.depth, status.capacity), (1, 4));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::models::LogEntry;
+use actix_web::web::Bytes;
+use sqlx::{postgres::PgListener, Pool, Postgres};
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{error, info, warn};
+
+use crate::pkg::db::postgres;
+use crate::pkg::time;
+
+/// A persisted log entry, serialized once and shared with every live-tail subscriber.
+#[derive(Debug)]
+pub struct TailEvent {
+    pub id: Option<String>,
+    pub json: String,
+}
+
+pub type TailSender = broadcast::Sender<Arc<TailEvent>>;
+
+impl TailEvent {
+    pub fn from_entry(entry: &LogEntry) -> Option<Self> {
+        let json = serde_json::to_string(entry).ok()?;
+        Some(Self {
+            id: entry.id.clone(),
+            json,
+        })
+    }
+
+    /// Formats the event as a Server-Sent Events frame.
+    pub fn to_sse_frame(&self) -> Bytes {
+        Bytes::from(format!("data: {}\n\n", self.json))
+    }
+}
+
+/// Writes `backfill` to `out`, then follows `live` until the client goes away.
+///
+/// `live` must be subscribed *before* the backfill query runs, so nothing persisted
+/// in between is missed. Entries that show up in both are only sent once.
+pub async fn backfill_then_follow(
+    backfill: Vec<TailEvent>,
+    mut live: broadcast::Receiver<Arc<TailEvent>>,
+    out: mpsc::Sender<Bytes>,
+) {
+    let mut backfilled: HashSet<String> = backfill.iter().filter_map(|e| e.id.clone()).collect();
+
+    for event in backfill {
+        if out.send(event.to_sse_frame()).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        match live.recv().await {
+            Ok(event) => {
+                // Each id is broadcast at most once, so a hit can be forgotten.
+                if let Some(id) = &event.id {
+                    if backfilled.remove(id) {
+                        continue;
+                    }
+                }
+                if out.send(event.to_sse_frame()).await.is_err() {
+                    return;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!("Live tail subscriber lagged behind; {} entries skipped.", skipped);
+                let notice = Bytes::from(format!(": lagged, {} entries skipped\n\n", skipped));
+                if out.send(notice).await.is_err() {
+                    return;
+                }
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Forwards logs persisted by any instance to this instance's tail subscribers, by
+/// listening for the ids other instances `NOTIFY` (see `postgres::notify_persisted`).
+pub struct TailListener {
+    listener: PgListener,
+    pool: Arc<Pool<Postgres>>,
+}
+
+impl TailListener {
+    /// Connects and starts listening on `channel`; nothing sent before this returns is seen.
+    pub async fn connect(pool: Arc<Pool<Postgres>>, channel: &str) -> Result<Self, sqlx::Error> {
+        let mut listener = PgListener::connect_with(&pool).await?;
+        listener.listen(channel).await?;
+        info!("Live tail is listening for persisted logs on '{}'.", channel);
+        Ok(Self { listener, pool })
+    }
+
+    /// Broadcasts every announced log on `tail_tx` until the pool is closed. Announcements
+    /// made while the connection is down are lost, as with any `NOTIFY`.
+    pub async fn run(mut self, tail_tx: TailSender) {
+        loop {
+            let notification = match self.listener.recv().await {
+                Ok(notification) => notification,
+                Err(sqlx::Error::PoolClosed) => return,
+                Err(e) => {
+                    error!("Live tail listener failed: {:?}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            if tail_tx.receiver_count() == 0 {
+                continue;
+            }
+            let ids: Vec<String> = match serde_json::from_str(notification.payload()) {
+                Ok(ids) => ids,
+                Err(e) => {
+                    warn!("Ignoring malformed tail notification: {}", e);
+                    continue;
+                }
+            };
+            match postgres::get_logs_by_ids(&self.pool, &ids).await {
+                Ok(entries) => {
+                    for event in entries.iter().filter_map(TailEvent::from_entry) {
+                        let _ = tail_tx.send(Arc::new(event));
+                    }
+                }
+                Err(e) => error!("Failed to load {} announced logs for the live tail: {:?}", ids.len(), e),
+            }
+        }
+    }
+}
+
+/// A position in the `(timestamp, id)` order of stored logs, handed to `/logs/poll`
+/// clients as `<timestamp>|<id>`.
+///
+/// Entries stored later but timestamped before the cursor are not picked up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    /// In the normalized storage form.
+    pub timestamp: String,
+    pub id: String,
+}
+
+impl Cursor {
+    /// Parses `<timestamp>|<id>`, or a bare RFC 3339 timestamp for "everything from then on".
+    pub fn parse(raw: &str) -> Option<Self> {
+        let (timestamp, id) = raw.split_once('|').unwrap_or((raw, ""));
+        let (instant, _) = time::parse_rfc3339_utc(timestamp)?;
+        Some(Self {
+            timestamp: time::to_storage_string(instant),
+            id: id.to_string(),
+        })
+    }
+
+    pub fn now() -> Self {
+        Self {
+            timestamp: time::to_storage_string(chrono::Utc::now()),
+            id: String::new(),
+        }
+    }
+
+    /// The cursor just past `entry`.
+    pub fn after(entry: &LogEntry) -> Self {
+        Self {
+            timestamp: entry.timestamp.clone(),
+            id: entry.id.clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}|{}", self.timestamp, self.id)
+    }
+}
+
+/// Waits up to `nap` for anything to be persisted on this instance.
+pub async fn wait_for_activity(live: &mut broadcast::Receiver<Arc<TailEvent>>, nap: Duration) {
+    if let Ok(Err(broadcast::error::RecvError::Closed)) = tokio::time::timeout(nap, live.recv()).await {
+        // Nothing will be broadcast any more; keep the caller from spinning.
+        tokio::time::sleep(nap).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: &str) -> TailEvent {
+        TailEvent {
+            id: Some(id.to_string()),
+            json: format!("{{\"id\":\"{}\"}}", id),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backfill_precedes_live_without_duplicates() {
+        let (tail_tx, _) = broadcast::channel(16);
+        let live = tail_tx.subscribe();
+
+        // "c" was persisted while the backfill query ran, so it is in both.
+        tail_tx.send(Arc::new(event("c"))).unwrap();
+        tail_tx.send(Arc::new(event("d"))).unwrap();
+        drop(tail_tx);
+
+        let (out_tx, mut out_rx) = mpsc::channel(16);
+        backfill_then_follow(vec![event("a"), event("b"), event("c")], live, out_tx).await;
+
+        let mut frames = Vec::new();
+        while let Some(frame) = out_rx.recv().await {
+            frames.push(String::from_utf8(frame.to_vec()).unwrap());
+        }
+        let expected: Vec<String> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|id| format!("data: {{\"id\":\"{}\"}}\n\n", id))
+            .collect();
+        assert_eq!(frames, expected);
+    }
+
+    #[test]
+    fn test_cursor_round_trips_and_accepts_bare_timestamps() {
+        let cursor = Cursor::parse("2024-01-01T00:00:05Z|a|b").unwrap();
+        assert_eq!(cursor.timestamp, "2024-01-01T00:00:05.000000Z");
+        assert_eq!(cursor.id, "a|b");
+        assert_eq!(Cursor::parse(&cursor.to_string()), Some(cursor));
+
+        let bare = Cursor::parse("2024-01-01T01:00:05+01:00").unwrap();
+        assert_eq!(bare.timestamp, "2024-01-01T00:00:05.000000Z");
+        assert_eq!(bare.id, "");
+        assert_eq!(Cursor::parse("yesterday"), None);
+    }
+}
@@ -0,0 +1,150 @@
+use chrono::{DateTime, FixedOffset, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use std::str::FromStr;
+
+/// Timestamp formats understood by [`parse_flexible`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// `2024-03-01T10:00:00+02:00`
+    Rfc3339,
+    /// Integer epoch; 12 or more digits are read as milliseconds, fewer as seconds.
+    Epoch,
+    EpochMillis,
+    EpochSeconds,
+    /// `2024-03-01T10:00:00` (optionally with fractional seconds or a space separator),
+    /// interpreted as UTC.
+    NaiveIso,
+}
+
+impl FromStr for TimestampFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "rfc3339" => Ok(Self::Rfc3339),
+            "epoch" => Ok(Self::Epoch),
+            "epoch_millis" => Ok(Self::EpochMillis),
+            "epoch_seconds" => Ok(Self::EpochSeconds),
+            "naive_iso" => Ok(Self::NaiveIso),
+            other => Err(format!("unknown timestamp format '{}'", other)),
+        }
+    }
+}
+
+/// The order formats are tried in when none is configured.
+pub const DEFAULT_FORMATS: &[TimestampFormat] = &[
+    TimestampFormat::Rfc3339,
+    TimestampFormat::Epoch,
+    TimestampFormat::NaiveIso,
+];
+
+/// Tries each of `formats` in order, returning the first successful parse as a UTC
+/// instant plus the offset the value was expressed in (UTC for offset-less formats).
+pub fn parse_flexible(value: &str, formats: &[TimestampFormat]) -> Option<(DateTime<Utc>, FixedOffset)> {
+    let value = value.trim();
+    formats.iter().find_map(|format| match format {
+        TimestampFormat::Rfc3339 => parse_rfc3339_utc(value),
+        TimestampFormat::Epoch => {
+            let digits = value.strip_prefix('-').unwrap_or(value);
+            if digits.len() >= 12 {
+                parse_epoch(value, 1)
+            } else {
+                parse_epoch(value, 1000)
+            }
+        }
+        TimestampFormat::EpochMillis => parse_epoch(value, 1),
+        TimestampFormat::EpochSeconds => parse_epoch(value, 1000),
+        TimestampFormat::NaiveIso => ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+            .iter()
+            .find_map(|pattern| NaiveDateTime::parse_from_str(value, pattern).ok())
+            .map(|naive| (Utc.from_utc_datetime(&naive), utc_offset())),
+    })
+}
+
+/// Parses an all-digit epoch value; `millis_per_unit` is 1 for millis, 1000 for seconds.
+fn parse_epoch(value: &str, millis_per_unit: i64) -> Option<(DateTime<Utc>, FixedOffset)> {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let millis = value.parse::<i64>().ok()?.checked_mul(millis_per_unit)?;
+    let instant = DateTime::from_timestamp_millis(millis)?;
+    Some((instant, utc_offset()))
+}
+
+fn utc_offset() -> FixedOffset {
+    FixedOffset::east_opt(0).expect("zero offset is valid")
+}
+
+/// Parses an RFC3339 timestamp, returning the instant in UTC along with the
+/// offset it was originally expressed in.
+pub fn parse_rfc3339_utc(value: &str) -> Option<(DateTime<Utc>, FixedOffset)> {
+    let parsed = DateTime::parse_from_rfc3339(value.trim()).ok()?;
+    Some((parsed.with_timezone(&Utc), *parsed.offset()))
+}
+
+/// Formats a UTC instant in the canonical stored form (fixed width, `Z` suffix),
+/// so that lexical and chronological ordering agree.
+pub fn to_storage_string(instant: DateTime<Utc>) -> String {
+    instant.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offsets_normalize_to_utc() {
+        let (berlin, offset) = parse_rfc3339_utc("2024-03-01T10:00:00+02:00").unwrap();
+        assert_eq!(offset.local_minus_utc(), 2 * 3600);
+        assert_eq!(to_storage_string(berlin), "2024-03-01T08:00:00.000000Z");
+
+        let (new_york, _) = parse_rfc3339_utc("2024-03-01T03:30:00-05:00").unwrap();
+        let (utc, _) = parse_rfc3339_utc("2024-03-01T08:15:00Z").unwrap();
+
+        // 08:00Z < 08:15Z < 08:30Z, even though the local wall-clock times say otherwise.
+        let mut stored = vec![
+            to_storage_string(new_york),
+            to_storage_string(berlin),
+            to_storage_string(utc),
+        ];
+        stored.sort();
+        assert_eq!(
+            stored,
+            vec![
+                "2024-03-01T08:00:00.000000Z",
+                "2024-03-01T08:15:00.000000Z",
+                "2024-03-01T08:30:00.000000Z",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_timestamp_is_rejected() {
+        assert!(parse_rfc3339_utc("yesterday").is_none());
+        assert!(parse_flexible("yesterday", DEFAULT_FORMATS).is_none());
+        assert!(parse_flexible("", DEFAULT_FORMATS).is_none());
+    }
+
+    #[test]
+    fn test_parse_flexible_formats_agree() {
+        let expected = parse_rfc3339_utc("2024-03-01T08:00:00Z").unwrap().0;
+        for value in [
+            "2024-03-01T10:00:00+02:00",
+            "1709280000000",
+            "1709280000",
+            "2024-03-01T08:00:00",
+            "2024-03-01 08:00:00.000",
+        ] {
+            let (instant, _) = parse_flexible(value, DEFAULT_FORMATS)
+                .unwrap_or_else(|| panic!("failed to parse {}", value));
+            assert_eq!(instant, expected, "for {}", value);
+        }
+    }
+
+    #[test]
+    fn test_parse_flexible_respects_configured_formats() {
+        assert!(parse_flexible("1709280000", &[TimestampFormat::Rfc3339]).is_none());
+        let (as_millis, _) = parse_flexible("1709280000", &[TimestampFormat::EpochMillis]).unwrap();
+        assert_eq!(as_millis.timestamp(), 1_709_280);
+    }
+}
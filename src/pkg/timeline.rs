@@ -0,0 +1,98 @@
+use crate::models::{LogEntry, LogLevel};
+use crate::pkg::time::{self, DEFAULT_FORMATS};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// One point on an entry's timeline: a breadcrumb, or the logged event itself.
+#[derive(Debug, Serialize)]
+pub struct TimelineEvent<'a> {
+    /// Normalized to the stored form when parseable, otherwise as the SDK sent it.
+    pub timestamp: String,
+    /// `"log"` for the entry itself, otherwise the breadcrumb type.
+    pub kind: &'static str,
+    pub message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<&'a LogLevel>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<&'a serde_json::Value>,
+}
+
+/// The entry's breadcrumbs merged with the entry itself, in chronological order.
+///
+/// SDKs don't guarantee breadcrumb order, so everything is sorted by parsed timestamp.
+/// Events whose timestamp can't be parsed keep their relative order at the end.
+pub fn build(entry: &LogEntry) -> Vec<TimelineEvent<'_>> {
+    let breadcrumbs = entry.breadcrumbs.iter().flatten().map(|breadcrumb| {
+        (
+            breadcrumb.timestamp.as_str(),
+            TimelineEvent {
+                timestamp: String::new(),
+                kind: breadcrumb.breadcrumb_type.as_str(),
+                message: &breadcrumb.message,
+                level: None,
+                data: breadcrumb.data.as_ref(),
+            },
+        )
+    });
+    let event = (
+        entry.timestamp.as_str(),
+        TimelineEvent {
+            timestamp: String::new(),
+            kind: "log",
+            message: &entry.message,
+            level: Some(&entry.level),
+            data: None,
+        },
+    );
+
+    let mut timeline: Vec<(Option<DateTime<Utc>>, TimelineEvent)> = breadcrumbs
+        .chain(std::iter::once(event))
+        .map(|(raw, mut event)| {
+            let instant = time::parse_flexible(raw, DEFAULT_FORMATS).map(|(instant, _)| instant);
+            event.timestamp = instant.map_or_else(|| raw.to_string(), time::to_storage_string);
+            (instant, event)
+        })
+        .collect();
+    // Stable, so ties keep breadcrumbs ahead of the event they led up to.
+    timeline.sort_by_key(|(instant, _)| (instant.is_none(), *instant));
+    timeline.into_iter().map(|(_, event)| event).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_out_of_order_breadcrumbs_are_sorted() {
+        let entry: LogEntry = serde_json::from_value(json!({
+            "level": "error",
+            "message": "Checkout failed",
+            "timestamp": "2024-03-01T10:00:05Z",
+            "service": "web",
+            "breadcrumbs": [
+                {"timestamp": "2024-03-01T10:00:03Z", "type": "xhr", "message": "POST /api/pay"},
+                {"timestamp": "1709287200000", "type": "navigation", "message": "/cart"},
+                {"timestamp": "sometime", "type": "console", "message": "???"},
+                {"timestamp": "2024-03-01T12:00:01+02:00", "type": "click", "message": "Pay now"}
+            ]
+        }))
+        .unwrap();
+
+        let timeline = build(&entry);
+        let order: Vec<(&str, &str)> = timeline.iter().map(|e| (e.kind, e.message)).collect();
+        assert_eq!(
+            order,
+            vec![
+                ("navigation", "/cart"),
+                ("click", "Pay now"),
+                ("xhr", "POST /api/pay"),
+                ("log", "Checkout failed"),
+                ("console", "???"),
+            ]
+        );
+        assert_eq!(timeline[0].timestamp, "2024-03-01T10:00:00.000000Z");
+        assert_eq!(timeline[1].timestamp, "2024-03-01T10:00:01.000000Z");
+        assert_eq!(timeline[4].timestamp, "sometime");
+    }
+}
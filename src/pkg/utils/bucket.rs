@@ -3,7 +3,8 @@ use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub struct TokenBucket {
-    tokens: i64,
+    /// Fractional, so refills too frequent to add a whole token still count.
+    tokens: f64,
     capacity: i64,
     fill_rate: f64,
     last_refill: Instant,
@@ -13,7 +14,7 @@ impl TokenBucket {
     /// Create a new TokenBucket with a specified fill interval and capacity.
     pub fn new(fill_interval: Duration, capacity: i64) -> Arc<Mutex<Self>> {
         Arc::new(Mutex::new(Self {
-            tokens: capacity,
+            tokens: capacity as f64,
             capacity,
             fill_rate: capacity as f64 / fill_interval.as_secs_f64(),
             last_refill: Instant::now(),
@@ -23,62 +24,117 @@ impl TokenBucket {
     /// Attempt to take `count` tokens from the bucket.
     /// Returns true if successful, false otherwise.
     pub fn take_available(&mut self, count: i64) -> bool {
-        self.refill();
-        if self.tokens >= count {
-            self.tokens -= count;
+        self.take_available_at(count, Instant::now())
+    }
+
+    fn take_available_at(&mut self, count: i64, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= count as f64 {
+            self.tokens -= count as f64;
             true
         } else {
             false
         }
     }
 
-    /// Calculate the time duration required to get at least one token.
-    pub fn retry_after(&mut self) -> Duration {
-        self.refill();
-        if self.tokens >= 1 {
+    /// Calculate the time duration required to get at least `count` tokens.
+    pub fn retry_after(&mut self, count: i64) -> Duration {
+        self.retry_after_at(count, Instant::now())
+    }
+
+    fn retry_after_at(&mut self, count: i64, now: Instant) -> Duration {
+        self.refill(now);
+        let missing = count as f64 - self.tokens;
+        if missing <= 0.0 {
             Duration::ZERO
         } else {
-            let fill_time = (self.capacity as f64 / self.fill_rate) as u64;
-            Duration::from_secs(fill_time) - self.last_refill.elapsed()
+            Duration::from_secs_f64(missing / self.fill_rate)
         }
     }
 
-    /// Refill tokens based on elapsed time.
-    fn refill(&mut self) {
-        let now = Instant::now();
-        let elapsed_time = now.duration_since(self.last_refill).as_secs_f64();
+    /// Refill tokens based on the time elapsed until `now`.
+    fn refill(&mut self, now: Instant) {
+        let elapsed_time = now.saturating_duration_since(self.last_refill).as_secs_f64();
         self.last_refill = now;
 
-        self.tokens = std::cmp::min(
-            self.tokens + (elapsed_time * self.fill_rate) as i64,
-            self.capacity,
-        );
+        self.tokens = (self.tokens + elapsed_time * self.fill_rate).min(self.capacity as f64);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::thread;
 
     #[test]
     fn test_token_bucket() {
         let fill_interval = Duration::from_secs(10);
         let capacity = 5;
         let bucket = TokenBucket::new(fill_interval, capacity);
+        let mut tb = bucket.lock().unwrap();
+        let start = tb.last_refill;
 
-        {
-            let mut tb = bucket.lock().unwrap();
-            assert!(tb.take_available(1));
-            assert!(tb.take_available(4));
-            assert!(!tb.take_available(1));
-        }
+        assert!(tb.take_available_at(1, start));
+        assert!(tb.take_available_at(4, start));
+        assert!(!tb.take_available_at(1, start));
+
+        assert!(tb.take_available_at(1, start + fill_interval));
+    }
+
+    #[test]
+    fn test_retry_after_is_one_token_away() {
+        let bucket = TokenBucket::new(Duration::from_secs(10), 5);
+        let mut tb = bucket.lock().unwrap();
+        let start = tb.last_refill;
+        assert!(tb.take_available_at(5, start));
+
+        // One token accrues every 2s, not the 10s the whole bucket takes.
+        assert_eq!(tb.retry_after_at(1, start), Duration::from_secs(2));
+    }
 
-        thread::sleep(Duration::from_secs(10));
+    #[test]
+    fn test_retry_after_waits_for_the_whole_cost() {
+        let bucket = TokenBucket::new(Duration::from_secs(10), 5);
+        let mut tb = bucket.lock().unwrap();
+        let start = tb.last_refill;
+        assert!(tb.take_available_at(4, start));
+
+        // One token is left, so three more are needed at one every 2s.
+        assert_eq!(tb.retry_after_at(4, start), Duration::from_secs(6));
+        assert_eq!(tb.retry_after_at(1, start), Duration::ZERO);
+        assert!(tb.take_available_at(4, start + Duration::from_secs(6)));
+    }
+
+    #[test]
+    fn test_retry_after_when_empty_longer_than_fill_interval() {
+        let fill_interval = Duration::from_secs(10);
+        let start = Instant::now();
+        let mut tb = TokenBucket {
+            tokens: 0.0,
+            capacity: 5,
+            fill_rate: 0.5,
+            last_refill: start,
+        };
+
+        let later = start + fill_interval * 3;
+        assert_eq!(tb.retry_after_at(1, later), Duration::ZERO);
+        assert!(tb.take_available_at(5, later));
+        assert_eq!(tb.retry_after_at(1, later), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_rapid_refills_replenish_the_bucket() {
+        let fill_interval = Duration::from_millis(200);
+        let bucket = TokenBucket::new(fill_interval, 10);
+        let mut tb = bucket.lock().unwrap();
+        let start = tb.last_refill;
+        assert!(tb.take_available_at(10, start));
 
-        {
-            let mut tb = bucket.lock().unwrap();
-            assert!(tb.take_available(1));
+        // A thousand refills a fifth of a millisecond apart, each adding a hundredth of a token.
+        for i in 1..=1000 {
+            tb.refill(start + fill_interval / 1000 * i);
         }
+        assert!((tb.tokens - 10.0).abs() < 1e-6, "{} tokens", tb.tokens);
+        tb.refill(start + fill_interval * 2);
+        assert_eq!(tb.tokens, 10.0, "capped at capacity");
     }
 }
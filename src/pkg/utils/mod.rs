@@ -1 +1,3 @@
 pub mod bucket;
+pub mod cardinality;
+pub mod sliding_window;
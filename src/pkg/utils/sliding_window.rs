@@ -0,0 +1,109 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Allows at most `limit` requests in any `window`-long span. Unlike [`super::bucket::TokenBucket`]
+/// nothing accumulates while idle, so there is no burst allowance beyond `limit`.
+#[derive(Debug)]
+pub struct SlidingWindow {
+    window: Duration,
+    limit: usize,
+    /// Times of the requests still inside the window, oldest first; never longer than `limit`.
+    requests: VecDeque<Instant>,
+}
+
+impl SlidingWindow {
+    pub fn new(window: Duration, limit: usize) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            window,
+            limit,
+            requests: VecDeque::with_capacity(limit),
+        }))
+    }
+
+    /// Records `count` requests if they fit in the window. Returns true if successful.
+    pub fn take_available(&mut self, count: usize) -> bool {
+        self.take_available_at(count, Instant::now())
+    }
+
+    fn take_available_at(&mut self, count: usize, now: Instant) -> bool {
+        self.expire(now);
+        if self.requests.len() + count > self.limit {
+            return false;
+        }
+        self.requests.extend(std::iter::repeat_n(now, count));
+        true
+    }
+
+    /// Time until enough requests have left the window for `count` more to fit.
+    pub fn retry_after(&mut self, count: usize) -> Duration {
+        self.retry_after_at(count, Instant::now())
+    }
+
+    fn retry_after_at(&mut self, count: usize, now: Instant) -> Duration {
+        self.expire(now);
+        let excess = (self.requests.len() + count).saturating_sub(self.limit);
+        if excess == 0 {
+            return Duration::ZERO;
+        }
+        match self.requests.get(excess.min(self.requests.len()).saturating_sub(1)) {
+            Some(leaving) => (*leaving + self.window).saturating_duration_since(now),
+            None => Duration::ZERO,
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while self
+            .requests
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= self.window)
+        {
+            self.requests.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exactly_limit_requests_per_window() {
+        let window = Duration::from_secs(10);
+        let limiter = SlidingWindow::new(window, 3);
+        let mut limiter = limiter.lock().unwrap();
+        let start = Instant::now();
+
+        for offset in [0, 1, 2] {
+            assert!(limiter.take_available_at(1, start + Duration::from_secs(offset)));
+        }
+        assert!(!limiter.take_available_at(1, start + Duration::from_secs(5)));
+
+        // Only the first request has left the window, so exactly one more fits.
+        assert!(limiter.take_available_at(1, start + Duration::from_secs(10)));
+        assert!(!limiter.take_available_at(1, start + Duration::from_secs(10)));
+
+        // After a long idle stretch the allowance is still 3, not a saved-up burst.
+        let later = start + Duration::from_secs(100);
+        assert!(limiter.take_available_at(3, later));
+        assert!(!limiter.take_available_at(1, later));
+    }
+
+    #[test]
+    fn test_retry_after_waits_for_room_for_the_whole_cost() {
+        let window = Duration::from_secs(10);
+        let limiter = SlidingWindow::new(window, 5);
+        let mut limiter = limiter.lock().unwrap();
+        let start = Instant::now();
+
+        for offset in [0, 1, 2] {
+            assert!(limiter.take_available_at(1, start + Duration::from_secs(offset)));
+        }
+        let now = start + Duration::from_secs(3);
+        assert_eq!(limiter.retry_after_at(2, now), Duration::ZERO);
+        // Two slots are free, so a cost of 4 waits for the two oldest requests to leave.
+        assert!(!limiter.take_available_at(4, now));
+        assert_eq!(limiter.retry_after_at(4, now), Duration::from_secs(8));
+        assert!(limiter.take_available_at(4, start + Duration::from_secs(11)));
+    }
+}